@@ -0,0 +1,621 @@
+use std;
+use std::f64::consts::PI;
+use specs;
+use elements::Nodes;
+use elements::ac_source::AcSource;
+use elements::resistor::Resistor;
+use elements::capacitor::Capacitor;
+use elements::inductor::Inductor;
+use elements::diode::Diode;
+use elements::bjt::Bjt;
+use elements::mosfet::Mosfet;
+use elements::voltage_source::VoltageSource;
+use elements::current_source::CurrentSource;
+use elements::vcvs::Vcvs;
+use elements::vccs::Vccs;
+use elements::ccvs::Ccvs;
+use elements::cccs::Cccs;
+use elements::opamp::OpAmp;
+use elements::switch::Switch;
+use elements::opamp::Model;
+use analysis::complex::Complex;
+use analysis::complex::ComplexEquation;
+use analysis::operating_point;
+use analysis::logarithmic;
+use solver;
+use solver::equation;
+use solver::stamp_dynamic;
+
+// Frequencies to analyse, as in a SPICE `.ac` line. All frequencies are in
+// Hz, and must be greater than zero if there are any inductors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sweep {
+    // `points` frequencies, evenly spaced from `start` to `stop` inclusive
+    Linear { points: usize, start: f64, stop: f64 },
+    Decade {
+        points_per_decade: usize,
+        start: f64,
+        stop: f64,
+    },
+    Octave {
+        points_per_octave: usize,
+        start: f64,
+        stop: f64,
+    },
+}
+
+impl Sweep {
+    pub fn frequencies(&self) -> Result<Vec<f64>, Error> {
+        let frequencies = match *self {
+            Sweep::Linear { points: 0, .. } => None,
+            Sweep::Linear { points: 1, start, .. } => Some(vec![start]),
+            Sweep::Linear { points, start, stop } => {
+                let step = (stop - start) / (points - 1) as f64;
+                Some((0..points).map(|i| start + step * i as f64).collect())
+            }
+            Sweep::Decade { points_per_decade, start, stop } => {
+                logarithmic(10.0, points_per_decade, start, stop)
+            }
+            Sweep::Octave { points_per_octave, start, stop } => {
+                logarithmic(2.0, points_per_octave, start, stop)
+            }
+        };
+        frequencies.ok_or(Error::InvalidSweep(*self))
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // A sweep with no points, or a logarithmic sweep which doesn't go up from
+    // a positive start frequency
+    InvalidSweep(Sweep),
+    Unsolvable(equation::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Error::InvalidSweep(sweep) => write!(f, "Cannot sweep frequencies {:?}", sweep),
+            Error::Unsolvable(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::InvalidSweep(_) => None,
+            Error::Unsolvable(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<equation::Error> for Error {
+    fn from(err: equation::Error) -> Error {
+        Error::Unsolvable(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FrequencyResponse {
+    pub frequencies: Vec<f64>,
+    // Node voltages, by frequency then node index
+    pub voltages: Vec<Vec<Complex>>,
+}
+
+impl FrequencyResponse {
+    // The magnitude of a node's voltage at each frequency.
+    pub fn magnitude(&self, node: usize) -> Vec<f64> {
+        self.voltages.iter().map(|vs| vs[node].norm()).collect()
+    }
+
+    // The magnitude of a node's voltage at each frequency, in decibels.
+    pub fn magnitude_db(&self, node: usize) -> Vec<f64> {
+        self.magnitude(node).iter().map(|m| 20.0 * m.log10()).collect()
+    }
+
+    // The phase of a node's voltage at each frequency, in radians.
+    pub fn phase(&self, node: usize) -> Vec<f64> {
+        self.voltages.iter().map(|vs| vs[node].arg()).collect()
+    }
+}
+
+// Small-signal AC analysis over a range of frequencies.
+//
+// Non-linear elements are linearised around the DC operating point. Sources
+// are driven by their `AcSource`, if they have one, and are otherwise zero.
+// Node indexes must already be assigned.
+pub fn ac_sweep(world: &mut specs::World,
+                sweep: &Sweep)
+                -> Result<FrequencyResponse, Error> {
+    use specs::Join;
+    use specs::Gate;
+
+    let frequencies = sweep.frequencies()?;
+
+    // find the bias point of any diodes, transistors and op-amps, and assign
+    // branches
+    let op = operating_point(world)?;
+    let num_branches = solver::assign_branches(world);
+    let num_nodes = solver::count_nodes(world);
+
+    let entities = world.entities();
+    let nodes_ticket = world.read::<Nodes>().pass();
+    let ac_sources = world.read::<AcSource>().pass();
+    let v_sources = world.read::<VoltageSource>().pass();
+    let c_sources = world.read::<CurrentSource>().pass();
+    let resistors = world.read::<Resistor>().pass();
+    let capacitors = world.read::<Capacitor>().pass();
+    let inductors = world.read::<Inductor>().pass();
+    let diodes = world.read::<Diode>().pass();
+    let bjts = world.read::<Bjt>().pass();
+    let mosfets = world.read::<Mosfet>().pass();
+    let vcvs = world.read::<Vcvs>().pass();
+    let vccs = world.read::<Vccs>().pass();
+    let ccvs = world.read::<Ccvs>().pass();
+    let cccs = world.read::<Cccs>().pass();
+    let opamps = world.read::<OpAmp>().pass();
+    let switches = world.read::<Switch>().pass();
+
+    let ac_value = |entity: specs::Entity| match ac_sources.get(entity) {
+        Some(ac) => Complex::from_polar(ac.magnitude, ac.phase),
+        None => Complex::default(),
+    };
+
+    let mut voltages = Vec::with_capacity(frequencies.len());
+    for &frequency in &frequencies {
+        let omega = 2.0 * PI * frequency;
+        let mut equation = ComplexEquation::new(num_nodes, num_branches);
+
+        for (entity, nodes, vi) in (&entities, &nodes_ticket, &v_sources).join() {
+            let &Nodes(ref ns) = nodes;
+            equation.stamp_voltage_source(ac_value(entity),
+                                          ns[vi.node_index_from()].index,
+                                          ns[vi.node_index_to()].index,
+                                          vi.index);
+        }
+        for (entity, nodes, ci) in (&entities, &nodes_ticket, &c_sources).join() {
+            let &Nodes(ref ns) = nodes;
+            equation.stamp_current_source(ac_value(entity),
+                                          ns[ci.node_index_from()].index,
+                                          ns[ci.node_index_to()].index);
+        }
+
+        for (nodes, res) in (&nodes_ticket, &resistors).join() {
+            let &Nodes(ref ns) = nodes;
+            equation.stamp_admittance(Complex::from(res.conductance()),
+                                      ns[res.node_indexes.0].index,
+                                      ns[res.node_indexes.1].index);
+        }
+        for (nodes, switch) in (&nodes_ticket, &switches).join() {
+            let &Nodes(ref ns) = nodes;
+            for (node, resistance) in switch.contacts() {
+                equation.stamp_admittance(Complex::from(1.0 / resistance),
+                                          ns[0].index,
+                                          ns[node].index);
+            }
+        }
+        for (nodes, cap) in (&nodes_ticket, &capacitors).join() {
+            let &Nodes(ref ns) = nodes;
+            equation.stamp_admittance(Complex::new(0.0, omega * cap.capacitance),
+                                      ns[cap.node_indexes.0].index,
+                                      ns[cap.node_indexes.1].index);
+            if cap.uses_branch() {
+                equation.stamp_open_branch(cap.voltage_source.index);
+            }
+        }
+        for (nodes, ind) in (&nodes_ticket, &inductors).join() {
+            let &Nodes(ref ns) = nodes;
+            equation.stamp_admittance(Complex::new(0.0, -1.0 / (omega * ind.inductance)),
+                                      ns[ind.node_indexes.0].index,
+                                      ns[ind.node_indexes.1].index);
+        }
+        for (nodes, diode) in (&nodes_ticket, &diodes).join() {
+            let &Nodes(ref ns) = nodes;
+            let anode = ns[diode.node_indexes.0].index;
+            let cathode = ns[diode.node_indexes.1].index;
+            let junction_voltage = op.voltages[anode] - op.voltages[cathode];
+            let conductance = diode.conductance(junction_voltage) + stamp_dynamic::GMIN;
+            equation.stamp_admittance(Complex::from(conductance), anode, cathode);
+        }
+        for (nodes, bjt) in (&nodes_ticket, &bjts).join() {
+            let &Nodes(ref ns) = nodes;
+            let collector = ns[bjt.node_indexes.0].index;
+            let base = ns[bjt.node_indexes.1].index;
+            let emitter = ns[bjt.node_indexes.2].index;
+
+            let sign = bjt.polarity.sign();
+            let v = &op.voltages;
+            let small_signal = bjt.small_signal(sign * (v[base] - v[emitter]),
+                                                sign * (v[base] - v[collector]));
+            let (gc_be, gc_bc) = small_signal.collector_conductances;
+            let (gb_be, gb_bc) = small_signal.base_conductances;
+            equation.stamp_vccs(gc_be, collector, emitter, emitter, base);
+            equation.stamp_vccs(gc_bc, collector, emitter, collector, base);
+            equation.stamp_vccs(gb_be, base, emitter, emitter, base);
+            equation.stamp_vccs(gb_bc, base, emitter, collector, base);
+
+            let base_emitter = omega * bjt.base_emitter_capacitance.capacitance;
+            let base_collector = omega * bjt.base_collector_capacitance.capacitance;
+            equation.stamp_admittance(Complex::new(stamp_dynamic::GMIN, base_emitter),
+                                      base,
+                                      emitter);
+            equation.stamp_admittance(Complex::new(stamp_dynamic::GMIN, base_collector),
+                                      base,
+                                      collector);
+        }
+        for (nodes, mosfet) in (&nodes_ticket, &mosfets).join() {
+            let &Nodes(ref ns) = nodes;
+            let drain = ns[mosfet.node_indexes.0].index;
+            let gate = ns[mosfet.node_indexes.1].index;
+            let source = ns[mosfet.node_indexes.2].index;
+            let body = ns[mosfet.node_indexes.3].index;
+
+            let sign = mosfet.polarity.sign();
+            let v = &op.voltages;
+            let small_signal = mosfet.small_signal(sign * (v[gate] - v[source]),
+                                                   sign * (v[drain] - v[source]),
+                                                   sign * (v[body] - v[source]));
+            equation.stamp_vccs(small_signal.transconductance, drain, source, source, gate);
+            equation.stamp_vccs(small_signal.output_conductance, drain, source, source, drain);
+            equation.stamp_vccs(small_signal.body_transconductance, drain, source, source, body);
+            equation.stamp_admittance(Complex::new(stamp_dynamic::GMIN, 0.0), drain, source);
+            equation.stamp_admittance(Complex::new(stamp_dynamic::GMIN, 0.0), body, source);
+            equation.stamp_admittance(Complex::new(stamp_dynamic::GMIN, 0.0), body, drain);
+
+            let gate_source = omega * mosfet.gate_source_capacitance.capacitance;
+            let gate_drain = omega * mosfet.gate_drain_capacitance.capacitance;
+            equation.stamp_admittance(Complex::new(0.0, gate_source), gate, source);
+            equation.stamp_admittance(Complex::new(0.0, gate_drain), gate, drain);
+        }
+
+        for (nodes, source) in (&nodes_ticket, &vcvs).join() {
+            let &Nodes(ref ns) = nodes;
+            equation.stamp_vcvs(source.gain,
+                                ns[source.node_indexes.0].index,
+                                ns[source.node_indexes.1].index,
+                                ns[source.control_node_indexes.0].index,
+                                ns[source.control_node_indexes.1].index,
+                                source.index);
+        }
+        for (nodes, source) in (&nodes_ticket, &vccs).join() {
+            let &Nodes(ref ns) = nodes;
+            equation.stamp_vccs(source.transconductance,
+                                ns[source.node_indexes.0].index,
+                                ns[source.node_indexes.1].index,
+                                ns[source.control_node_indexes.0].index,
+                                ns[source.control_node_indexes.1].index);
+        }
+        // the operating point has already failed if any controls are
+        // unresolved
+        for (nodes, source) in (&nodes_ticket, &ccvs).join() {
+            let &Nodes(ref ns) = nodes;
+            if let Some(control) = source.control.and_then(|control| v_sources.get(control)) {
+                equation.stamp_ccvs(source.transresistance,
+                                    ns[source.node_indexes.0].index,
+                                    ns[source.node_indexes.1].index,
+                                    control.index,
+                                    source.index);
+            }
+        }
+        for (nodes, source) in (&nodes_ticket, &cccs).join() {
+            let &Nodes(ref ns) = nodes;
+            if let Some(control) = source.control.and_then(|control| v_sources.get(control)) {
+                equation.stamp_cccs(source.gain,
+                                    ns[source.node_indexes.0].index,
+                                    ns[source.node_indexes.1].index,
+                                    control.index);
+            }
+        }
+
+        for (nodes, opamp) in (&nodes_ticket, &opamps).join() {
+            let &Nodes(ref ns) = nodes;
+            let non_inverting = ns[opamp.input_node_indexes.0].index;
+            let inverting = ns[opamp.input_node_indexes.1].index;
+            let output = ns[opamp.output_node_index].index;
+
+            match opamp.model {
+                Model::Ideal => {
+                    equation.stamp_nullor(inverting, non_inverting, 0, output, opamp.index);
+                }
+                Model::Macro(ref model) => {
+                    // no gain if saturated at the operating point
+                    let input_voltage = op.voltages[non_inverting] - op.voltages[inverting];
+                    let gain = match model.saturation(model.gain * input_voltage) {
+                        Some(_) => Complex::default(),
+                        None => {
+                            Complex::from(model.gain) /
+                            Complex::new(1.0, omega * model.time_constant())
+                        }
+                    };
+
+                    equation.stamp_admittance(Complex::from(1.0 / model.input_resistance),
+                                              non_inverting,
+                                              inverting);
+                    let output_impedance = Complex::from(model.output_resistance);
+                    equation.stamp_voltage_source_with_impedance(Complex::default(),
+                                                                 output_impedance,
+                                                                 0,
+                                                                 output,
+                                                                 opamp.index);
+                    equation.stamp_voltage_gain(gain, inverting, non_inverting, opamp.index);
+                }
+            }
+        }
+
+        let (node_voltages, _) = equation.solve()?;
+        voltages.push(node_voltages);
+    }
+
+    Ok(FrequencyResponse {
+        frequencies: frequencies,
+        voltages: voltages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::Gate;
+    use std::f64::consts::FRAC_PI_4;
+    use analysis::tests::create_world;
+    use netlist;
+
+    #[test]
+    fn linear_frequencies() {
+        let sweep = Sweep::Linear {
+            points: 5,
+            start: 100.0,
+            stop: 500.0,
+        };
+
+        assert_eq!(sweep.frequencies().unwrap(), vec![100.0, 200.0, 300.0, 400.0, 500.0]);
+    }
+
+    #[test]
+    fn decade_frequencies() {
+        let sweep = Sweep::Decade {
+            points_per_decade: 2,
+            start: 10.0,
+            stop: 1000.0,
+        };
+        let frequencies = sweep.frequencies().unwrap();
+        assert_eq!(frequencies.len(), 5);
+        assert_approx_eq!(frequencies[1], 10.0 * 10f64.sqrt());
+        assert_approx_eq!(frequencies[4], 1000.0, 1e-9);
+    }
+
+    #[test]
+    fn octave_frequencies() {
+        let sweep = Sweep::Octave {
+            points_per_octave: 1,
+            start: 100.0,
+            stop: 1000.0,
+        };
+
+        assert_eq!(sweep.frequencies().unwrap(), vec![100.0, 200.0, 400.0, 800.0]);
+    }
+
+    #[test]
+    fn invalid_sweeps() {
+        let sweeps = [Sweep::Linear {
+                          points: 0,
+                          start: 100.0,
+                          stop: 500.0,
+                      },
+                      Sweep::Decade {
+                          points_per_decade: 0,
+                          start: 10.0,
+                          stop: 1000.0,
+                      },
+                      Sweep::Decade {
+                          points_per_decade: 2,
+                          start: 0.0,
+                          stop: 1000.0,
+                      },
+                      Sweep::Octave {
+                          points_per_octave: 1,
+                          start: -100.0,
+                          stop: 1000.0,
+                      }];
+
+        for sweep in &sweeps {
+            match sweep.frequencies() {
+                Err(Error::InvalidSweep(_)) => {}
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    fn low_pass(world: &mut specs::World, reactive: &str) -> netlist::Netlist {
+        let netlist = netlist::parse(&format!("Low pass
+V1 in 0 0
+R1 in out 1k
+{}
+.end",
+                                              reactive),
+                                     world)
+            .unwrap();
+
+        let mut ac_sources = world.write::<AcSource>().pass();
+        ac_sources.insert(netlist.entity("V1").unwrap(), AcSource::new(1.0, 0.0));
+        netlist
+    }
+
+    #[test]
+    fn resistor_capacitor_low_pass() {
+        let mut world = create_world();
+        let netlist = low_pass(&mut world, "C1 out 0 1u");
+        let out = netlist.node_index("out").unwrap();
+
+        // at the corner frequency, 1/(2πRC)
+        let corner = 1.0 / (2.0 * PI * 1e3 * 1e-6);
+        let sweep = Sweep::Linear {
+            points: 1,
+            start: corner,
+            stop: corner,
+        };
+        let response = ac_sweep(&mut world, &sweep).unwrap();
+
+        assert_approx_eq!(response.magnitude(out)[0], 1.0 / 2f64.sqrt());
+        assert_approx_eq!(response.magnitude_db(out)[0], -3.0103, 1e-3);
+        assert_approx_eq!(response.phase(out)[0], -FRAC_PI_4);
+    }
+
+    #[test]
+    fn resistor_inductor_high_pass() {
+        let mut world = create_world();
+        let netlist = low_pass(&mut world, "L1 out 0 1m");
+        let out = netlist.node_index("out").unwrap();
+
+        // at the corner frequency, R/(2πL)
+        let corner = 1e3 / (2.0 * PI * 1e-3);
+        let sweep = Sweep::Decade {
+            points_per_decade: 1,
+            start: corner / 10.0,
+            stop: corner * 10.0,
+        };
+        let response = ac_sweep(&mut world, &sweep).unwrap();
+
+        assert_approx_eq!(response.magnitude(out)[1], 1.0 / 2f64.sqrt());
+        assert_approx_eq!(response.phase(out)[1], FRAC_PI_4);
+
+        // rolls off at 20dB/decade below the corner, and flat above it
+        assert_approx_eq!(response.magnitude_db(out)[0], -20.0, 0.1);
+        assert_approx_eq!(response.magnitude_db(out)[2], 0.0, 0.1);
+    }
+
+    #[test]
+    fn opamp_follower_bandwidth() {
+        use elements::opamp::MacroModel;
+        use elements::opamp;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = low_pass(&mut world, "R2 follower 0 10meg");
+        let model = MacroModel::default();
+        let follower_opamp = opamp::create(&mut world);
+        world.write::<OpAmp>().pass().get_mut(follower_opamp).unwrap().model = Model::Macro(model);
+        connect(&mut world, &netlist, follower_opamp, &["out", "follower", "follower"]);
+        let follower = netlist.node_index("follower").unwrap();
+
+        // unity gain falls to -3dB at the gain-bandwidth product
+        let sweep = Sweep::Decade {
+            points_per_decade: 1,
+            start: model.gain_bandwidth / 100.0,
+            stop: model.gain_bandwidth,
+        };
+        let response = ac_sweep(&mut world, &sweep).unwrap();
+
+        assert_approx_eq!(response.magnitude_db(follower)[0], 0.0, 0.01);
+        assert_approx_eq!(response.magnitude_db(follower)[2], -3.0103, 0.01);
+        assert_approx_eq!(response.phase(follower)[2], -FRAC_PI_4, 0.01);
+    }
+
+    #[test]
+    fn bjt_common_emitter_gain() {
+        use elements::bjt::Polarity;
+        use elements::diode::THERMAL_VOLTAGE;
+        use elements::bjt;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Common emitter
+V1 b 0 0.65
+V2 vcc 0 10
+R1 vcc c 10k
+.end",
+                                     &mut world)
+            .unwrap();
+        let bjt = bjt::create(&mut world, Polarity::Npn);
+        connect(&mut world, &netlist, bjt, &["c", "b", "0"]);
+        {
+            let mut ac_sources = world.write::<AcSource>().pass();
+            ac_sources.insert(netlist.entity("V1").unwrap(), AcSource::new(1.0, 0.0));
+        }
+
+        let op = operating_point(&mut world).unwrap();
+        let transconductance = op.element(bjt).unwrap().current.unwrap() / THERMAL_VOLTAGE;
+
+        let sweep = Sweep::Linear {
+            points: 1,
+            start: 1e3,
+            stop: 1e3,
+        };
+        let response = ac_sweep(&mut world, &sweep).unwrap();
+
+        // inverting, with a gain of gm * R
+        let c = netlist.node_index("c").unwrap();
+        let gain = transconductance * 10e3;
+        assert_approx_eq!(response.magnitude(c)[0], gain, gain * 1e-3);
+        assert_approx_eq!(response.phase(c)[0].abs(), PI, 1e-6);
+    }
+
+    #[test]
+    fn mosfet_common_source_gain() {
+        use elements::mosfet::Mosfet;
+        use elements::mosfet;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Common source
+V1 g 0 3
+V2 vdd 0 10
+R1 vdd d 10k
+.end",
+                                     &mut world)
+            .unwrap();
+        let mut model = Mosfet {
+            threshold_voltage: 1.0,
+            transconductance: 1e-4,
+            width: 10e-6,
+            length: 10e-6,
+            ..Mosfet::default()
+        };
+        model.gate_drain_capacitance.capacitance = 1e-9;
+        let transistor = mosfet::create(&mut world, model.polarity);
+        world.write::<Mosfet>().pass().insert(transistor, model);
+        connect(&mut world, &netlist, transistor, &["d", "g", "0", "0"]);
+        {
+            let mut ac_sources = world.write::<AcSource>().pass();
+            ac_sources.insert(netlist.entity("V1").unwrap(), AcSource::new(1.0, 0.0));
+        }
+
+        let sweep = Sweep::Linear {
+            points: 2,
+            start: 1.0,
+            stop: 1e6,
+        };
+        let response = ac_sweep(&mut world, &sweep).unwrap();
+
+        // inverting, with a gain of gm * R = beta * (Vgs - Vt) * R
+        let d = netlist.node_index("d").unwrap();
+        assert_approx_eq!(response.magnitude(d)[0], 2.0, 1e-6);
+        assert_approx_eq!(response.phase(d)[0].abs(), PI, 1e-3);
+
+        // until the gate-drain capacitance shorts the drain to the gate
+        assert_approx_eq!(response.magnitude(d)[1], 1.0, 1e-2);
+    }
+
+    #[test]
+    fn undriven_sources_are_zero() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Undriven
+V1 in 0 5
+R1 in out 1k
+R2 out 0 1k
+I1 0 out 1m
+.end",
+                                     &mut world)
+            .unwrap();
+
+        let sweep = Sweep::Linear {
+            points: 1,
+            start: 1e3,
+            stop: 1e3,
+        };
+        let response = ac_sweep(&mut world, &sweep).unwrap();
+
+        assert_eq!(response.magnitude(netlist.node_index("out").unwrap())[0], 0.0);
+    }
+}
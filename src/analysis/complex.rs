@@ -0,0 +1,376 @@
+use std::ops::Add;
+use std::ops::AddAssign;
+use std::ops::Div;
+use std::ops::Mul;
+use std::ops::Neg;
+use std::ops::Sub;
+use std::ops::SubAssign;
+use rulinalg;
+use rulinalg::error::ErrorKind;
+use solver::equation::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re: re, im: im }
+    }
+
+    pub fn from_polar(magnitude: f64, phase: f64) -> Self {
+        Complex::new(magnitude * phase.cos(), magnitude * phase.sin())
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    // Phase in radians, between -π and π.
+    pub fn arg(&self) -> f64 {
+        self.im.atan2(self.re)
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(self.re * other.re - self.im * other.im,
+                     self.re * other.im + self.im * other.re)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.re * other.re + other.im * other.im;
+        Complex::new((self.re * other.re + self.im * other.im) / denominator,
+                     (self.im * other.re - self.re * other.im) / denominator)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl AddAssign for Complex {
+    fn add_assign(&mut self, other: Complex) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Complex {
+    fn sub_assign(&mut self, other: Complex) {
+        *self = *self - other;
+    }
+}
+
+// The complex-valued equivalent of `solver::equation::Equation`, for
+// small-signal AC analysis. Uses the same node and branch numbering.
+#[derive(Debug, Clone)]
+pub struct ComplexEquation {
+    // row-major
+    nodal_admittances: Vec<Complex>,
+    inputs: Vec<Complex>,
+
+    size: usize,
+    nodes: usize,
+}
+
+impl ComplexEquation {
+    pub fn new(nodes: usize, voltage_sources: usize) -> Self {
+        let size = nodes + voltage_sources - 1;
+        ComplexEquation {
+            nodal_admittances: vec![Complex::default(); size * size],
+            inputs: vec![Complex::default(); size],
+
+            size: size,
+            nodes: nodes,
+        }
+    }
+
+    fn stamp_nodal_admittance(&mut self, row: usize, col: usize, x: Complex) {
+        if row != 0 && col != 0 {
+            // ignore ground node
+            self.nodal_admittances[(row - 1) * self.size + (col - 1)] += x;
+        }
+    }
+
+    fn stamp_input(&mut self, row: usize, x: Complex) {
+        if row != 0 {
+            self.inputs[row - 1] += x;
+        }
+    }
+
+    pub fn stamp_admittance(&mut self,
+                            admittance: Complex,
+                            node1: usize,
+                            node2: usize)
+                            -> &mut Self {
+        self.stamp_nodal_admittance(node1, node1, admittance);
+        self.stamp_nodal_admittance(node2, node2, admittance);
+        self.stamp_nodal_admittance(node1, node2, -admittance);
+        self.stamp_nodal_admittance(node2, node1, -admittance);
+        self
+    }
+
+    pub fn stamp_voltage_source(&mut self,
+                                voltage: Complex,
+                                from_node: usize,
+                                to_node: usize,
+                                v_num: usize)
+                                -> &mut Self {
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, from_node, Complex::from(-1.0));
+        self.stamp_nodal_admittance(v_index, to_node, Complex::from(1.0));
+        self.stamp_nodal_admittance(from_node, v_index, Complex::from(1.0));
+        self.stamp_nodal_admittance(to_node, v_index, Complex::from(-1.0));
+        self.stamp_input(v_index, voltage);
+        self
+    }
+
+    // A voltage source in series with an impedance.
+    pub fn stamp_voltage_source_with_impedance(&mut self,
+                                               voltage: Complex,
+                                               impedance: Complex,
+                                               from_node: usize,
+                                               to_node: usize,
+                                               v_num: usize)
+                                               -> &mut Self {
+        self.stamp_voltage_source(voltage, from_node, to_node, v_num);
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, v_index, impedance);
+        self
+    }
+
+    pub fn stamp_current_source(&mut self,
+                                current: Complex,
+                                from_node: usize,
+                                to_node: usize)
+                                -> &mut Self {
+        self.stamp_input(from_node, -current);
+        self.stamp_input(to_node, current);
+        self
+    }
+
+    // A branch which carries no current, e.g. a capacitor's companion model
+    // branch, which isn't used in AC analysis.
+    pub fn stamp_open_branch(&mut self, v_num: usize) -> &mut Self {
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, v_index, Complex::from(1.0));
+        self
+    }
+
+    pub fn stamp_vcvs(&mut self,
+                      gain: f64,
+                      from_node: usize,
+                      to_node: usize,
+                      control_from_node: usize,
+                      control_to_node: usize,
+                      v_num: usize)
+                      -> &mut Self {
+        self.stamp_voltage_source(Complex::default(), from_node, to_node, v_num);
+        self.stamp_voltage_gain(Complex::from(gain), control_from_node, control_to_node, v_num);
+        self
+    }
+
+    // Add `gain` times V(control_to) - V(control_from) to the voltage of an
+    // already stamped voltage source.
+    pub fn stamp_voltage_gain(&mut self,
+                              gain: Complex,
+                              control_from_node: usize,
+                              control_to_node: usize,
+                              v_num: usize)
+                              -> &mut Self {
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, control_from_node, gain);
+        self.stamp_nodal_admittance(v_index, control_to_node, -gain);
+        self
+    }
+
+    pub fn stamp_nullor(&mut self,
+                        input_from_node: usize,
+                        input_to_node: usize,
+                        output_from_node: usize,
+                        output_to_node: usize,
+                        v_num: usize)
+                        -> &mut Self {
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, input_from_node, Complex::from(-1.0));
+        self.stamp_nodal_admittance(v_index, input_to_node, Complex::from(1.0));
+        self.stamp_nodal_admittance(output_from_node, v_index, Complex::from(1.0));
+        self.stamp_nodal_admittance(output_to_node, v_index, Complex::from(-1.0));
+        self
+    }
+
+    pub fn stamp_vccs(&mut self,
+                      transconductance: f64,
+                      from_node: usize,
+                      to_node: usize,
+                      control_from_node: usize,
+                      control_to_node: usize)
+                      -> &mut Self {
+        let gm = Complex::from(transconductance);
+        self.stamp_nodal_admittance(from_node, control_from_node, -gm);
+        self.stamp_nodal_admittance(from_node, control_to_node, gm);
+        self.stamp_nodal_admittance(to_node, control_from_node, gm);
+        self.stamp_nodal_admittance(to_node, control_to_node, -gm);
+        self
+    }
+
+    pub fn stamp_ccvs(&mut self,
+                      transresistance: f64,
+                      from_node: usize,
+                      to_node: usize,
+                      control_v_num: usize,
+                      v_num: usize)
+                      -> &mut Self {
+        self.stamp_voltage_source(Complex::default(), from_node, to_node, v_num);
+        let v_index = self.nodes + v_num;
+        let control_index = self.nodes + control_v_num;
+        self.stamp_nodal_admittance(v_index, control_index, Complex::from(-transresistance));
+        self
+    }
+
+    pub fn stamp_cccs(&mut self,
+                      gain: f64,
+                      from_node: usize,
+                      to_node: usize,
+                      control_v_num: usize)
+                      -> &mut Self {
+        let control_index = self.nodes + control_v_num;
+        self.stamp_nodal_admittance(from_node, control_index, Complex::from(gain));
+        self.stamp_nodal_admittance(to_node, control_index, Complex::from(-gain));
+        self
+    }
+
+    // Solve by Gaussian elimination with partial pivoting, returning the
+    // node voltages (including ground) and branch currents.
+    pub fn solve(&self) -> Result<(Vec<Complex>, Vec<Complex>), Error> {
+        let size = self.size;
+        let mut a = self.nodal_admittances.clone();
+        let mut b = self.inputs.clone();
+
+        for k in 0..size {
+            let pivot_row = (k..size)
+                .max_by(|&r1, &r2| {
+                    a[r1 * size + k].norm().partial_cmp(&a[r2 * size + k].norm()).unwrap()
+                })
+                .unwrap();
+            if a[pivot_row * size + k].norm() == 0.0 {
+                let error = rulinalg::error::Error::new(ErrorKind::DecompFailure,
+                                                        "Matrix is singular");
+                return Err(Error::Unsolvable(error));
+            }
+            if pivot_row != k {
+                for col in 0..size {
+                    a.swap(k * size + col, pivot_row * size + col);
+                }
+                b.swap(k, pivot_row);
+            }
+
+            let pivot = a[k * size + k];
+            for row in (k + 1)..size {
+                let factor = a[row * size + k] / pivot;
+                if factor == Complex::default() {
+                    continue;
+                }
+                for col in k..size {
+                    let x = a[k * size + col];
+                    a[row * size + col] -= factor * x;
+                }
+                let x = b[k];
+                b[row] -= factor * x;
+            }
+        }
+
+        let mut x = vec![Complex::default(); size];
+        for row in (0..size).rev() {
+            let mut sum = b[row];
+            for col in (row + 1)..size {
+                sum -= a[row * size + col] * x[col];
+            }
+            x[row] = sum / a[row * size + row];
+        }
+
+        let mut voltages = vec![Complex::default()]; // ground node
+        voltages.extend_from_slice(&x[..(self.nodes - 1)]);
+        let currents = x[(self.nodes - 1)..].to_vec();
+        Ok((voltages, currents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_4;
+
+    #[test]
+    fn arithmetic() {
+        let a = Complex::new(1.0, 2.0);
+        let b = Complex::new(3.0, -1.0);
+
+        assert_eq!(a + b, Complex::new(4.0, 1.0));
+        assert_eq!(a - b, Complex::new(-2.0, 3.0));
+        assert_eq!(a * b, Complex::new(5.0, 5.0));
+        let c = (a * b) / b;
+        assert_approx_eq!(c.re, a.re);
+        assert_approx_eq!(c.im, a.im);
+    }
+
+    #[test]
+    fn polar() {
+        let c = Complex::from_polar(2.0, -0.5);
+
+        assert_approx_eq!(c.norm(), 2.0);
+        assert_approx_eq!(c.arg(), -0.5);
+    }
+
+    #[test]
+    fn solve_voltage_divider() {
+        // 1V across a resistor and an equal reactance
+        let mut equation = ComplexEquation::new(3, 1);
+        equation.stamp_voltage_source(Complex::from(1.0), 0, 1, 0);
+        equation.stamp_admittance(Complex::from(1.0), 1, 2);
+        equation.stamp_admittance(Complex::new(0.0, 1.0), 2, 0);
+
+        let (voltages, currents) = equation.solve().unwrap();
+
+        assert_approx_eq!(voltages[2].norm(), 1.0 / 2f64.sqrt());
+        assert_approx_eq!(voltages[2].arg(), -FRAC_PI_4);
+        assert_approx_eq!(currents[0].norm(), 1.0 / 2f64.sqrt());
+    }
+
+    #[test]
+    fn singular() {
+        let mut equation = ComplexEquation::new(3, 0);
+        equation.stamp_admittance(Complex::from(1.0), 1, 2);
+
+        assert!(equation.solve().is_err());
+    }
+}
@@ -0,0 +1,440 @@
+use std;
+use std::collections::HashMap;
+use specs;
+use elements::resistor::Resistor;
+use elements::voltage_source::VoltageSource;
+use elements::current_source::CurrentSource;
+use elements::waveform::Waveform;
+use analysis::operating_point;
+use analysis::logarithmic;
+use solver::equation;
+
+// The value being swept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parameter {
+    // The voltage of a `VoltageSource`
+    Voltage(specs::Entity),
+    // The current of a `CurrentSource`
+    Current(specs::Entity),
+    // The resistance of a `Resistor`
+    Resistance(specs::Entity),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+    // From `start` to `stop` inclusive, in increments of `step`
+    Linear { start: f64, stop: f64, step: f64 },
+    Logarithmic {
+        points_per_decade: usize,
+        start: f64,
+        stop: f64,
+    },
+    List(Vec<f64>),
+}
+
+impl Values {
+    pub fn values(&self) -> Result<Vec<f64>, Error> {
+        match *self {
+            Values::Linear { start, stop, step } => {
+                // a step of zero, or away from `stop`, never gets there
+                if step == 0.0 || !step.is_finite() || (stop - start) * step < 0.0 {
+                    return Err(Error::InvalidStep(step));
+                }
+                let steps = ((stop - start) / step + 1e-9).floor();
+                Ok((0..(steps as usize + 1)).map(|i| start + step * i as f64).collect())
+            }
+            Values::Logarithmic { points_per_decade, start, stop } => {
+                logarithmic(10.0, points_per_decade, start, stop)
+                    .ok_or(Error::InvalidStep(points_per_decade as f64))
+            }
+            Values::List(ref values) => Ok(values.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    pub parameter: Parameter,
+    pub values: Values,
+}
+
+impl Sweep {
+    pub fn new(parameter: Parameter, values: Values) -> Self {
+        Sweep {
+            parameter: parameter,
+            values: values,
+        }
+    }
+}
+
+// The operating point at a single point in the sweep.
+#[derive(Debug, Clone)]
+pub struct Row {
+    // The swept values: the inner sweep's first, then the outer sweep's
+    pub values: Vec<f64>,
+    // Node voltages, by node index
+    pub voltages: Vec<f64>,
+    // Current through each element which has one, e.g. sources
+    pub currents: HashMap<specs::Entity, f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    // The swept parameters, in the same order as each row's `values`
+    pub parameters: Vec<Parameter>,
+    pub rows: Vec<Row>,
+}
+
+impl Table {
+    // A node's voltage at each point in the sweep.
+    pub fn voltage(&self, node: usize) -> Vec<f64> {
+        self.rows.iter().map(|row| row.voltages[node]).collect()
+    }
+
+    // A source's current at each point in the sweep.
+    pub fn current(&self, source: specs::Entity) -> Vec<f64> {
+        self.rows.iter().map(|row| row.currents[&source]).collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // The entity doesn't have the swept component, or is a source driven by
+    // a `Waveform`
+    InvalidParameter(Parameter),
+    // A linear sweep's step is zero, or goes away from its stop value, or a
+    // logarithmic sweep has no points per decade or doesn't go up from a
+    // positive start value
+    InvalidStep(f64),
+    Unsolvable(equation::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Error::InvalidParameter(parameter) => {
+                write!(f, "Cannot sweep parameter {:?}", parameter)
+            }
+            Error::InvalidStep(step) => write!(f, "Cannot sweep in steps of {}", step),
+            Error::Unsolvable(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::InvalidParameter(_) |
+            Error::InvalidStep(_) => None,
+            Error::Unsolvable(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<equation::Error> for Error {
+    fn from(err: equation::Error) -> Error {
+        Error::Unsolvable(err)
+    }
+}
+
+// Find the operating point at each value of `sweep`, optionally nested inside
+// an `outer` sweep, like `.dc` in SPICE.
+//
+// Swept parameters are restored to their original values afterwards. Node
+// indexes must already be assigned.
+pub fn dc_sweep(world: &mut specs::World,
+                sweep: &Sweep,
+                outer: Option<&Sweep>)
+                -> Result<Table, Error> {
+    let mut originals = vec![(sweep.parameter, parameter_value(world, sweep.parameter)?)];
+    if let Some(outer) = outer {
+        originals.push((outer.parameter, parameter_value(world, outer.parameter)?));
+    }
+
+    let table = sweep_table(world, sweep, outer);
+
+    for &(parameter, value) in &originals {
+        set_parameter(world, parameter, value);
+    }
+    table
+}
+
+fn sweep_table(world: &mut specs::World,
+               sweep: &Sweep,
+               outer: Option<&Sweep>)
+               -> Result<Table, Error> {
+    let mut parameters = vec![sweep.parameter];
+    let outer_values = match outer {
+        Some(outer) => {
+            parameters.push(outer.parameter);
+            outer.values.values()?.into_iter().map(Some).collect()
+        }
+        None => vec![None],
+    };
+
+    let values = sweep.values.values()?;
+
+    let mut rows = Vec::new();
+    for outer_value in outer_values {
+        if let (Some(outer), Some(value)) = (outer, outer_value) {
+            set_parameter(world, outer.parameter, value);
+        }
+
+        for &value in &values {
+            set_parameter(world, sweep.parameter, value);
+            let op = operating_point(world)?;
+
+            let mut values = vec![value];
+            values.extend(outer_value);
+            let currents = op.elements
+                .iter()
+                .filter_map(|(&entity, state)| state.current.map(|current| (entity, current)))
+                .collect();
+
+            rows.push(Row {
+                values: values,
+                voltages: op.voltages,
+                currents: currents,
+            });
+        }
+    }
+
+    Ok(Table {
+        parameters: parameters,
+        rows: rows,
+    })
+}
+
+// The current value of a parameter, if it can be swept.
+fn parameter_value(world: &specs::World, parameter: Parameter) -> Result<f64, Error> {
+    use specs::Gate;
+
+    let waveforms = world.read::<Waveform>().pass();
+    let value = match parameter {
+        Parameter::Voltage(entity) if waveforms.get(entity).is_none() => {
+            world.read::<VoltageSource>().pass().get(entity).map(|vi| vi.voltage)
+        }
+        Parameter::Current(entity) if waveforms.get(entity).is_none() => {
+            world.read::<CurrentSource>().pass().get(entity).map(|ci| ci.current)
+        }
+        Parameter::Resistance(entity) => {
+            world.read::<Resistor>().pass().get(entity).map(|res| res.resistance())
+        }
+        _ => None,
+    };
+    value.ok_or(Error::InvalidParameter(parameter))
+}
+
+fn set_parameter(world: &mut specs::World, parameter: Parameter, value: f64) {
+    use specs::Gate;
+
+    match parameter {
+        Parameter::Voltage(entity) => {
+            if let Some(vi) = world.write::<VoltageSource>().pass().get_mut(entity) {
+                vi.voltage = value;
+            }
+        }
+        Parameter::Current(entity) => {
+            if let Some(ci) = world.write::<CurrentSource>().pass().get_mut(entity) {
+                ci.current = value;
+            }
+        }
+        Parameter::Resistance(entity) => {
+            if let Some(res) = world.write::<Resistor>().pass().get_mut(entity) {
+                res.set_resistance(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::Gate;
+    use analysis::tests::create_world;
+    use netlist;
+
+    #[test]
+    fn linear_values() {
+        let values = Values::Linear {
+            start: -1.0,
+            stop: 1.0,
+            step: 0.5,
+        };
+
+        assert_eq!(values.values().unwrap(), vec![-1.0, -0.5, 0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn invalid_steps() {
+        for &(stop, step) in &[(1.0, 0.0), (1.0, -0.5), (-1.0, 0.5), (1.0, std::f64::NAN)] {
+            let values = Values::Linear {
+                start: 0.0,
+                stop: stop,
+                step: step,
+            };
+            match values.values() {
+                Err(Error::InvalidStep(_)) => {}
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn logarithmic_values() {
+        let values = Values::Logarithmic {
+            points_per_decade: 1,
+            start: 1.0,
+            stop: 1000.0,
+        };
+
+        let values = values.values().unwrap();
+        assert_eq!(values.len(), 4);
+        assert_approx_eq!(values[3], 1000.0, 1e-9);
+    }
+
+    #[test]
+    fn invalid_logarithmic_values() {
+        for &(points_per_decade, start, stop) in &[(0, 1.0, 1000.0),
+                                                   (1, 0.0, 1000.0),
+                                                   (1, -1.0, 1000.0),
+                                                   (1, 1000.0, 1.0)] {
+            let values = Values::Logarithmic {
+                points_per_decade: points_per_decade,
+                start: start,
+                stop: stop,
+            };
+            match values.values() {
+                Err(Error::InvalidStep(_)) => {}
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_voltage_source() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Divider
+V1 in 0 5
+R1 in out 1k
+R2 out 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+        let v1 = netlist.entity("V1").unwrap();
+
+        let sweep = Sweep::new(Parameter::Voltage(v1),
+                               Values::Linear {
+                                   start: 0.0,
+                                   stop: 10.0,
+                                   step: 2.0,
+                               });
+        let table = dc_sweep(&mut world, &sweep, None).unwrap();
+
+        let out = table.voltage(netlist.node_index("out").unwrap());
+        assert_eq!(out.len(), 6);
+        for (row, v) in table.rows.iter().zip(out) {
+            assert_approx_eq!(v, row.values[0] / 2.0);
+        }
+        assert_approx_eq!(table.current(v1)[5], 5e-3);
+
+        // the source is left as it was
+        let v_sources = world.read::<VoltageSource>().pass();
+        assert_eq!(v_sources.get(v1).unwrap().voltage, 5.0);
+    }
+
+    #[test]
+    fn cmos_inverter() {
+        use elements::mosfet::Mosfet;
+        use elements::mosfet::Polarity;
+        use elements::mosfet;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Inverter
+V1 vdd 0 5
+V2 in 0 0
+C1 out 0 1p
+.end",
+                                     &mut world)
+            .unwrap();
+        let nmos = Mosfet {
+            threshold_voltage: 1.0,
+            transconductance: 1e-4,
+            channel_length_modulation: 0.02,
+            width: 10e-6,
+            length: 10e-6,
+            ..Mosfet::default()
+        };
+        let pmos = Mosfet {
+            polarity: Polarity::Pmos,
+            threshold_voltage: -1.0,
+            ..nmos
+        };
+        let n = mosfet::create(&mut world, Polarity::Nmos);
+        let p = mosfet::create(&mut world, Polarity::Pmos);
+        {
+            let mut mosfets = world.write::<Mosfet>().pass();
+            mosfets.insert(n, nmos);
+            mosfets.insert(p, pmos);
+        }
+        connect(&mut world, &netlist, n, &["out", "in", "0", "0"]);
+        connect(&mut world, &netlist, p, &["out", "in", "vdd", "vdd"]);
+
+        let sweep = Sweep::new(Parameter::Voltage(netlist.entity("V2").unwrap()),
+                               Values::List(vec![0.0, 2.5, 5.0]));
+        let table = dc_sweep(&mut world, &sweep, None).unwrap();
+
+        // rail to rail, and symmetrical
+        let out = table.voltage(netlist.node_index("out").unwrap());
+        assert_approx_eq!(out[0], 5.0, 1e-6);
+        assert_approx_eq!(out[1], 2.5, 1e-6);
+        assert_approx_eq!(out[2], 0.0, 1e-6);
+    }
+
+    #[test]
+    fn nested_sweep() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Divider
+V1 in 0 5
+I1 0 out 1m
+R1 in out 1k
+R2 out 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+
+        let sweep = Sweep::new(Parameter::Resistance(netlist.entity("R2").unwrap()),
+                               Values::List(vec![1e3, 3e3]));
+        let outer = Sweep::new(Parameter::Current(netlist.entity("I1").unwrap()),
+                               Values::List(vec![0.0, 1e-3, 2e-3]));
+        let table = dc_sweep(&mut world, &sweep, Some(&outer)).unwrap();
+
+        assert_eq!(table.rows.len(), 6);
+        assert_eq!(table.rows[3].values, vec![3e3, 1e-3]);
+
+        // 5V through 1k and 3k, plus 1mA into 750R
+        let out = netlist.node_index("out").unwrap();
+        assert_approx_eq!(table.rows[3].voltages[out], 3.75 + 0.75);
+        assert_approx_eq!(table.current(netlist.entity("I1").unwrap())[5], 2e-3);
+    }
+
+    #[test]
+    fn invalid_parameter() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Sine
+V1 in 0 SIN(0 1 1k)
+R1 in 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+
+        let resistor_as_source = Sweep::new(Parameter::Voltage(netlist.entity("R1").unwrap()),
+                                            Values::List(vec![1.0]));
+        let waveform_source = Sweep::new(Parameter::Voltage(netlist.entity("V1").unwrap()),
+                                         Values::List(vec![1.0]));
+
+        assert!(dc_sweep(&mut world, &resistor_as_source, None).is_err());
+        assert!(dc_sweep(&mut world, &waveform_source, None).is_err());
+    }
+}
@@ -0,0 +1,25 @@
+mod operating_point;
+pub mod complex;
+pub mod ac;
+pub mod dc;
+
+pub use self::operating_point::operating_point;
+pub use self::operating_point::OperatingPoint;
+pub use self::operating_point::ElementState;
+pub use self::ac::ac_sweep;
+pub use self::dc::dc_sweep;
+
+// `points_per` values per multiple of `base`, from `start` up to `stop`, or
+// `None` if there are no points per multiple or the range doesn't go up from
+// a positive `start`.
+fn logarithmic(base: f64, points_per: usize, start: f64, stop: f64) -> Option<Vec<f64>> {
+    if points_per == 0 || !(start > 0.0) || !(stop >= start) || !stop.is_finite() {
+        return None;
+    }
+    // allow for rounding, so `stop` is included when it's on a point
+    let steps = ((stop / start).log(base) * points_per as f64 + 1e-9).floor() as usize;
+    Some((0..(steps + 1)).map(|i| start * base.powf(i as f64 / points_per as f64)).collect())
+}
+
+#[cfg(test)]
+pub mod tests;
@@ -0,0 +1,621 @@
+use std::cmp;
+use std::collections::HashMap;
+use specs;
+use elements::Nodes;
+use elements::CalculatedCurrent;
+use elements::DerivedCurrent;
+use elements::resistor::Resistor;
+use elements::capacitor::Capacitor;
+use elements::inductor::Inductor;
+use elements::diode::Diode;
+use elements::bjt::Bjt;
+use elements::mosfet::Mosfet;
+use elements::voltage_source::VoltageSource;
+use elements::current_source::CurrentSource;
+use elements::waveform::Waveform;
+use elements::vcvs::Vcvs;
+use elements::vccs::Vccs;
+use elements::ccvs::Ccvs;
+use elements::cccs::Cccs;
+use elements::opamp::OpAmp;
+use elements::switch::Switch;
+use solver;
+use solver::equation;
+use solver::equation::Equation;
+use solver::newton;
+use solver::newton::Linearisation;
+use solver::stamp_dynamic;
+use solver::diagnostics;
+
+// The state of a single element at the operating point.
+#[derive(Debug, Clone)]
+pub struct ElementState {
+    // Voltage at each of the element's terminals
+    pub voltages: Vec<f64>,
+    // Current through the element, in the same direction as its
+    // `CalculatedCurrent` or `DerivedCurrent` (e.g. from the first node to
+    // the second for passive elements), if known
+    pub current: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OperatingPoint {
+    // Node voltages, by node index
+    pub voltages: Vec<f64>,
+    pub elements: HashMap<specs::Entity, ElementState>,
+}
+
+impl OperatingPoint {
+    pub fn element(&self, entity: specs::Entity) -> Option<&ElementState> {
+        self.elements.get(&entity)
+    }
+
+    // Start the transient solver from the operating point, rather than from
+    // every node at 0V. Non-linear elements are linearised around their
+    // operating point voltages, so the first step starts from there too.
+    //
+    // Node indexes must not have changed since the operating point was
+    // found.
+    pub fn seed_transient(&self, world: &mut specs::World) {
+        use specs::Join;
+        use specs::Gate;
+
+        let mut nodes_ticket = world.write::<Nodes>().pass();
+        let mut calc_currents = world.write::<CalculatedCurrent>().pass();
+        let mut derived_currents = world.write::<DerivedCurrent>().pass();
+        let mut opamps = world.write::<OpAmp>().pass();
+        let mut diodes = world.write::<Diode>().pass();
+        let mut bjts = world.write::<Bjt>().pass();
+        let mut mosfets = world.write::<Mosfet>().pass();
+
+        for (nodes,) in (&mut nodes_ticket,).join() {
+            let &mut Nodes(ref mut ns) = nodes;
+            for node in ns.iter_mut() {
+                node.voltage = self.voltages[node.index];
+            }
+        }
+        for (nodes, diode) in (&nodes_ticket, &mut diodes).join() {
+            let &Nodes(ref ns) = nodes;
+            diode.junction_voltage = ns[diode.node_indexes.0].voltage -
+                                     ns[diode.node_indexes.1].voltage;
+        }
+        for (nodes, bjt) in (&nodes_ticket, &mut bjts).join() {
+            let &Nodes(ref ns) = nodes;
+            let collector = ns[bjt.node_indexes.0].voltage;
+            let base = ns[bjt.node_indexes.1].voltage;
+            let emitter = ns[bjt.node_indexes.2].voltage;
+            let sign = bjt.polarity.sign();
+            bjt.base_emitter_voltage = sign * (base - emitter);
+            bjt.base_collector_voltage = sign * (base - collector);
+        }
+        for (nodes, mosfet) in (&nodes_ticket, &mut mosfets).join() {
+            let &Nodes(ref ns) = nodes;
+            let drain = ns[mosfet.node_indexes.0].voltage;
+            let gate = ns[mosfet.node_indexes.1].voltage;
+            let source = ns[mosfet.node_indexes.2].voltage;
+            let body = ns[mosfet.node_indexes.3].voltage;
+            let sign = mosfet.polarity.sign();
+            mosfet.gate_source_voltage = sign * (gate - source);
+            mosfet.drain_source_voltage = sign * (drain - source);
+            mosfet.body_source_voltage = sign * (body - source);
+        }
+        for (nodes, opamp) in (&nodes_ticket, &mut opamps).join() {
+            opamp.internal_voltage = stamp_dynamic::opamp_internal_voltage(opamp, nodes, None);
+        }
+
+        for (&entity, state) in &self.elements {
+            if let Some(current) = state.current {
+                if let Some(calc_current) = calc_currents.get_mut(entity) {
+                    calc_current.0 = current;
+                }
+                if let Some(derived_current) = derived_currents.get_mut(entity) {
+                    derived_current.0 = current;
+                }
+            }
+        }
+    }
+}
+
+// Find the DC operating point of the circuit.
+//
+// Capacitors are treated as open circuits, inductors as short circuits, op-amp
+// macro models have their DC gain, and time-varying sources take their value
+// at time zero. A tiny conductance
+// (`GMIN`) to ground is added at every node, so nodes only connected through
+// capacitors aren't left floating.
+//
+// If the circuit can't be solved because of floating nodes or voltage source
+// loops, the error says which. Node indexes must already be assigned.
+pub fn operating_point(world: &mut specs::World) -> Result<OperatingPoint, equation::Error> {
+    solve_operating_point(world).map_err(|error| diagnostics::explain(world, error))
+}
+
+fn solve_operating_point(world: &mut specs::World) -> Result<OperatingPoint, equation::Error> {
+    use specs::Join;
+    use specs::Gate;
+
+    let num_branches = solver::assign_branches(world);
+    let num_nodes = solver::count_nodes(world);
+
+    // inductors are shorted by a 0V source, which needs a branch each
+    let inductor_branches: HashMap<specs::Entity, usize> = {
+        let entities = world.entities();
+        let inductors = world.read::<Inductor>().pass();
+        (&entities, &inductors)
+            .join()
+            .enumerate()
+            .map(|(i, (entity, _))| (entity, num_branches + i))
+            .collect()
+    };
+
+    let mut equation = Equation::new(num_nodes, num_branches + inductor_branches.len());
+    solver::stamp_static_elements(world, &mut equation);
+
+    let entities = world.entities();
+    let nodes_ticket = world.read::<Nodes>().pass();
+    let mut v_sources = world.write::<VoltageSource>().pass();
+    let mut c_sources = world.write::<CurrentSource>().pass();
+    let waveforms = world.read::<Waveform>().pass();
+    let resistors = world.read::<Resistor>().pass();
+    let capacitors = world.read::<Capacitor>().pass();
+    let inductors = world.read::<Inductor>().pass();
+    let diodes = world.read::<Diode>().pass();
+    let bjts = world.read::<Bjt>().pass();
+    let mosfets = world.read::<Mosfet>().pass();
+    let vcvs = world.read::<Vcvs>().pass();
+    let vccs = world.read::<Vccs>().pass();
+    let ccvs = world.read::<Ccvs>().pass();
+    let cccs = world.read::<Cccs>().pass();
+    let opamps = world.read::<OpAmp>().pass();
+    let switches = world.read::<Switch>().pass();
+
+    for (nodes, vi, waveform) in (&nodes_ticket, &mut v_sources, &waveforms).join() {
+        stamp_dynamic::stamp_voltage_source(&mut equation, vi, waveform, nodes, 0.0);
+    }
+    for (nodes, ci, waveform) in (&nodes_ticket, &mut c_sources, &waveforms).join() {
+        stamp_dynamic::stamp_current_source(&mut equation, ci, waveform, nodes, 0.0);
+    }
+
+    for (nodes, switch) in (&nodes_ticket, &switches).join() {
+        stamp_dynamic::stamp_switch(&mut equation, switch, nodes);
+    }
+
+    for (cap,) in (&capacitors,).join() {
+        if cap.uses_branch() {
+            equation.stamp_open_branch(cap.voltage_source.index);
+        }
+    }
+
+    for (entity, nodes, ind) in (&entities, &nodes_ticket, &inductors).join() {
+        let &Nodes(ref ns) = nodes;
+        let n0 = ns[ind.node_indexes.0];
+        let n1 = ns[ind.node_indexes.1];
+
+        equation.stamp_voltage_source(0.0, n0.index, n1.index, inductor_branches[&entity]);
+    }
+
+    for node in 1..num_nodes {
+        equation.stamp_conductance(stamp_dynamic::GMIN, node, 0);
+    }
+
+    // Newton-Raphson iterations update the junction voltages of non-linear
+    // elements, so work on copies and leave the world's alone
+    let mut local_diodes: Vec<_> = (&nodes_ticket, &diodes).join()
+        .map(|(nodes, diode)| (nodes, *diode))
+        .collect();
+    let mut local_bjts: Vec<_> = (&nodes_ticket, &bjts).join()
+        .map(|(nodes, bjt)| (nodes, *bjt))
+        .collect();
+    let mut local_mosfets: Vec<_> = (&nodes_ticket, &mosfets).join()
+        .map(|(nodes, mosfet)| (nodes, *mosfet))
+        .collect();
+
+    let solution = newton::solve(&equation,
+                                 &newton::Settings::default(),
+                                 &vec![0f64; num_nodes],
+                                 |equation, voltages| {
+        let mut linearisation = Linearisation::Linear;
+        for &mut (nodes, ref mut diode) in &mut local_diodes {
+            linearisation = cmp::max(linearisation,
+                                     stamp_dynamic::stamp_diode(equation, diode, nodes, voltages));
+        }
+        for &mut (nodes, ref mut bjt) in &mut local_bjts {
+            linearisation = cmp::max(linearisation,
+                                     stamp_dynamic::stamp_bjt(equation, bjt, nodes, voltages));
+        }
+        for &mut (nodes, ref mut mosfet) in &mut local_mosfets {
+            linearisation = cmp::max(linearisation,
+                                     stamp_dynamic::stamp_mosfet(equation,
+                                                                 mosfet,
+                                                                 nodes,
+                                                                 voltages));
+        }
+        for (nodes, opamp) in (&nodes_ticket, &opamps).join() {
+            linearisation = cmp::max(linearisation,
+                                     stamp_dynamic::stamp_opamp(equation,
+                                                                opamp,
+                                                                nodes,
+                                                                voltages,
+                                                                None));
+        }
+        linearisation
+    })?;
+    let voltages = solution.voltages();
+    let currents = solution.currents();
+
+    let mut elements = HashMap::new();
+    for (entity, nodes) in (&entities, &nodes_ticket).join() {
+        let &Nodes(ref ns) = nodes;
+        let across = |(n0, n1): (usize, usize)| voltages[ns[n0].index] - voltages[ns[n1].index];
+
+        let current = if let Some(vi) = v_sources.get(entity) {
+            Some(currents[vi.index])
+        } else if let Some(ci) = c_sources.get(entity) {
+            Some(ci.current)
+        } else if let Some(res) = resistors.get(entity) {
+            Some(across(res.node_indexes) / res.resistance())
+        } else if capacitors.get(entity).is_some() {
+            Some(0.0)
+        } else if inductors.get(entity).is_some() {
+            Some(currents[inductor_branches[&entity]])
+        } else if let Some(diode) = diodes.get(entity) {
+            Some(diode.current(across(diode.node_indexes)))
+        } else if let Some(bjt) = bjts.get(entity) {
+            let (collector, base, emitter) = bjt.node_indexes;
+            Some(bjt.currents(across((base, emitter)), across((base, collector))).0)
+        } else if let Some(mosfet) = mosfets.get(entity) {
+            let (drain, gate, source, body) = mosfet.node_indexes;
+            Some(mosfet.current(across((gate, source)),
+                                across((drain, source)),
+                                across((body, source))))
+        } else if let Some(source) = vcvs.get(entity) {
+            Some(currents[source.index])
+        } else if let Some(switch) = switches.get(entity) {
+            Some(stamp_dynamic::switch_current(switch, nodes, voltages))
+        } else if let Some(source) = vccs.get(entity) {
+            Some(-source.transconductance * across(source.control_node_indexes))
+        } else if let Some(source) = ccvs.get(entity) {
+            Some(currents[source.index])
+        } else if let Some(source) = cccs.get(entity) {
+            let control = source.control.and_then(|control| v_sources.get(control));
+            Some(control.map_or(0.0, |control| source.gain * currents[control.index]))
+        } else if let Some(opamp) = opamps.get(entity) {
+            Some(currents[opamp.index])
+        } else {
+            None
+        };
+
+        elements.insert(entity,
+                        ElementState {
+                            voltages: ns.iter().map(|node| voltages[node.index]).collect(),
+                            current: current,
+                        });
+    }
+
+    Ok(OperatingPoint {
+        voltages: voltages.clone(),
+        elements: elements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::Gate;
+    use analysis::tests::create_world;
+    use netlist;
+
+    #[test]
+    fn capacitors_are_open() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Divider
+V1 in 0 10
+R1 in out 1k
+R2 out 0 1k
+C1 out 0 1u
+C2 in out 1u
+.end",
+                                     &mut world)
+            .unwrap();
+
+        let op = operating_point(&mut world).unwrap();
+
+        let out = netlist.node_index("out").unwrap();
+        assert_approx_eq!(op.voltages[out], 5.0);
+
+        let c1 = op.element(netlist.entity("C1").unwrap()).unwrap();
+        assert_approx_eq!(c1.voltages[0], 5.0);
+        assert_approx_eq!(c1.voltages[1], 0.0);
+        assert_eq!(c1.current, Some(0.0));
+
+        let r1 = op.element(netlist.entity("R1").unwrap()).unwrap();
+        assert_approx_eq!(r1.current.unwrap(), 5e-3);
+
+        // the source delivers 5mA
+        let v1 = op.element(netlist.entity("V1").unwrap()).unwrap();
+        assert_approx_eq!(v1.current.unwrap(), 5e-3);
+    }
+
+    #[test]
+    fn capacitor_companion_branch_is_open() {
+        use elements::capacitor::CompanionModel;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Divider
+V1 in 0 10
+R1 in out 1k
+R2 out 0 1k
+C1 out 0 1u
+.end",
+                                     &mut world)
+            .unwrap();
+        {
+            let mut capacitors = world.write::<Capacitor>().pass();
+            let cap = capacitors.get_mut(netlist.entity("C1").unwrap()).unwrap();
+            cap.companion_model = CompanionModel::Thevenin;
+        }
+
+        let op = operating_point(&mut world).unwrap();
+
+        assert_approx_eq!(op.voltages[netlist.node_index("out").unwrap()], 5.0);
+    }
+
+    #[test]
+    fn inductors_are_shorted() {
+        let mut world = create_world();
+        let netlist = netlist::parse("RL
+V1 in 0 5
+R1 in out 1k
+L1 out 0 1m
+.end",
+                                     &mut world)
+            .unwrap();
+
+        let op = operating_point(&mut world).unwrap();
+
+        assert_approx_eq!(op.voltages[netlist.node_index("out").unwrap()], 0.0);
+        let l1 = op.element(netlist.entity("L1").unwrap()).unwrap();
+        assert_approx_eq!(l1.current.unwrap(), 5e-3);
+    }
+
+    #[test]
+    fn opamp_amplifiers() {
+        use elements::opamp::MacroModel;
+        use elements::opamp::Model;
+        use elements::opamp;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Amplifiers
+V1 in 0 2
+R1 in a 1k
+R2 a b 2k
+R3 c 0 1k
+R4 d c 10k
+.end",
+                                     &mut world)
+            .unwrap();
+        // an inverting amplifier with a gain of -2, and a non-inverting one
+        // with a gain of 11
+        let inverting = opamp::create(&mut world);
+        connect(&mut world, &netlist, inverting, &["0", "a", "b"]);
+        let non_inverting = opamp::create(&mut world);
+        world.write::<OpAmp>().pass().get_mut(non_inverting).unwrap().model =
+            Model::Macro(MacroModel::default());
+        connect(&mut world, &netlist, non_inverting, &["in", "c", "d"]);
+
+        let op = operating_point(&mut world).unwrap();
+
+        let node = |name| netlist.node_index(name).unwrap();
+        assert_approx_eq!(op.voltages[node("a")], 0.0);
+        assert_approx_eq!(op.voltages[node("b")], -4.0);
+        assert_approx_eq!(op.element(inverting).unwrap().current.unwrap(), -2e-3);
+
+        // 22V is beyond the rail, less the drop across the output resistance
+        let output_current = 15.0 / (11e3 + 75.0);
+        assert_approx_eq!(op.voltages[node("d")], 15.0 - 75.0 * output_current, 1e-5);
+    }
+
+    #[test]
+    fn bjt_bias() {
+        use elements::bjt::Polarity;
+        use elements::bjt;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Common emitter
+V1 vcc 0 10
+R1 vcc npn_b 1meg
+R2 vcc npn_c 1k
+R3 pnp_b 0 1meg
+R4 pnp_c 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+        let npn = bjt::create(&mut world, Polarity::Npn);
+        connect(&mut world, &netlist, npn, &["npn_c", "npn_b", "0"]);
+        let pnp = bjt::create(&mut world, Polarity::Pnp);
+        connect(&mut world, &netlist, pnp, &["pnp_c", "pnp_b", "vcc"]);
+
+        let op = operating_point(&mut world).unwrap();
+        let node = |name| netlist.node_index(name).unwrap();
+
+        // forward active, with a gain of BF
+        let base_voltage = op.voltages[node("npn_b")];
+        assert!(base_voltage > 0.6 && base_voltage < 0.8);
+        let base_current = (10.0 - base_voltage) / 1e6;
+        let collector_current = op.element(npn).unwrap().current.unwrap();
+        assert_approx_eq!(collector_current, 100.0 * base_current, 1e-8);
+        assert_approx_eq!(op.voltages[node("npn_c")], 10.0 - 1e3 * collector_current, 1e-5);
+
+        // the same, upside down
+        assert_approx_eq!(op.voltages[node("pnp_b")], 10.0 - base_voltage, 1e-6);
+        assert_approx_eq!(op.element(pnp).unwrap().current.unwrap(),
+                          -collector_current,
+                          1e-8);
+        assert_approx_eq!(op.voltages[node("pnp_c")], 1e3 * collector_current, 1e-5);
+    }
+
+    #[test]
+    fn mosfet_bias() {
+        use elements::mosfet::Mosfet;
+        use elements::mosfet::Polarity;
+        use elements::mosfet;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Common source
+V1 vdd 0 10
+V2 nmos_g 0 3
+V3 pmos_g 0 7
+R1 vdd nmos_d 10k
+R2 pmos_d 0 10k
+R3 vdd triode_d 50k
+.end",
+                                     &mut world)
+            .unwrap();
+        let model = Mosfet {
+            threshold_voltage: 1.0,
+            transconductance: 1e-4,
+            width: 10e-6,
+            length: 10e-6,
+            ..Mosfet::default()
+        };
+        let nmos = mosfet::create(&mut world, Polarity::Nmos);
+        let pmos = mosfet::create(&mut world, Polarity::Pmos);
+        let triode = mosfet::create(&mut world, Polarity::Nmos);
+        {
+            let mut mosfets = world.write::<Mosfet>().pass();
+            mosfets.insert(nmos, model);
+            mosfets.insert(pmos,
+                           Mosfet {
+                               polarity: Polarity::Pmos,
+                               threshold_voltage: -1.0,
+                               ..model
+                           });
+            mosfets.insert(triode, model);
+        }
+        connect(&mut world, &netlist, nmos, &["nmos_d", "nmos_g", "0", "0"]);
+        connect(&mut world, &netlist, pmos, &["pmos_d", "pmos_g", "vdd", "vdd"]);
+        connect(&mut world, &netlist, triode, &["triode_d", "vdd", "0", "0"]);
+
+        let op = operating_point(&mut world).unwrap();
+        let node = |name| netlist.node_index(name).unwrap();
+
+        // saturated, with beta * (Vgs - Vt)^2 / 2
+        let saturation_current = 1e-4 * 2.0 * 2.0 / 2.0;
+        assert_approx_eq!(op.element(nmos).unwrap().current.unwrap(), saturation_current, 1e-9);
+        assert_approx_eq!(op.voltages[node("nmos_d")], 10.0 - 10e3 * saturation_current, 1e-5);
+
+        // the same, upside down
+        assert_approx_eq!(op.element(pmos).unwrap().current.unwrap(),
+                          -saturation_current,
+                          1e-9);
+        assert_approx_eq!(op.voltages[node("pmos_d")], 10e3 * saturation_current, 1e-5);
+
+        // in the triode region, with beta * Vds * (Vgs - Vt - Vds / 2)
+        let drain_voltage = op.voltages[node("triode_d")];
+        let triode_current = 1e-4 * drain_voltage * (9.0 - drain_voltage / 2.0);
+        assert!(drain_voltage < 9.0);
+        assert_approx_eq!(op.element(triode).unwrap().current.unwrap(), triode_current, 1e-9);
+        assert_approx_eq!((10.0 - drain_voltage) / 50e3, triode_current, 1e-9);
+    }
+
+    #[test]
+    fn switches() {
+        use specs::Gate;
+        use elements::switch::Kind;
+        use elements::switch::Switch;
+        use elements::switch::DEFAULT_ON_RESISTANCE;
+        use elements::switch;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Changeover
+V1 in 0 10
+R1 a 0 1k
+R2 b 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+        let switch = switch::create(&mut world, Kind::Spdt);
+        connect(&mut world, &netlist, switch, &["in", "a", "b"]);
+        let node = |name| netlist.node_index(name).unwrap();
+
+        let op = operating_point(&mut world).unwrap();
+        let current = 10.0 / (1e3 + DEFAULT_ON_RESISTANCE);
+        assert_approx_eq!(op.voltages[node("a")], 1e3 * current, 1e-6);
+        assert_approx_eq!(op.voltages[node("b")], 0.0, 1e-6);
+        assert_approx_eq!(op.element(switch).unwrap().current.unwrap(), current, 1e-9);
+
+        world.write::<Switch>().pass().get_mut(switch).unwrap().toggle();
+
+        let op = operating_point(&mut world).unwrap();
+        assert_approx_eq!(op.voltages[node("a")], 0.0, 1e-6);
+        assert_approx_eq!(op.voltages[node("b")], 1e3 * current, 1e-6);
+    }
+
+    #[test]
+    fn seed_transient() {
+        let mut world = create_world();
+        let netlist = netlist::parse("RC
+V1 in 0 10
+R1 in out 1k
+R2 out 0 1k
+C1 out 0 1u
+.end",
+                                     &mut world)
+            .unwrap();
+
+        let op = operating_point(&mut world).unwrap();
+        op.seed_transient(&mut world);
+
+        let nodes = world.read::<Nodes>().pass();
+        match nodes.get(netlist.entity("C1").unwrap()).unwrap() {
+            &Nodes(ref ns) => assert_approx_eq!(ns[0].voltage, 5.0),
+        }
+        let calc_currents = world.read::<CalculatedCurrent>().pass();
+        assert_approx_eq!(calc_currents.get(netlist.entity("V1").unwrap()).unwrap().0,
+                          5e-3);
+    }
+
+    #[test]
+    fn seed_transient_nonlinear() {
+        use elements::bjt;
+        use elements::mosfet;
+        use analysis::tests::connect;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Upside down
+V1 vcc 0 10
+V2 g 0 7
+R1 b 0 1meg
+R2 c 0 1k
+R3 d 0 10k
+.end",
+                                     &mut world)
+            .unwrap();
+        let pnp = bjt::create(&mut world, bjt::Polarity::Pnp);
+        connect(&mut world, &netlist, pnp, &["c", "b", "vcc"]);
+        let pmos = mosfet::create(&mut world, mosfet::Polarity::Pmos);
+        world.write::<Mosfet>().pass().get_mut(pmos).unwrap().threshold_voltage = -1.0;
+        connect(&mut world, &netlist, pmos, &["d", "g", "vcc", "vcc"]);
+
+        let op = operating_point(&mut world).unwrap();
+        // finding the operating point leaves the elements alone
+        assert_eq!(world.read::<Bjt>().pass().get(pnp).unwrap().base_emitter_voltage, 0.0);
+        assert_eq!(world.read::<Mosfet>().pass().get(pmos).unwrap().gate_source_voltage, 0.0);
+        op.seed_transient(&mut world);
+
+        let node = |name| op.voltages[netlist.node_index(name).unwrap()];
+        let bjts = world.read::<Bjt>().pass();
+        let pnp = bjts.get(pnp).unwrap();
+        assert_approx_eq!(pnp.base_emitter_voltage, 10.0 - node("b"));
+        assert_approx_eq!(pnp.base_collector_voltage, node("c") - node("b"));
+        let mosfets = world.read::<Mosfet>().pass();
+        let pmos = mosfets.get(pmos).unwrap();
+        assert_approx_eq!(pmos.gate_source_voltage, 3.0);
+        assert_approx_eq!(pmos.drain_source_voltage, 10.0 - node("d"));
+        assert_approx_eq!(pmos.body_source_voltage, 0.0);
+    }
+}
@@ -0,0 +1,27 @@
+use specs;
+use elements;
+use netlist;
+
+pub fn create_world() -> specs::World {
+    let mut world = specs::World::new();
+    elements::register(&mut world);
+    world
+}
+
+// Put an element's terminals on the named nodes of a parsed netlist, in the
+// same order as its `Nodes`.
+pub fn connect(world: &mut specs::World,
+               netlist: &netlist::Netlist,
+               entity: specs::Entity,
+               names: &[&str]) {
+    use specs::Gate;
+    use elements::Nodes;
+
+    match world.write::<Nodes>().pass().get_mut(entity).unwrap() {
+        &mut Nodes(ref mut ns) => {
+            for (node, name) in ns.iter_mut().zip(names) {
+                node.index = netlist.node_index(name).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use specs;
+
+// The small-signal magnitude and phase of a source for AC analysis,
+// attached to a `VoltageSource` or `CurrentSource`.
+//
+// Sources without one are zero in AC analysis, i.e. voltage sources are
+// shorts and current sources are open. Phase is in radians.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AcSource {
+    pub magnitude: f64,
+    pub phase: f64,
+}
+impl AcSource {
+    pub fn new(magnitude: f64, phase: f64) -> Self {
+        AcSource {
+            magnitude: magnitude,
+            phase: phase,
+        }
+    }
+}
+impl Default for AcSource {
+    fn default() -> Self {
+        AcSource::new(1.0, 0.0)
+    }
+}
+impl specs::Component for AcSource {
+    type Storage = specs::HashMapStorage<AcSource>;
+}
@@ -0,0 +1,236 @@
+use std::f64;
+use specs;
+use elements::capacitor::InternalCapacitance;
+use elements::diode::THERMAL_VOLTAGE;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::DerivedCurrent;
+
+pub const NPN_NAME: &'static str = "NPN transistor";
+pub const PNP_NAME: &'static str = "PNP transistor";
+
+// As in SPICE
+pub const DEFAULT_SATURATION_CURRENT: f64 = 1e-16;
+pub const DEFAULT_FORWARD_BETA: f64 = 100.0;
+pub const DEFAULT_REVERSE_BETA: f64 = 1.0;
+pub const DEFAULT_EARLY_VOLTAGE: f64 = f64::INFINITY;
+pub const DEFAULT_JUNCTION_CAPACITANCE: f64 = 0.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Polarity {
+    Npn,
+    Pnp,
+}
+impl Polarity {
+    // Junction voltages and terminal currents are negated for PNP, so the
+    // same equations work for both.
+    pub fn sign(&self) -> f64 {
+        match *self {
+            Polarity::Npn => 1.0,
+            Polarity::Pnp => -1.0,
+        }
+    }
+}
+
+// Terminal currents at a bias point, and their derivatives with respect to
+// the junction voltages, as for an NPN.
+#[derive(Debug, Clone, Copy)]
+pub struct SmallSignal {
+    pub collector_current: f64,
+    pub base_current: f64,
+    // (dIc/dVbe, dIc/dVbc)
+    pub collector_conductances: (f64, f64),
+    // (dIb/dVbe, dIb/dVbc)
+    pub base_conductances: (f64, f64),
+}
+
+// Bipolar junction transistor, with its collector as its first node, its
+// base as its second and its emitter as its third.
+//
+// Modelled with the transport form of Ebers-Moll, plus the Early effect (as
+// Gummel-Poon does with only VAF), and constant junction capacitances.
+// Linearised around `base_emitter_voltage` and `base_collector_voltage` on
+// every Newton-Raphson iteration.
+#[derive(Debug, Clone, Copy)]
+pub struct Bjt {
+    pub polarity: Polarity,
+
+    // IS
+    pub saturation_current: f64,
+    // BF
+    pub forward_beta: f64,
+    // BR
+    pub reverse_beta: f64,
+    // VAF, or infinite to ignore the Early effect
+    pub early_voltage: f64,
+
+    // CJE and CJC, both from the base
+    pub base_emitter_capacitance: InternalCapacitance,
+    pub base_collector_capacitance: InternalCapacitance,
+
+    // Junction voltages (negated for PNP) last linearised around
+    pub base_emitter_voltage: f64,
+    pub base_collector_voltage: f64,
+
+    // (collector, base, emitter)
+    pub node_indexes: (usize, usize, usize),
+}
+impl Bjt {
+    pub fn new(polarity: Polarity) -> Self {
+        let node_indexes = (0, 1, 2);
+
+        Bjt {
+            polarity: polarity,
+
+            saturation_current: DEFAULT_SATURATION_CURRENT,
+            forward_beta: DEFAULT_FORWARD_BETA,
+            reverse_beta: DEFAULT_REVERSE_BETA,
+            early_voltage: DEFAULT_EARLY_VOLTAGE,
+
+            base_emitter_capacitance:
+                InternalCapacitance::new(DEFAULT_JUNCTION_CAPACITANCE,
+                                         (node_indexes.1, node_indexes.2)),
+            base_collector_capacitance:
+                InternalCapacitance::new(DEFAULT_JUNCTION_CAPACITANCE,
+                                         (node_indexes.1, node_indexes.0)),
+
+            base_emitter_voltage: 0.0,
+            base_collector_voltage: 0.0,
+
+            node_indexes: node_indexes,
+        }
+    }
+
+    // Voltage above which the exponentials make Newton-Raphson overshoot.
+    pub fn critical_voltage(&self) -> f64 {
+        let vt = THERMAL_VOLTAGE;
+        vt * (vt / (2f64.sqrt() * self.saturation_current)).ln()
+    }
+
+    // The collector and base currents, and their derivatives, at the given
+    // junction voltages (negated for PNP).
+    pub fn small_signal(&self,
+                        base_emitter_voltage: f64,
+                        base_collector_voltage: f64)
+                        -> SmallSignal {
+        let vt = THERMAL_VOLTAGE;
+
+        let forward_exp = (base_emitter_voltage / vt).exp();
+        let reverse_exp = (base_collector_voltage / vt).exp();
+        let forward_current = self.saturation_current * (forward_exp - 1.0);
+        let reverse_current = self.saturation_current * (reverse_exp - 1.0);
+        let forward_conductance = self.saturation_current * forward_exp / vt;
+        let reverse_conductance = self.saturation_current * reverse_exp / vt;
+
+        // the Early effect widens the base as the collector junction is
+        // reverse biased
+        let early = 1.0 - base_collector_voltage / self.early_voltage;
+        let transport_current = (forward_current - reverse_current) * early;
+
+        SmallSignal {
+            collector_current: transport_current - reverse_current / self.reverse_beta,
+            base_current: forward_current / self.forward_beta +
+                          reverse_current / self.reverse_beta,
+            collector_conductances: (forward_conductance * early,
+                                     -reverse_conductance * early -
+                                     (forward_current - reverse_current) / self.early_voltage -
+                                     reverse_conductance / self.reverse_beta),
+            base_conductances: (forward_conductance / self.forward_beta,
+                                reverse_conductance / self.reverse_beta),
+        }
+    }
+
+    // The currents into the collector and base, given V(base) - V(emitter)
+    // and V(base) - V(collector), not including the junction capacitances.
+    pub fn currents(&self,
+                    base_emitter_voltage: f64,
+                    base_collector_voltage: f64)
+                    -> (f64, f64) {
+        let sign = self.polarity.sign();
+        let small_signal = self.small_signal(sign * base_emitter_voltage,
+                                             sign * base_collector_voltage);
+        (sign * small_signal.collector_current, sign * small_signal.base_current)
+    }
+}
+impl Default for Bjt {
+    fn default() -> Self {
+        Bjt::new(Polarity::Npn)
+    }
+}
+impl specs::Component for Bjt {
+    type Storage = specs::HashMapStorage<Bjt>;
+}
+
+pub fn create(world: &mut specs::World, polarity: Polarity) -> specs::Entity {
+    let name = match polarity {
+        Polarity::Npn => NPN_NAME,
+        Polarity::Pnp => PNP_NAME,
+    };
+    world.create_now()
+        .with(CircuitElement { display_name: name })
+        .with(Nodes::new(3))
+        .with(Bjt::new(polarity))
+        .with(DerivedCurrent::default())
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_active() {
+        let bjt = Bjt::default();
+
+        let (collector, base) = bjt.currents(0.7, -5.0);
+
+        assert!(collector > 1e-5);
+        assert_approx_eq!(collector / base, DEFAULT_FORWARD_BETA, 1e-6);
+    }
+
+    #[test]
+    fn pnp_is_mirrored() {
+        let npn = Bjt::default();
+        let pnp = Bjt::new(Polarity::Pnp);
+
+        let (npn_collector, npn_base) = npn.currents(0.65, -2.0);
+        let (pnp_collector, pnp_base) = pnp.currents(-0.65, 2.0);
+
+        assert_eq!(pnp_collector, -npn_collector);
+        assert_eq!(pnp_base, -npn_base);
+    }
+
+    #[test]
+    fn early_effect() {
+        let bjt = Bjt { early_voltage: 50.0, ..Bjt::default() };
+
+        let (low, _) = bjt.currents(0.65, -1.0);
+        let (high, _) = bjt.currents(0.65, -11.0);
+
+        assert_approx_eq!(high / low, 61.0 / 51.0, 1e-6);
+    }
+
+    #[test]
+    fn conductances_are_derivatives_of_currents() {
+        let bjt = Bjt { early_voltage: 50.0, ..Bjt::default() };
+        let (vbe, vbc) = (0.65, -3.0);
+        let dv = 1e-6;
+
+        let small_signal = bjt.small_signal(vbe, vbc);
+        let be_plus = bjt.small_signal(vbe + dv, vbc);
+        let be_minus = bjt.small_signal(vbe - dv, vbc);
+        let bc_plus = bjt.small_signal(vbe, vbc + dv);
+        let bc_minus = bjt.small_signal(vbe, vbc - dv);
+
+        let numerical = |plus: f64, minus: f64| (plus - minus) / (2.0 * dv);
+        let dic_dvbe = numerical(be_plus.collector_current, be_minus.collector_current);
+        let dic_dvbc = numerical(bc_plus.collector_current, bc_minus.collector_current);
+        let dib_dvbe = numerical(be_plus.base_current, be_minus.base_current);
+
+        assert_approx_eq!(small_signal.collector_conductances.0, dic_dvbe, dic_dvbe * 1e-4);
+        assert_approx_eq!(small_signal.collector_conductances.1,
+                          dic_dvbc,
+                          dic_dvbc.abs() * 1e-4);
+        assert_approx_eq!(small_signal.base_conductances.0, dib_dvbe, dib_dvbe * 1e-4);
+    }
+}
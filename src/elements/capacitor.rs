@@ -1,6 +1,7 @@
 use specs;
 use elements::resistor::Resistor;
 use elements::current_source::CurrentSource;
+use elements::voltage_source::VoltageSource;
 use elements::CircuitElement;
 use elements::Nodes;
 use elements::DerivedCurrent;
@@ -25,14 +26,29 @@ pub enum IntegrationMethod {
 pub struct Capacitor {
     pub capacitance: f64,
 
+    // Norton companion model uses the resistor and current source, Thevenin
+    // uses the resistor and voltage source
     pub resistor: Resistor,
     pub current_source: CurrentSource,
+    pub voltage_source: VoltageSource,
 
     pub node_indexes: (usize, usize),
 
     pub companion_model: CompanionModel,
     pub integration_method: IntegrationMethod,
 }
+impl Capacitor {
+    // Whether the companion model needs its own voltage source branch in the
+    // circuit equation. Forward Euler has no conductance term, so is always
+    // modelled as a voltage source.
+    pub fn uses_branch(&self) -> bool {
+        match (self.companion_model, self.integration_method) {
+            (CompanionModel::Thevenin, _) |
+            (_, IntegrationMethod::ForwardEuler) => true,
+            _ => false,
+        }
+    }
+}
 impl Default for Capacitor {
     fn default() -> Self {
         Capacitor {
@@ -40,10 +56,10 @@ impl Default for Capacitor {
 
             resistor: Resistor::default(),
             current_source: CurrentSource::default(),
+            voltage_source: VoltageSource::zero(),
 
             node_indexes: (0, 1),
 
-            // not used, but interesting to know
             companion_model: CompanionModel::Norton,
             integration_method: IntegrationMethod::Trapezoidal,
         }
@@ -53,6 +69,37 @@ impl specs::Component for Capacitor {
     type Storage = specs::HashMapStorage<Capacitor>;
 }
 
+// A capacitance between two nodes of another element, e.g. a transistor's
+// junction capacitances.
+//
+// Unlike a `Capacitor` it has no branch of its own in the circuit equation,
+// so is always stamped as a trapezoidal Norton companion model.
+#[derive(Debug, Clone, Copy)]
+pub struct InternalCapacitance {
+    pub capacitance: f64,
+    // By position in the element's `Nodes`
+    pub node_indexes: (usize, usize),
+
+    // The companion model for the current timestep
+    pub conductance: f64,
+    pub companion_current: f64,
+    // Current from the first node to the second at the end of the previous
+    // timestep
+    pub current: f64,
+}
+impl InternalCapacitance {
+    pub fn new(capacitance: f64, node_indexes: (usize, usize)) -> Self {
+        InternalCapacitance {
+            capacitance: capacitance,
+            node_indexes: node_indexes,
+
+            conductance: 0.0,
+            companion_current: 0.0,
+            current: 0.0,
+        }
+    }
+}
+
 pub fn create(world: &mut specs::World) -> specs::Entity {
     world.create_now()
         .with(CircuitElement { display_name: NAME })
@@ -0,0 +1,42 @@
+use specs;
+use elements::CircuitElement;
+use elements::Nodes;
+
+pub const NAME: &'static str = "Current-controlled current source";
+pub const DEFAULT_GAIN: f64 = 1.0;
+
+// A current source, from its first node to its second, of `gain` times the
+// current through the `control` voltage source (e.g. a 0V source used as an
+// ammeter).
+//
+// The circuit can't be solved while there's no control.
+#[derive(Debug, Clone, Copy)]
+pub struct Cccs {
+    pub gain: f64,
+    pub control: Option<specs::Entity>,
+
+    pub node_indexes: (usize, usize),
+}
+
+impl Default for Cccs {
+    fn default() -> Self {
+        Cccs {
+            gain: DEFAULT_GAIN,
+            control: None,
+
+            node_indexes: (0, 1),
+        }
+    }
+}
+
+impl specs::Component for Cccs {
+    type Storage = specs::HashMapStorage<Cccs>;
+}
+
+pub fn create(world: &mut specs::World) -> specs::Entity {
+    world.create_now()
+        .with(CircuitElement { display_name: NAME })
+        .with(Nodes::new(2))
+        .with(Cccs::default())
+        .build()
+}
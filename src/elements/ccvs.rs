@@ -0,0 +1,46 @@
+use specs;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::CalculatedCurrent;
+
+pub const NAME: &'static str = "Current-controlled voltage source";
+pub const DEFAULT_TRANSRESISTANCE: f64 = 1e3;
+
+// A voltage source, from its first node to its second, of `transresistance`
+// times the current through the `control` voltage source (e.g. a 0V source
+// used as an ammeter).
+//
+// The circuit can't be solved while there's no control.
+#[derive(Debug, Clone, Copy)]
+pub struct Ccvs {
+    pub transresistance: f64,
+    pub control: Option<specs::Entity>,
+    pub index: usize,
+
+    pub node_indexes: (usize, usize),
+}
+
+impl Default for Ccvs {
+    fn default() -> Self {
+        Ccvs {
+            transresistance: DEFAULT_TRANSRESISTANCE,
+            control: None,
+            index: 0,
+
+            node_indexes: (0, 1),
+        }
+    }
+}
+
+impl specs::Component for Ccvs {
+    type Storage = specs::HashMapStorage<Ccvs>;
+}
+
+pub fn create(world: &mut specs::World) -> specs::Entity {
+    world.create_now()
+        .with(CircuitElement { display_name: NAME })
+        .with(Nodes::new(2))
+        .with(Ccvs::default())
+        .with(CalculatedCurrent::default())
+        .build()
+}
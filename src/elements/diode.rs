@@ -0,0 +1,150 @@
+use specs;
+use elements::resistor::Resistor;
+use elements::current_source::CurrentSource;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::DerivedCurrent;
+
+pub const NAME: &'static str = "Diode";
+pub const DEFAULT_SATURATION_CURRENT: f64 = 1e-14;
+pub const DEFAULT_EMISSION_COEFFICIENT: f64 = 1.0;
+
+// kT/q at 300K
+pub const THERMAL_VOLTAGE: f64 = 0.025852;
+
+// Shockley diode, conducting from its first node (anode) to its second
+// (cathode).
+//
+// Linearised around `junction_voltage` as a Norton equivalent on every
+// Newton-Raphson iteration.
+#[derive(Debug, Clone, Copy)]
+pub struct Diode {
+    pub saturation_current: f64,
+    pub emission_coefficient: f64,
+
+    pub resistor: Resistor,
+    pub current_source: CurrentSource,
+
+    pub junction_voltage: f64,
+
+    pub node_indexes: (usize, usize),
+}
+impl Diode {
+    fn scaled_thermal_voltage(&self) -> f64 {
+        self.emission_coefficient * THERMAL_VOLTAGE
+    }
+
+    pub fn current(&self, voltage: f64) -> f64 {
+        self.saturation_current * ((voltage / self.scaled_thermal_voltage()).exp() - 1.0)
+    }
+
+    // dI/dV
+    pub fn conductance(&self, voltage: f64) -> f64 {
+        let vt = self.scaled_thermal_voltage();
+        (self.saturation_current / vt) * (voltage / vt).exp()
+    }
+
+    // Voltage above which the exponential makes Newton-Raphson overshoot.
+    pub fn critical_voltage(&self) -> f64 {
+        let vt = self.scaled_thermal_voltage();
+        vt * (vt / (2f64.sqrt() * self.saturation_current)).ln()
+    }
+
+    // Limit the change in junction voltage between iterations.
+    pub fn limit_voltage(&self, new_voltage: f64, old_voltage: f64) -> f64 {
+        limit_junction_voltage(new_voltage,
+                               old_voltage,
+                               self.scaled_thermal_voltage(),
+                               self.critical_voltage())
+    }
+}
+
+// Limit the change in a pn junction's voltage between Newton-Raphson
+// iterations, as done by SPICE's `pnjlim`.
+pub fn limit_junction_voltage(new_voltage: f64,
+                              old_voltage: f64,
+                              thermal_voltage: f64,
+                              critical_voltage: f64)
+                              -> f64 {
+    let vt = thermal_voltage;
+
+    if new_voltage > critical_voltage && (new_voltage - old_voltage).abs() > 2.0 * vt {
+        if old_voltage > 0.0 {
+            let arg = 1.0 + (new_voltage - old_voltage) / vt;
+            if arg > 0.0 {
+                old_voltage + vt * arg.ln()
+            } else {
+                critical_voltage
+            }
+        } else {
+            vt * (new_voltage / vt).ln()
+        }
+    } else {
+        new_voltage
+    }
+}
+impl Default for Diode {
+    fn default() -> Self {
+        Diode {
+            saturation_current: DEFAULT_SATURATION_CURRENT,
+            emission_coefficient: DEFAULT_EMISSION_COEFFICIENT,
+
+            resistor: Resistor::default(),
+            current_source: CurrentSource::default(),
+
+            junction_voltage: 0.0,
+
+            node_indexes: (0, 1),
+        }
+    }
+}
+impl specs::Component for Diode {
+    type Storage = specs::HashMapStorage<Diode>;
+}
+
+pub fn create(world: &mut specs::World) -> specs::Entity {
+    world.create_now()
+        .with(CircuitElement { display_name: NAME })
+        .with(Nodes::new(2))
+        .with(Diode::default())
+        .with(DerivedCurrent::default())
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current() {
+        let diode = Diode::default();
+
+        assert_eq!(diode.current(0.0), 0.0);
+        assert_approx_eq!(diode.current(-1.0), -DEFAULT_SATURATION_CURRENT);
+        assert!(diode.current(0.7) > 1e-3);
+    }
+
+    #[test]
+    fn conductance_is_derivative_of_current() {
+        let diode = Diode::default();
+        let v = 0.6;
+        let dv = 1e-6;
+
+        let numerical = (diode.current(v + dv) - diode.current(v - dv)) / (2.0 * dv);
+
+        assert_approx_eq!(diode.conductance(v), numerical, numerical * 1e-4);
+    }
+
+    #[test]
+    fn limit_voltage() {
+        let diode = Diode::default();
+
+        // small steps are not limited
+        assert_eq!(diode.limit_voltage(0.65, 0.64), 0.65);
+        // neither is reverse bias
+        assert_eq!(diode.limit_voltage(-10.0, 0.6), -10.0);
+        // large forward steps are
+        let limited = diode.limit_voltage(5.0, 0.6);
+        assert!(limited > 0.6 && limited < 1.0);
+    }
+}
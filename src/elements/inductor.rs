@@ -0,0 +1,44 @@
+use specs;
+use elements::resistor::Resistor;
+use elements::current_source::CurrentSource;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::DerivedCurrent;
+
+pub const NAME: &'static str = "Inductor";
+pub const DEFAULT_INDUCTANCE: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Inductor {
+    pub inductance: f64,
+
+    // Norton companion model
+    pub resistor: Resistor,
+    pub current_source: CurrentSource,
+
+    pub node_indexes: (usize, usize),
+}
+impl Default for Inductor {
+    fn default() -> Self {
+        Inductor {
+            inductance: DEFAULT_INDUCTANCE,
+
+            resistor: Resistor::default(),
+            current_source: CurrentSource::default(),
+
+            node_indexes: (0, 1),
+        }
+    }
+}
+impl specs::Component for Inductor {
+    type Storage = specs::HashMapStorage<Inductor>;
+}
+
+pub fn create(world: &mut specs::World) -> specs::Entity {
+    world.create_now()
+        .with(CircuitElement { display_name: NAME })
+        .with(Nodes::new(2))
+        .with(Inductor::default())
+        .with(DerivedCurrent::default())
+        .build()
+}
@@ -1,11 +1,23 @@
 use specs;
 
 pub mod capacitor;
+pub mod inductor;
+pub mod diode;
+pub mod bjt;
+pub mod mosfet;
 pub mod resistor;
 pub mod voltage_source;
 pub mod current_source;
 pub mod wire;
 pub mod ground;
+pub mod waveform;
+pub mod ac_source;
+pub mod vcvs;
+pub mod vccs;
+pub mod ccvs;
+pub mod cccs;
+pub mod opamp;
+pub mod switch;
 
 #[derive(Debug, Clone, Copy)]
 pub struct CircuitElement {
@@ -49,6 +61,29 @@ impl specs::Component for Nodes {
     type Storage = specs::VecStorage<Nodes>;
 }
 
+// A point on the circuit diagram grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+impl Position {
+    pub fn new(x: i32, y: i32) -> Self {
+        Position { x: x, y: y }
+    }
+}
+
+// Positions of an element's connectors, in the same order as its `Nodes`.
+//
+// Used to assign node indexes: connectors at the same position are
+// connected. Any nodes without a connector (e.g. the reference side of
+// `Ground`) are the reference node.
+#[derive(Debug)]
+pub struct Terminals(pub Vec<Position>);
+impl specs::Component for Terminals {
+    type Storage = specs::VecStorage<Terminals>;
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct CalculatedCurrent(pub f64);
 impl specs::Component for CalculatedCurrent {
@@ -72,3 +107,28 @@ impl Default for DerivedCurrent {
         DerivedCurrent(0f64)
     }
 }
+
+// Register the components of every element with the world.
+pub fn register(world: &mut specs::World) {
+    world.register::<CircuitElement>();
+    world.register::<Nodes>();
+    world.register::<Terminals>();
+    world.register::<CalculatedCurrent>();
+    world.register::<DerivedCurrent>();
+    world.register::<voltage_source::VoltageSource>();
+    world.register::<current_source::CurrentSource>();
+    world.register::<resistor::Resistor>();
+    world.register::<capacitor::Capacitor>();
+    world.register::<inductor::Inductor>();
+    world.register::<diode::Diode>();
+    world.register::<bjt::Bjt>();
+    world.register::<mosfet::Mosfet>();
+    world.register::<switch::Switch>();
+    world.register::<vcvs::Vcvs>();
+    world.register::<vccs::Vccs>();
+    world.register::<ccvs::Ccvs>();
+    world.register::<cccs::Cccs>();
+    world.register::<opamp::OpAmp>();
+    world.register::<waveform::Waveform>();
+    world.register::<ac_source::AcSource>();
+}
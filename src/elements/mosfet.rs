@@ -0,0 +1,471 @@
+use specs;
+use elements::capacitor::InternalCapacitance;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::DerivedCurrent;
+
+pub const NMOS_NAME: &'static str = "N-channel MOSFET";
+pub const PMOS_NAME: &'static str = "P-channel MOSFET";
+
+// As in SPICE
+pub const DEFAULT_THRESHOLD_VOLTAGE: f64 = 0.0;
+pub const DEFAULT_TRANSCONDUCTANCE: f64 = 2e-5;
+pub const DEFAULT_BODY_EFFECT: f64 = 0.0;
+pub const DEFAULT_SURFACE_POTENTIAL: f64 = 0.6;
+pub const DEFAULT_CHANNEL_LENGTH_MODULATION: f64 = 0.0;
+pub const DEFAULT_WIDTH: f64 = 100e-6;
+pub const DEFAULT_LENGTH: f64 = 100e-6;
+pub const DEFAULT_GATE_CAPACITANCE: f64 = 0.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Polarity {
+    Nmos,
+    Pmos,
+}
+impl Polarity {
+    // Terminal voltages and currents are negated for PMOS, so the same
+    // equations work for both.
+    pub fn sign(&self) -> f64 {
+        match *self {
+            Polarity::Nmos => 1.0,
+            Polarity::Pmos => -1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Cutoff,
+    Triode,
+    Saturation,
+}
+
+// The drain current at a bias point, and its derivatives with respect to the
+// terminal voltages, as for an NMOS.
+#[derive(Debug, Clone, Copy)]
+pub struct SmallSignal {
+    pub region: Region,
+    pub drain_current: f64,
+    // dId/dVgs
+    pub transconductance: f64,
+    // dId/dVds
+    pub output_conductance: f64,
+    // dId/dVbs
+    pub body_transconductance: f64,
+}
+
+// MOSFET, with its drain as its first node, its gate as its second, its
+// source as its third and its body as its fourth.
+//
+// Modelled with SPICE's level 1 (Shichman-Hodges) equations, plus constant
+// gate capacitances. The drain and source are interchangeable: when the
+// drain is below the source they swap roles. Linearised around
+// `gate_source_voltage`, `drain_source_voltage` and `body_source_voltage` on
+// every Newton-Raphson iteration.
+#[derive(Debug, Clone, Copy)]
+pub struct Mosfet {
+    pub polarity: Polarity,
+
+    // VTO, negative for an enhancement mode PMOS as in SPICE
+    pub threshold_voltage: f64,
+    // KP
+    pub transconductance: f64,
+    // GAMMA
+    pub body_effect: f64,
+    // PHI
+    pub surface_potential: f64,
+    // LAMBDA
+    pub channel_length_modulation: f64,
+    // W and L
+    pub width: f64,
+    pub length: f64,
+
+    // Both from the gate
+    pub gate_source_capacitance: InternalCapacitance,
+    pub gate_drain_capacitance: InternalCapacitance,
+
+    // Terminal voltages (negated for PMOS) last linearised around
+    pub gate_source_voltage: f64,
+    pub drain_source_voltage: f64,
+    pub body_source_voltage: f64,
+
+    // (drain, gate, source, body)
+    pub node_indexes: (usize, usize, usize, usize),
+}
+impl Mosfet {
+    pub fn new(polarity: Polarity) -> Self {
+        let node_indexes = (0, 1, 2, 3);
+
+        Mosfet {
+            polarity: polarity,
+
+            threshold_voltage: DEFAULT_THRESHOLD_VOLTAGE,
+            transconductance: DEFAULT_TRANSCONDUCTANCE,
+            body_effect: DEFAULT_BODY_EFFECT,
+            surface_potential: DEFAULT_SURFACE_POTENTIAL,
+            channel_length_modulation: DEFAULT_CHANNEL_LENGTH_MODULATION,
+            width: DEFAULT_WIDTH,
+            length: DEFAULT_LENGTH,
+
+            gate_source_capacitance: InternalCapacitance::new(DEFAULT_GATE_CAPACITANCE,
+                                                              (node_indexes.1, node_indexes.2)),
+            gate_drain_capacitance: InternalCapacitance::new(DEFAULT_GATE_CAPACITANCE,
+                                                             (node_indexes.1, node_indexes.0)),
+
+            gate_source_voltage: 0.0,
+            drain_source_voltage: 0.0,
+            body_source_voltage: 0.0,
+
+            node_indexes: node_indexes,
+        }
+    }
+
+    // The threshold voltage (as for an NMOS) at the given body-source
+    // voltage, and its derivative with respect to it.
+    pub fn threshold(&self, body_source_voltage: f64) -> (f64, f64) {
+        let phi = self.surface_potential;
+        let sqrt_phi = phi.sqrt();
+
+        // forward biasing the body is linearised, as in SPICE
+        let (root, root_derivative) = if body_source_voltage <= 0.0 {
+            let root = (phi - body_source_voltage).sqrt();
+            (root, -0.5 / root)
+        } else {
+            let root = sqrt_phi - body_source_voltage / (2.0 * sqrt_phi);
+            if root > 0.0 {
+                (root, -0.5 / sqrt_phi)
+            } else {
+                (0.0, 0.0)
+            }
+        };
+
+        (self.polarity.sign() * self.threshold_voltage +
+         self.body_effect * (root - sqrt_phi),
+         self.body_effect * root_derivative)
+    }
+
+    // The drain current, and its derivatives, at the given terminal voltages
+    // (negated for PMOS).
+    pub fn small_signal(&self,
+                        gate_source_voltage: f64,
+                        drain_source_voltage: f64,
+                        body_source_voltage: f64)
+                        -> SmallSignal {
+        if drain_source_voltage >= 0.0 {
+            return self.forward(gate_source_voltage, drain_source_voltage, body_source_voltage);
+        }
+
+        // the source is acting as the drain
+        let reverse = self.forward(gate_source_voltage - drain_source_voltage,
+                                   -drain_source_voltage,
+                                   body_source_voltage - drain_source_voltage);
+        SmallSignal {
+            region: reverse.region,
+            drain_current: -reverse.drain_current,
+            transconductance: -reverse.transconductance,
+            output_conductance: reverse.transconductance + reverse.output_conductance +
+                                reverse.body_transconductance,
+            body_transconductance: -reverse.body_transconductance,
+        }
+    }
+
+    // As `small_signal`, for a non-negative drain-source voltage.
+    fn forward(&self,
+               gate_source_voltage: f64,
+               drain_source_voltage: f64,
+               body_source_voltage: f64)
+               -> SmallSignal {
+        let (threshold, threshold_derivative) = self.threshold(body_source_voltage);
+        let overdrive = gate_source_voltage - threshold;
+        let vds = drain_source_voltage;
+
+        if overdrive <= 0.0 {
+            return SmallSignal {
+                region: Region::Cutoff,
+                drain_current: 0.0,
+                transconductance: 0.0,
+                output_conductance: 0.0,
+                body_transconductance: 0.0,
+            };
+        }
+
+        let beta = self.transconductance * self.width / self.length;
+        let lambda = self.channel_length_modulation;
+        let modulation = 1.0 + lambda * vds;
+
+        let (region, current, transconductance, output_conductance) = if vds < overdrive {
+            let current = beta * vds * (overdrive - 0.5 * vds);
+            (Region::Triode,
+             current * modulation,
+             beta * vds * modulation,
+             beta * (overdrive - vds) * modulation + current * lambda)
+        } else {
+            let current = 0.5 * beta * overdrive * overdrive;
+            (Region::Saturation,
+             current * modulation,
+             beta * overdrive * modulation,
+             current * lambda)
+        };
+
+        SmallSignal {
+            region: region,
+            drain_current: current,
+            transconductance: transconductance,
+            output_conductance: output_conductance,
+            body_transconductance: -transconductance * threshold_derivative,
+        }
+    }
+
+    // The current into the drain given V(gate) - V(source), V(drain) -
+    // V(source) and V(body) - V(source), not including the gate capacitances.
+    pub fn current(&self,
+                   gate_source_voltage: f64,
+                   drain_source_voltage: f64,
+                   body_source_voltage: f64)
+                   -> f64 {
+        let sign = self.polarity.sign();
+        let small_signal = self.small_signal(sign * gate_source_voltage,
+                                             sign * drain_source_voltage,
+                                             sign * body_source_voltage);
+        sign * small_signal.drain_current
+    }
+
+    // Limit the change in the gate-source and drain-source voltages (negated
+    // for PMOS) since the last iteration, as done by SPICE.
+    pub fn limit_voltages(&self,
+                          gate_source_voltage: f64,
+                          drain_source_voltage: f64)
+                          -> (f64, f64) {
+        let (threshold, _) = self.threshold(self.body_source_voltage);
+        let old_gate_source = self.gate_source_voltage;
+        let old_drain_source = self.drain_source_voltage;
+        let old_gate_drain = old_gate_source - old_drain_source;
+
+        if old_drain_source >= 0.0 {
+            let gate_source = limit_gate_voltage(gate_source_voltage, old_gate_source, threshold);
+            let drain_source = limit_drain_voltage(gate_source - gate_source_voltage +
+                                                   drain_source_voltage,
+                                                   old_drain_source);
+            (gate_source, drain_source)
+        } else {
+            let gate_drain = limit_gate_voltage(gate_source_voltage - drain_source_voltage,
+                                                old_gate_drain,
+                                                threshold);
+            let drain_source = -limit_drain_voltage(gate_drain - gate_source_voltage,
+                                                    -old_drain_source);
+            (gate_drain + drain_source, drain_source)
+        }
+    }
+}
+impl Default for Mosfet {
+    fn default() -> Self {
+        Mosfet::new(Polarity::Nmos)
+    }
+}
+impl specs::Component for Mosfet {
+    type Storage = specs::HashMapStorage<Mosfet>;
+}
+
+// Limit the change in a gate voltage between Newton-Raphson iterations, so it
+// doesn't jump far across the threshold, as done by SPICE's `fetlim`.
+pub fn limit_gate_voltage(new_voltage: f64, old_voltage: f64, threshold: f64) -> f64 {
+    let high_step = (2.0 * (old_voltage - threshold)).abs() + 2.0;
+    let low_step = (old_voltage - threshold).abs() + 1.0;
+    let fully_on = threshold + 3.5;
+    let step = new_voltage - old_voltage;
+
+    if old_voltage >= threshold {
+        if old_voltage >= fully_on {
+            if step <= 0.0 {
+                // turning off
+                if new_voltage >= fully_on {
+                    if -step > low_step {
+                        return old_voltage - low_step;
+                    }
+                    new_voltage
+                } else {
+                    new_voltage.max(threshold + 2.0)
+                }
+            } else if step >= high_step {
+                old_voltage + high_step
+            } else {
+                new_voltage
+            }
+        } else if step <= 0.0 {
+            new_voltage.max(threshold - 0.5)
+        } else {
+            new_voltage.min(threshold + 4.0)
+        }
+    } else if step <= 0.0 {
+        if -step > high_step {
+            old_voltage - high_step
+        } else {
+            new_voltage
+        }
+    } else if new_voltage <= threshold + 0.5 {
+        if step > low_step {
+            old_voltage + low_step
+        } else {
+            new_voltage
+        }
+    } else {
+        threshold + 0.5
+    }
+}
+
+// Limit the change in a (non-negative) drain-source voltage between
+// Newton-Raphson iterations, as done by SPICE's `limvds`.
+pub fn limit_drain_voltage(new_voltage: f64, old_voltage: f64) -> f64 {
+    if old_voltage >= 3.5 {
+        if new_voltage > old_voltage {
+            new_voltage.min(3.0 * old_voltage + 2.0)
+        } else if new_voltage < 3.5 {
+            new_voltage.max(2.0)
+        } else {
+            new_voltage
+        }
+    } else if new_voltage > old_voltage {
+        new_voltage.min(4.0)
+    } else {
+        new_voltage.max(-0.5)
+    }
+}
+
+pub fn create(world: &mut specs::World, polarity: Polarity) -> specs::Entity {
+    let name = match polarity {
+        Polarity::Nmos => NMOS_NAME,
+        Polarity::Pmos => PMOS_NAME,
+    };
+    world.create_now()
+        .with(CircuitElement { display_name: name })
+        .with(Nodes::new(4))
+        .with(Mosfet::new(polarity))
+        .with(DerivedCurrent::default())
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> Mosfet {
+        Mosfet {
+            threshold_voltage: 1.0,
+            transconductance: 1e-4,
+            width: 2.0,
+            length: 1.0,
+            ..Mosfet::default()
+        }
+    }
+
+    #[test]
+    fn regions() {
+        let mosfet = model();
+
+        assert_eq!(mosfet.small_signal(0.5, 5.0, 0.0).region, Region::Cutoff);
+        assert_eq!(mosfet.current(0.5, 5.0, 0.0), 0.0);
+
+        // beta * (Vgs - Vt)^2 / 2
+        assert_eq!(mosfet.small_signal(3.0, 5.0, 0.0).region, Region::Saturation);
+        assert_approx_eq!(mosfet.current(3.0, 5.0, 0.0), 2e-4 * 4.0 / 2.0);
+
+        // beta * Vds * (Vgs - Vt - Vds / 2)
+        assert_eq!(mosfet.small_signal(3.0, 1.0, 0.0).region, Region::Triode);
+        assert_approx_eq!(mosfet.current(3.0, 1.0, 0.0), 2e-4 * 1.0 * 1.5);
+    }
+
+    #[test]
+    fn drain_and_source_are_interchangeable() {
+        let mosfet = model();
+
+        // the gate 3V above, and the body tied to, the lower terminal
+        let forward = mosfet.current(3.0, 1.0, 0.0);
+        let reverse = mosfet.current(2.0, -1.0, -1.0);
+
+        assert_approx_eq!(reverse, -forward);
+    }
+
+    #[test]
+    fn pmos_is_mirrored() {
+        let nmos = model();
+        let pmos = Mosfet {
+            polarity: Polarity::Pmos,
+            threshold_voltage: -1.0,
+            ..model()
+        };
+
+        assert_eq!(pmos.current(-3.0, -1.0, 0.0), -nmos.current(3.0, 1.0, 0.0));
+        assert_eq!(pmos.current(-3.0, -5.0, 0.0), -nmos.current(3.0, 5.0, 0.0));
+    }
+
+    #[test]
+    fn channel_length_modulation() {
+        let mosfet = Mosfet { channel_length_modulation: 0.02, ..model() };
+
+        let low = mosfet.current(3.0, 5.0, 0.0);
+        let high = mosfet.current(3.0, 10.0, 0.0);
+
+        assert_approx_eq!(high / low, 1.2 / 1.1);
+    }
+
+    #[test]
+    fn body_effect() {
+        let mosfet = Mosfet { body_effect: 0.5, ..model() };
+
+        assert_eq!(mosfet.threshold(0.0).0, 1.0);
+        let (threshold, _) = mosfet.threshold(-2.0);
+        assert_approx_eq!(threshold, 1.0 + 0.5 * (2.6f64.sqrt() - 0.6f64.sqrt()));
+        assert!(mosfet.current(3.0, 5.0, -2.0) < mosfet.current(3.0, 5.0, 0.0));
+    }
+
+    #[test]
+    fn conductances_are_derivatives_of_current() {
+        let mosfet = Mosfet {
+            body_effect: 0.5,
+            channel_length_modulation: 0.02,
+            ..model()
+        };
+        let dv = 1e-6;
+
+        // saturation, triode, and both reversed
+        for &(vgs, vds, vbs) in &[(3.0, 5.0, -1.0),
+                                  (3.0, 1.0, -1.0),
+                                  (-2.0, -5.0, -6.0),
+                                  (2.0, -1.0, -2.0)] {
+            let small_signal = mosfet.small_signal(vgs, vds, vbs);
+
+            let numerical = |plus: SmallSignal, minus: SmallSignal| {
+                (plus.drain_current - minus.drain_current) / (2.0 * dv)
+            };
+            let gm = numerical(mosfet.small_signal(vgs + dv, vds, vbs),
+                               mosfet.small_signal(vgs - dv, vds, vbs));
+            let gds = numerical(mosfet.small_signal(vgs, vds + dv, vbs),
+                                mosfet.small_signal(vgs, vds - dv, vbs));
+            let gmbs = numerical(mosfet.small_signal(vgs, vds, vbs + dv),
+                                 mosfet.small_signal(vgs, vds, vbs - dv));
+
+            assert_approx_eq!(small_signal.transconductance, gm, 1e-8);
+            assert_approx_eq!(small_signal.output_conductance, gds, 1e-8);
+            assert_approx_eq!(small_signal.body_transconductance, gmbs, 1e-8);
+        }
+    }
+
+    #[test]
+    fn limit_voltages() {
+        let mosfet = model();
+
+        // small steps are not limited
+        assert_eq!(mosfet.limit_voltages(0.2, 0.5), (0.2, 0.5));
+        // large steps from off are
+        let (gate_source, drain_source) = mosfet.limit_voltages(10.0, 20.0);
+        assert_eq!(gate_source, 1.5);
+        assert_eq!(drain_source, 4.0);
+
+        // and the same with the drain and source swapped
+        let reversed = Mosfet { drain_source_voltage: -1.0, ..model() };
+        let (gate_source, drain_source) = reversed.limit_voltages(0.2, -1.1);
+        assert_approx_eq!(gate_source, 0.2);
+        assert_approx_eq!(drain_source, -1.1);
+        assert_eq!(reversed.limit_voltages(0.0, -20.0), (1.0, -4.0));
+    }
+}
@@ -0,0 +1,174 @@
+use std::f64::consts::PI;
+use specs;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::CalculatedCurrent;
+
+pub const NAME: &'static str = "Operational amplifier";
+
+// Roughly a 741
+pub const DEFAULT_GAIN: f64 = 2e5;
+pub const DEFAULT_INPUT_RESISTANCE: f64 = 2e6;
+pub const DEFAULT_OUTPUT_RESISTANCE: f64 = 75.0;
+pub const DEFAULT_GAIN_BANDWIDTH: f64 = 1e6;
+pub const DEFAULT_POSITIVE_RAIL: f64 = 15.0;
+pub const DEFAULT_NEGATIVE_RAIL: f64 = -15.0;
+
+// A finite-gain op-amp: a resistance between the inputs, and a voltage source
+// in series with a resistance at the output.
+//
+// The source follows the differential input through a single pole, so the
+// gain falls off to unity at `gain_bandwidth` (in Hz), and is clamped to the
+// rails.
+#[derive(Debug, Clone, Copy)]
+pub struct MacroModel {
+    // Open-loop DC gain
+    pub gain: f64,
+    pub input_resistance: f64,
+    pub output_resistance: f64,
+    pub gain_bandwidth: f64,
+    pub positive_rail: f64,
+    pub negative_rail: f64,
+}
+impl MacroModel {
+    // Time constant of the dominant pole.
+    pub fn time_constant(&self) -> f64 {
+        self.gain / (2.0 * PI * self.gain_bandwidth)
+    }
+
+    // The (gain, offset) of the source, i.e. its voltage is `offset` plus
+    // `gain` times the differential input.
+    //
+    // At DC (`timestep` is `None`) this is just the open-loop gain. Otherwise
+    // the pole is integrated with backward Euler from the source's voltage at
+    // the end of the previous timestep.
+    pub fn transfer(&self, previous_voltage: f64, timestep: Option<f64>) -> (f64, f64) {
+        match timestep {
+            None => (self.gain, 0.0),
+            Some(timestep) => {
+                let tau = self.time_constant();
+                let gain = self.gain * timestep / (timestep + tau);
+                let offset = previous_voltage * tau / (timestep + tau);
+                (gain, offset)
+            }
+        }
+    }
+
+    // The rail the source is clamped to at the given voltage, if any.
+    pub fn saturation(&self, voltage: f64) -> Option<f64> {
+        if voltage > self.positive_rail {
+            Some(self.positive_rail)
+        } else if voltage < self.negative_rail {
+            Some(self.negative_rail)
+        } else {
+            None
+        }
+    }
+
+    pub fn clamp(&self, voltage: f64) -> f64 {
+        self.saturation(voltage).unwrap_or(voltage)
+    }
+}
+impl Default for MacroModel {
+    fn default() -> Self {
+        MacroModel {
+            gain: DEFAULT_GAIN,
+            input_resistance: DEFAULT_INPUT_RESISTANCE,
+            output_resistance: DEFAULT_OUTPUT_RESISTANCE,
+            gain_bandwidth: DEFAULT_GAIN_BANDWIDTH,
+            positive_rail: DEFAULT_POSITIVE_RAIL,
+            negative_rail: DEFAULT_NEGATIVE_RAIL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Model {
+    // Infinite gain and bandwidth, no input current and no output resistance
+    // (a nullor): the output takes whatever voltage makes the inputs equal,
+    // so it needs negative feedback.
+    Ideal,
+    Macro(MacroModel),
+}
+
+// An op-amp with its non-inverting input as its first node, its inverting
+// input as its second, and its output (referenced to ground) as its third.
+#[derive(Debug, Clone, Copy)]
+pub struct OpAmp {
+    pub model: Model,
+    pub index: usize,
+
+    // The voltage of the macro model's output source at the end of the
+    // previous timestep, i.e. the state of its pole
+    pub internal_voltage: f64,
+
+    // (non-inverting, inverting)
+    pub input_node_indexes: (usize, usize),
+    pub output_node_index: usize,
+}
+impl OpAmp {
+    pub fn new(model: Model) -> Self {
+        OpAmp {
+            model: model,
+            index: 0,
+
+            internal_voltage: 0.0,
+
+            input_node_indexes: (0, 1),
+            output_node_index: 2,
+        }
+    }
+}
+impl Default for OpAmp {
+    fn default() -> Self {
+        OpAmp::new(Model::Ideal)
+    }
+}
+impl specs::Component for OpAmp {
+    type Storage = specs::HashMapStorage<OpAmp>;
+}
+
+pub fn create(world: &mut specs::World) -> specs::Entity {
+    world.create_now()
+        .with(CircuitElement { display_name: NAME })
+        .with(Nodes::new(3))
+        .with(OpAmp::default())
+        .with(CalculatedCurrent::default())
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_at_dc() {
+        let model = MacroModel::default();
+
+        assert_eq!(model.transfer(1.0, None), (DEFAULT_GAIN, 0.0));
+    }
+
+    #[test]
+    fn transfer_settles_to_dc_gain() {
+        let model = MacroModel::default();
+        let timestep = model.time_constant() / 10.0;
+
+        // a step of 10µV at the input
+        let mut voltage = 0.0;
+        for _ in 0..200 {
+            let (gain, offset) = model.transfer(voltage, Some(timestep));
+            voltage = model.clamp(gain * 10e-6 + offset);
+        }
+
+        assert_approx_eq!(voltage, DEFAULT_GAIN * 10e-6, 1e-6);
+    }
+
+    #[test]
+    fn saturation() {
+        let model = MacroModel::default();
+
+        assert_eq!(model.saturation(1.0), None);
+        assert_eq!(model.clamp(20.0), DEFAULT_POSITIVE_RAIL);
+        assert_eq!(model.clamp(-20.0), DEFAULT_NEGATIVE_RAIL);
+    }
+}
@@ -0,0 +1,153 @@
+use specs;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::DerivedCurrent;
+
+pub const SPST_NAME: &'static str = "Switch";
+pub const SPDT_NAME: &'static str = "Changeover switch";
+pub const PUSH_BUTTON_NAME: &'static str = "Push button";
+
+// As in SPICE
+pub const DEFAULT_ON_RESISTANCE: f64 = 1.0;
+pub const DEFAULT_OFF_RESISTANCE: f64 = 1e12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    // Single pole, single throw
+    Spst,
+    // Single pole, double throw
+    Spdt,
+    // An SPST switch which is only closed while it's held down
+    PushButton,
+}
+impl Kind {
+    pub fn num_nodes(&self) -> usize {
+        match *self {
+            Kind::Spst | Kind::PushButton => 2,
+            Kind::Spdt => 3,
+        }
+    }
+}
+
+// A switch, with its common terminal as its first node.
+//
+// An SPST switch (or push button) connects its common terminal to its second
+// node when closed. An SPDT switch connects it to its second node when open,
+// and to its third node when closed. Each contact is a resistance, of
+// `on_resistance` when made and `off_resistance` when broken.
+//
+// Switches can be opened and closed while the circuit is running, so aren't
+// part of the static equation: they are stamped afresh for every step.
+#[derive(Debug, Clone, Copy)]
+pub struct Switch {
+    pub kind: Kind,
+    pub closed: bool,
+    pub on_resistance: f64,
+    pub off_resistance: f64,
+}
+impl Switch {
+    pub fn new(kind: Kind) -> Self {
+        Switch {
+            kind: kind,
+            closed: false,
+            on_resistance: DEFAULT_ON_RESISTANCE,
+            off_resistance: DEFAULT_OFF_RESISTANCE,
+        }
+    }
+
+    // Open a closed switch, or close an open one. Push buttons don't latch,
+    // so are only closed between `press` and `release`.
+    pub fn toggle(&mut self) {
+        if self.kind != Kind::PushButton {
+            self.closed = !self.closed;
+        }
+    }
+
+    // Hold a push button down. Other switches are unaffected.
+    pub fn press(&mut self) {
+        if self.kind == Kind::PushButton {
+            self.closed = true;
+        }
+    }
+
+    // Let go of a push button, which springs back open.
+    pub fn release(&mut self) {
+        if self.kind == Kind::PushButton {
+            self.closed = false;
+        }
+    }
+
+    // Each contact's resistance, from the common terminal to the given node
+    // (by position in `Nodes`).
+    pub fn contacts(&self) -> Vec<(usize, f64)> {
+        let (made, broken) = (self.on_resistance, self.off_resistance);
+        match (self.kind, self.closed) {
+            (Kind::Spst, true) |
+            (Kind::PushButton, true) => vec![(1, made)],
+            (Kind::Spst, false) |
+            (Kind::PushButton, false) => vec![(1, broken)],
+            (Kind::Spdt, true) => vec![(1, broken), (2, made)],
+            (Kind::Spdt, false) => vec![(1, made), (2, broken)],
+        }
+    }
+}
+impl Default for Switch {
+    fn default() -> Self {
+        Switch::new(Kind::Spst)
+    }
+}
+impl specs::Component for Switch {
+    type Storage = specs::HashMapStorage<Switch>;
+}
+
+pub fn create(world: &mut specs::World, kind: Kind) -> specs::Entity {
+    let name = match kind {
+        Kind::Spst => SPST_NAME,
+        Kind::Spdt => SPDT_NAME,
+        Kind::PushButton => PUSH_BUTTON_NAME,
+    };
+    world.create_now()
+        .with(CircuitElement { display_name: name })
+        .with(Nodes::new(kind.num_nodes()))
+        .with(Switch::new(kind))
+        .with(DerivedCurrent::default())
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spst() {
+        let mut switch = Switch::default();
+        assert_eq!(switch.contacts(), vec![(1, DEFAULT_OFF_RESISTANCE)]);
+
+        switch.toggle();
+        assert_eq!(switch.contacts(), vec![(1, DEFAULT_ON_RESISTANCE)]);
+    }
+
+    #[test]
+    fn push_button() {
+        let mut switch = Switch::new(Kind::PushButton);
+        switch.toggle();
+        assert_eq!(switch.contacts(), vec![(1, DEFAULT_OFF_RESISTANCE)]);
+
+        switch.press();
+        assert_eq!(switch.contacts(), vec![(1, DEFAULT_ON_RESISTANCE)]);
+
+        switch.release();
+        assert_eq!(switch.contacts(), vec![(1, DEFAULT_OFF_RESISTANCE)]);
+    }
+
+    #[test]
+    fn spdt() {
+        let mut switch = Switch::new(Kind::Spdt);
+        assert_eq!(switch.contacts(),
+                   vec![(1, DEFAULT_ON_RESISTANCE), (2, DEFAULT_OFF_RESISTANCE)]);
+
+        switch.toggle();
+        assert_eq!(switch.contacts(),
+                   vec![(1, DEFAULT_OFF_RESISTANCE), (2, DEFAULT_ON_RESISTANCE)]);
+    }
+}
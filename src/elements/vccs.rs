@@ -0,0 +1,39 @@
+use specs;
+use elements::CircuitElement;
+use elements::Nodes;
+
+pub const NAME: &'static str = "Voltage-controlled current source";
+pub const DEFAULT_TRANSCONDUCTANCE: f64 = 1e-3;
+
+// A current source, from its first node to its second, of `transconductance`
+// times the voltage from its third node to its fourth.
+#[derive(Debug, Clone, Copy)]
+pub struct Vccs {
+    pub transconductance: f64,
+
+    pub node_indexes: (usize, usize),
+    pub control_node_indexes: (usize, usize),
+}
+
+impl Default for Vccs {
+    fn default() -> Self {
+        Vccs {
+            transconductance: DEFAULT_TRANSCONDUCTANCE,
+
+            node_indexes: (0, 1),
+            control_node_indexes: (2, 3),
+        }
+    }
+}
+
+impl specs::Component for Vccs {
+    type Storage = specs::HashMapStorage<Vccs>;
+}
+
+pub fn create(world: &mut specs::World) -> specs::Entity {
+    world.create_now()
+        .with(CircuitElement { display_name: NAME })
+        .with(Nodes::new(4))
+        .with(Vccs::default())
+        .build()
+}
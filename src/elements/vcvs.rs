@@ -0,0 +1,43 @@
+use specs;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::CalculatedCurrent;
+
+pub const NAME: &'static str = "Voltage-controlled voltage source";
+pub const DEFAULT_GAIN: f64 = 1.0;
+
+// A voltage source, from its first node to its second, of `gain` times the
+// voltage from its third node to its fourth.
+#[derive(Debug, Clone, Copy)]
+pub struct Vcvs {
+    pub gain: f64,
+    pub index: usize,
+
+    pub node_indexes: (usize, usize),
+    pub control_node_indexes: (usize, usize),
+}
+
+impl Default for Vcvs {
+    fn default() -> Self {
+        Vcvs {
+            gain: DEFAULT_GAIN,
+            index: 0,
+
+            node_indexes: (0, 1),
+            control_node_indexes: (2, 3),
+        }
+    }
+}
+
+impl specs::Component for Vcvs {
+    type Storage = specs::HashMapStorage<Vcvs>;
+}
+
+pub fn create(world: &mut specs::World) -> specs::Entity {
+    world.create_now()
+        .with(CircuitElement { display_name: NAME })
+        .with(Nodes::new(4))
+        .with(Vcvs::default())
+        .with(CalculatedCurrent::default())
+        .build()
+}
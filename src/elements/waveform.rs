@@ -0,0 +1,292 @@
+use std::f64::consts::PI;
+use specs;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::CalculatedCurrent;
+use elements::voltage_source::VoltageSource;
+use elements::current_source::CurrentSource;
+
+pub const VOLTAGE_SOURCE_NAME: &'static str = "Time-varying voltage source";
+pub const CURRENT_SOURCE_NAME: &'static str = "Time-varying current source";
+
+// A time-varying value, attached to a `VoltageSource` or `CurrentSource`.
+//
+// Sources with a waveform are not stamped into the static equation, instead
+// they are re-evaluated at every timestep by the solver.
+#[derive(Debug, Clone)]
+pub enum Waveform {
+    // Phase is in radians.
+    Sine {
+        amplitude: f64,
+        frequency: f64,
+        phase: f64,
+        offset: f64,
+    },
+    // Switches between `offset + amplitude` and `offset - amplitude`.
+    // `duty_cycle` is the fraction of the period spent high.
+    Square {
+        amplitude: f64,
+        frequency: f64,
+        duty_cycle: f64,
+        offset: f64,
+    },
+    // Same parameters as a SPICE PULSE source. A period of zero (or infinity)
+    // means the pulse does not repeat.
+    Pulse {
+        initial: f64,
+        pulsed: f64,
+        delay: f64,
+        rise: f64,
+        fall: f64,
+        width: f64,
+        period: f64,
+    },
+    // (time, value) pairs, ordered by time. Values are interpolated linearly
+    // between points, and held constant outside them.
+    PiecewiseLinear(Vec<(f64, f64)>),
+}
+
+impl Waveform {
+    pub fn value(&self, time: f64) -> f64 {
+        match *self {
+            Waveform::Sine { amplitude, frequency, phase, offset } => {
+                offset + amplitude * (2.0 * PI * frequency * time + phase).sin()
+            }
+            Waveform::Square { amplitude, frequency, duty_cycle, offset } => {
+                let position = (time * frequency).fract();
+                if position < duty_cycle {
+                    offset + amplitude
+                } else {
+                    offset - amplitude
+                }
+            }
+            Waveform::Pulse { initial, pulsed, delay, rise, fall, width, period } => {
+                if time < delay {
+                    return initial;
+                }
+                let mut t = time - delay;
+                if period > 0.0 {
+                    t %= period;
+                }
+
+                if t < rise {
+                    initial + (pulsed - initial) * (t / rise)
+                } else if t < rise + width {
+                    pulsed
+                } else if t < rise + width + fall {
+                    pulsed + (initial - pulsed) * ((t - rise - width) / fall)
+                } else {
+                    initial
+                }
+            }
+            Waveform::PiecewiseLinear(ref points) => {
+                match points.iter().position(|&(t, _)| t > time) {
+                    Some(0) => points[0].1,
+                    Some(i) => {
+                        let (t0, v0) = points[i - 1];
+                        let (t1, v1) = points[i];
+                        v0 + (v1 - v0) * ((time - t0) / (t1 - t0))
+                    }
+                    None => points.last().map_or(0.0, |&(_, v)| v),
+                }
+            }
+        }
+    }
+
+    // The first time after `time` where the waveform has a corner or a step,
+    // which a variable timestep should land on exactly.
+    pub fn next_breakpoint(&self, time: f64) -> Option<f64> {
+        match *self {
+            Waveform::Sine { .. } => None,
+            Waveform::Square { frequency, duty_cycle, .. } => {
+                if frequency <= 0.0 {
+                    return None;
+                }
+                let cycle = (time * frequency).floor();
+                let position = time * frequency - cycle;
+                if position < duty_cycle {
+                    Some((cycle + duty_cycle) / frequency)
+                } else {
+                    Some((cycle + 1.0) / frequency)
+                }
+            }
+            Waveform::Pulse { delay, rise, fall, width, period, .. } => {
+                if time < delay {
+                    return Some(delay);
+                }
+                let corners = [rise, rise + width, rise + width + fall];
+                let t = time - delay;
+                let repeats = period > 0.0 && period.is_finite();
+                let start = if repeats {
+                    (t / period).floor() * period
+                } else {
+                    0.0
+                };
+
+                match corners.iter().find(|&&corner| start + corner > t && corner.is_finite()) {
+                    Some(corner) => Some(delay + start + corner),
+                    None if repeats => Some(delay + start + period),
+                    None => None,
+                }
+            }
+            Waveform::PiecewiseLinear(ref points) => {
+                points.iter().map(|&(t, _)| t).find(|&t| t > time)
+            }
+        }
+    }
+}
+
+impl specs::Component for Waveform {
+    type Storage = specs::HashMapStorage<Waveform>;
+}
+
+pub fn create_voltage_source(world: &mut specs::World, waveform: Waveform) -> specs::Entity {
+    world.create_now()
+        .with(CircuitElement { display_name: VOLTAGE_SOURCE_NAME })
+        .with(Nodes::new(2))
+        .with(VoltageSource::zero())
+        .with(CalculatedCurrent::default())
+        .with(waveform)
+        .build()
+}
+
+pub fn create_current_source(world: &mut specs::World, waveform: Waveform) -> specs::Entity {
+    let mut current_source = CurrentSource::default();
+    current_source.current = 0.0;
+
+    world.create_now()
+        .with(CircuitElement { display_name: CURRENT_SOURCE_NAME })
+        .with(Nodes::new(2))
+        .with(current_source)
+        .with(waveform)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine() {
+        let sine = Waveform::Sine {
+            amplitude: 2.0,
+            frequency: 50.0,
+            phase: 0.0,
+            offset: 1.0,
+        };
+
+        assert_approx_eq!(sine.value(0.0), 1.0);
+        assert_approx_eq!(sine.value(0.005), 3.0);
+        assert_approx_eq!(sine.value(0.015), -1.0);
+    }
+
+    #[test]
+    fn sine_with_phase() {
+        let cosine = Waveform::Sine {
+            amplitude: 1.0,
+            frequency: 1.0,
+            phase: PI / 2.0,
+            offset: 0.0,
+        };
+
+        assert_approx_eq!(cosine.value(0.0), 1.0);
+        assert_approx_eq!(cosine.value(0.5), -1.0);
+    }
+
+    #[test]
+    fn square() {
+        let square = Waveform::Square {
+            amplitude: 5.0,
+            frequency: 10.0,
+            duty_cycle: 0.25,
+            offset: 0.0,
+        };
+
+        assert_eq!(square.value(0.01), 5.0);
+        assert_eq!(square.value(0.03), -5.0);
+        assert_eq!(square.value(0.11), 5.0);
+    }
+
+    #[test]
+    fn pulse() {
+        let pulse = Waveform::Pulse {
+            initial: 0.0,
+            pulsed: 10.0,
+            delay: 1.0,
+            rise: 1.0,
+            fall: 2.0,
+            width: 3.0,
+            period: 10.0,
+        };
+
+        assert_eq!(pulse.value(0.5), 0.0);
+        assert_approx_eq!(pulse.value(1.5), 5.0);
+        assert_eq!(pulse.value(3.0), 10.0);
+        assert_approx_eq!(pulse.value(5.5), 7.5);
+        assert_eq!(pulse.value(8.0), 0.0);
+        assert_eq!(pulse.value(13.0), 10.0);
+    }
+
+    #[test]
+    fn piecewise_linear() {
+        let pwl = Waveform::PiecewiseLinear(vec![(1.0, 0.0), (2.0, 4.0), (4.0, 0.0)]);
+
+        assert_eq!(pwl.value(0.0), 0.0);
+        assert_approx_eq!(pwl.value(1.5), 2.0);
+        assert_approx_eq!(pwl.value(3.0), 2.0);
+        assert_eq!(pwl.value(5.0), 0.0);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let sine = Waveform::Sine {
+            amplitude: 1.0,
+            frequency: 1.0,
+            phase: 0.0,
+            offset: 0.0,
+        };
+        assert_eq!(sine.next_breakpoint(0.0), None);
+
+        let square = Waveform::Square {
+            amplitude: 5.0,
+            frequency: 10.0,
+            duty_cycle: 0.25,
+            offset: 0.0,
+        };
+        assert_approx_eq!(square.next_breakpoint(0.01).unwrap(), 0.025);
+        assert_approx_eq!(square.next_breakpoint(0.03).unwrap(), 0.1);
+
+        let pulse = Waveform::Pulse {
+            initial: 0.0,
+            pulsed: 10.0,
+            delay: 1.0,
+            rise: 1.0,
+            fall: 2.0,
+            width: 3.0,
+            period: 10.0,
+        };
+        assert_eq!(pulse.next_breakpoint(0.5), Some(1.0));
+        assert_eq!(pulse.next_breakpoint(1.0), Some(2.0));
+        assert_eq!(pulse.next_breakpoint(3.0), Some(5.0));
+        assert_eq!(pulse.next_breakpoint(6.0), Some(7.0));
+        assert_eq!(pulse.next_breakpoint(8.0), Some(11.0));
+
+        let single = Waveform::Pulse {
+            initial: 0.0,
+            pulsed: 10.0,
+            delay: 1.0,
+            rise: 0.0,
+            fall: 0.0,
+            width: std::f64::INFINITY,
+            period: std::f64::INFINITY,
+        };
+        assert_eq!(single.next_breakpoint(0.5), Some(1.0));
+        assert_eq!(single.next_breakpoint(1.5), None);
+        assert_eq!(single.value(100.0), 10.0);
+
+        let pwl = Waveform::PiecewiseLinear(vec![(1.0, 0.0), (2.0, 4.0), (4.0, 0.0)]);
+        assert_eq!(pwl.next_breakpoint(0.0), Some(1.0));
+        assert_eq!(pwl.next_breakpoint(2.0), Some(4.0));
+        assert_eq!(pwl.next_breakpoint(4.0), None);
+    }
+}
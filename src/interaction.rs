@@ -0,0 +1,465 @@
+use specs;
+use elements::Position;
+use elements::Terminals;
+use elements::resistor;
+use elements::resistor::Resistor;
+use elements::capacitor;
+use elements::capacitor::Capacitor;
+use elements::inductor;
+use elements::inductor::Inductor;
+use elements::diode;
+use elements::voltage_source;
+use elements::voltage_source::VoltageSource;
+use elements::current_source;
+use elements::current_source::CurrentSource;
+use elements::switch;
+use elements::switch::Switch;
+use elements::wire;
+use elements::ground;
+use solver;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementKind {
+    Resistor,
+    Capacitor,
+    Inductor,
+    Diode,
+    VoltageSource,
+    CurrentSource,
+    Switch(switch::Kind),
+    Wire,
+    Ground,
+}
+impl ElementKind {
+    pub fn create(&self, world: &mut specs::World) -> specs::Entity {
+        match *self {
+            ElementKind::Resistor => resistor::create(world),
+            ElementKind::Capacitor => capacitor::create(world),
+            ElementKind::Inductor => inductor::create(world),
+            ElementKind::Diode => diode::create(world),
+            ElementKind::VoltageSource => voltage_source::create(world),
+            ElementKind::CurrentSource => current_source::create(world),
+            ElementKind::Switch(kind) => switch::create(world, kind),
+            ElementKind::Wire => wire::create(world),
+            ElementKind::Ground => ground::create(world),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Parameter {
+    Resistance(f64),
+    Capacitance(f64),
+    Inductance(f64),
+    Voltage(f64),
+    Current(f64),
+    // Whether a switch is closed
+    Closed(bool),
+}
+impl Parameter {
+    // Whether changing this parameter changes the static equation.
+    fn is_static(&self) -> bool {
+        match *self {
+            Parameter::Resistance(_) |
+            Parameter::Voltage(_) |
+            Parameter::Current(_) => true,
+            Parameter::Capacitance(_) |
+            Parameter::Inductance(_) |
+            Parameter::Closed(_) => false,
+        }
+    }
+}
+
+// Changes to the circuit, e.g. from the user interacting with an editor.
+#[derive(Debug, Clone)]
+pub enum Event {
+    CreateElement {
+        kind: ElementKind,
+        terminals: Vec<Position>,
+    },
+    DeleteElement(specs::Entity),
+    // Move an element's connectors to new positions
+    MoveElement {
+        entity: specs::Entity,
+        terminals: Vec<Position>,
+    },
+    SetParameter {
+        entity: specs::Entity,
+        parameter: Parameter,
+    },
+    // Open a closed switch, or close an open one. Push buttons don't latch.
+    ToggleSwitch(specs::Entity),
+    // Hold a push button down, closing it until it's released
+    PressButton(specs::Entity),
+    ReleaseButton(specs::Entity),
+}
+
+// World resource holding events which have not been processed yet.
+#[derive(Debug, Default)]
+pub struct EventQueue(Vec<Event>);
+impl EventQueue {
+    pub fn new() -> Self {
+        EventQueue(Vec::new())
+    }
+    pub fn push(&mut self, event: Event) {
+        self.0.push(event);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// World resource tracking whether the static equation needs rebuilding.
+#[derive(Debug, Clone, Copy)]
+pub struct Topology {
+    pub dirty: bool,
+}
+impl Default for Topology {
+    fn default() -> Self {
+        // nothing has been built yet
+        Topology { dirty: true }
+    }
+}
+
+// Add the resources needed to process events.
+pub fn register(world: &mut specs::World) {
+    world.add_resource(EventQueue::new());
+    world.add_resource(Topology::default());
+}
+
+// Apply all queued events to the world, returning any entities created (in
+// the order they were queued).
+//
+// If the circuit has changed, node indexes are reassigned and the static
+// equation is rebuilt. Events for entities which are dead, or don't have
+// the relevant component, are ignored.
+//
+// Like `create_static_equation`, this runs before the 'update' event loop
+// cycle.
+pub fn process_events(world: &mut specs::World) -> Vec<specs::Entity> {
+    let events: Vec<Event> = {
+        let mut queue = world.write_resource_now::<EventQueue>();
+        queue.0.drain(..).collect()
+    };
+
+    let mut created = Vec::new();
+    let mut dirty = false;
+    for event in events {
+        match event {
+            Event::CreateElement { kind, terminals } => {
+                let entity = kind.create(world);
+                set_terminals(world, entity, terminals);
+                created.push(entity);
+                dirty = true;
+            }
+            Event::DeleteElement(entity) => {
+                if world.is_alive(entity) {
+                    world.delete_now(entity);
+                    dirty = true;
+                }
+            }
+            Event::MoveElement { entity, terminals } => {
+                dirty |= set_terminals(world, entity, terminals);
+            }
+            Event::SetParameter { entity, parameter } => {
+                if set_parameter(world, entity, parameter) && parameter.is_static() {
+                    dirty = true;
+                }
+            }
+            // switches aren't in the static equation, so these are picked up
+            // on the next step without rebuilding
+            Event::ToggleSwitch(entity) => update_switch(world, entity, Switch::toggle),
+            Event::PressButton(entity) => update_switch(world, entity, Switch::press),
+            Event::ReleaseButton(entity) => update_switch(world, entity, Switch::release),
+        }
+    }
+
+    let rebuild = {
+        let mut topology = world.write_resource_now::<Topology>();
+        let rebuild = topology.dirty || dirty;
+        topology.dirty = false;
+        rebuild
+    };
+    if rebuild {
+        solver::assign_nodes(world);
+        let equation = solver::create_static_equation(world);
+        world.add_resource(equation);
+    }
+
+    created
+}
+
+fn set_terminals(world: &mut specs::World,
+                 entity: specs::Entity,
+                 terminals: Vec<Position>)
+                 -> bool {
+    use specs::Gate;
+    use specs::InsertResult;
+
+    let mut storage = world.write::<Terminals>().pass();
+    match storage.insert(entity, Terminals(terminals)) {
+        InsertResult::Inserted |
+        InsertResult::Updated(_) => true,
+        InsertResult::EntityIsDead(_) => false,
+    }
+}
+
+fn set_parameter(world: &mut specs::World, entity: specs::Entity, parameter: Parameter) -> bool {
+    use specs::Gate;
+
+    match parameter {
+        Parameter::Resistance(resistance) => {
+            let mut resistors = world.write::<Resistor>().pass();
+            if let Some(r) = resistors.get_mut(entity) {
+                r.set_resistance(resistance);
+                return true;
+            }
+        }
+        Parameter::Capacitance(capacitance) => {
+            let mut capacitors = world.write::<Capacitor>().pass();
+            if let Some(c) = capacitors.get_mut(entity) {
+                c.capacitance = capacitance;
+                return true;
+            }
+        }
+        Parameter::Inductance(inductance) => {
+            let mut inductors = world.write::<Inductor>().pass();
+            if let Some(l) = inductors.get_mut(entity) {
+                l.inductance = inductance;
+                return true;
+            }
+        }
+        Parameter::Voltage(voltage) => {
+            let mut v_sources = world.write::<VoltageSource>().pass();
+            if let Some(v) = v_sources.get_mut(entity) {
+                v.voltage = voltage;
+                return true;
+            }
+        }
+        Parameter::Current(current) => {
+            let mut c_sources = world.write::<CurrentSource>().pass();
+            if let Some(c) = c_sources.get_mut(entity) {
+                c.current = current;
+                return true;
+            }
+        }
+        Parameter::Closed(closed) => {
+            let mut switches = world.write::<Switch>().pass();
+            if let Some(s) = switches.get_mut(entity) {
+                s.closed = closed;
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn update_switch(world: &mut specs::World, entity: specs::Entity, update: fn(&mut Switch)) {
+    use specs::Gate;
+
+    let mut switches = world.write::<Switch>().pass();
+    if let Some(s) = switches.get_mut(entity) {
+        update(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::Gate;
+    use elements::Nodes;
+    use solver::equation::Equation;
+    use analysis;
+
+    fn create_world() -> specs::World {
+        let mut world = analysis::tests::create_world();
+        register(&mut world);
+        world
+    }
+
+    // Voltage source and resistor in parallel, grounded at (0, 1)
+    fn create_circuit(world: &mut specs::World) -> (specs::Entity, specs::Entity) {
+        {
+            let mut queue = world.write_resource_now::<EventQueue>();
+            queue.push(Event::CreateElement {
+                kind: ElementKind::VoltageSource,
+                terminals: vec![Position::new(0, 1), Position::new(0, 0)],
+            });
+            queue.push(Event::CreateElement {
+                kind: ElementKind::Resistor,
+                terminals: vec![Position::new(0, 0), Position::new(0, 1)],
+            });
+            queue.push(Event::CreateElement {
+                kind: ElementKind::Ground,
+                terminals: vec![Position::new(0, 1)],
+            });
+        }
+
+        let created = process_events(world);
+        assert_eq!(created.len(), 3);
+        (created[0], created[1])
+    }
+
+    fn push(world: &mut specs::World, event: Event) {
+        world.write_resource_now::<EventQueue>().push(event);
+    }
+
+    fn voltages(world: &specs::World, entity: specs::Entity) -> Vec<f64> {
+        let solution = world.read_resource_now::<Equation>().solve().unwrap();
+        let nodes = world.read::<Nodes>().pass();
+        match nodes.get(entity) {
+            Some(&Nodes(ref ns)) => ns.iter().map(|n| solution.voltages()[n.index]).collect(),
+            None => panic!("oh no"),
+        }
+    }
+
+    #[test]
+    fn create_elements() {
+        let mut world = create_world();
+        let (_, resistor) = create_circuit(&mut world);
+
+        assert!(!world.read_resource_now::<Topology>().dirty);
+        assert!(world.read_resource_now::<EventQueue>().is_empty());
+        assert_eq!(voltages(&world, resistor),
+                   vec![voltage_source::DEFAULT_VOLTAGE, 0.0]);
+    }
+
+    #[test]
+    fn set_static_parameter() {
+        let mut world = create_world();
+        let (voltage_source, resistor) = create_circuit(&mut world);
+
+        push(&mut world,
+             Event::SetParameter {
+                 entity: voltage_source,
+                 parameter: Parameter::Voltage(12.0),
+             });
+        process_events(&mut world);
+
+        assert_eq!(voltages(&world, resistor), vec![12.0, 0.0]);
+    }
+
+    #[test]
+    fn set_dynamic_parameter_does_not_rebuild() {
+        let mut world = create_world();
+        create_circuit(&mut world);
+
+        let capacitor = {
+            push(&mut world,
+                 Event::CreateElement {
+                     kind: ElementKind::Capacitor,
+                     terminals: vec![Position::new(0, 0), Position::new(0, 1)],
+                 });
+            process_events(&mut world)[0]
+        };
+
+        // replace the equation, to see whether it gets rebuilt
+        world.add_resource(Equation::new(1, 0));
+        push(&mut world,
+             Event::SetParameter {
+                 entity: capacitor,
+                 parameter: Parameter::Capacitance(1e-3),
+             });
+        process_events(&mut world);
+
+        assert_eq!(world.read_resource_now::<Equation>().num_nodes(), 1);
+        let capacitors = world.read::<Capacitor>().pass();
+        assert_eq!(capacitors.get(capacitor).unwrap().capacitance, 1e-3);
+    }
+
+    #[test]
+    fn toggle_switch_does_not_rebuild() {
+        let mut world = create_world();
+        create_circuit(&mut world);
+
+        let switch = {
+            push(&mut world,
+                 Event::CreateElement {
+                     kind: ElementKind::Switch(switch::Kind::Spdt),
+                     terminals: vec![Position::new(0, 0), Position::new(0, 1), Position::new(1, 1)],
+                 });
+            process_events(&mut world)[0]
+        };
+
+        // replace the equation, to see whether it gets rebuilt
+        world.add_resource(Equation::new(1, 0));
+        push(&mut world, Event::ToggleSwitch(switch));
+        process_events(&mut world);
+
+        assert_eq!(world.read_resource_now::<Equation>().num_nodes(), 1);
+        assert!(world.read::<Switch>().pass().get(switch).unwrap().closed);
+
+        push(&mut world,
+             Event::SetParameter {
+                 entity: switch,
+                 parameter: Parameter::Closed(false),
+             });
+        process_events(&mut world);
+
+        assert_eq!(world.read_resource_now::<Equation>().num_nodes(), 1);
+        assert!(!world.read::<Switch>().pass().get(switch).unwrap().closed);
+    }
+
+    #[test]
+    fn push_button_is_momentary() {
+        let mut world = create_world();
+        create_circuit(&mut world);
+
+        let button = {
+            push(&mut world,
+                 Event::CreateElement {
+                     kind: ElementKind::Switch(switch::Kind::PushButton),
+                     terminals: vec![Position::new(0, 0), Position::new(1, 1)],
+                 });
+            process_events(&mut world)[0]
+        };
+        let closed = |world: &specs::World| {
+            world.read::<Switch>().pass().get(button).unwrap().closed
+        };
+
+        push(&mut world, Event::ToggleSwitch(button));
+        process_events(&mut world);
+        assert!(!closed(&world));
+
+        push(&mut world, Event::PressButton(button));
+        process_events(&mut world);
+        assert!(closed(&world));
+
+        push(&mut world, Event::ReleaseButton(button));
+        process_events(&mut world);
+        assert!(!closed(&world));
+    }
+
+    #[test]
+    fn move_element() {
+        let mut world = create_world();
+        let (voltage_source, resistor) = create_circuit(&mut world);
+
+        // disconnect the resistor from ground
+        push(&mut world,
+             Event::MoveElement {
+                 entity: resistor,
+                 terminals: vec![Position::new(0, 0), Position::new(1, 1)],
+             });
+        process_events(&mut world);
+
+        let v = voltage_source::DEFAULT_VOLTAGE;
+        assert_eq!(voltages(&world, resistor), vec![v, v]);
+        assert_eq!(voltages(&world, voltage_source), vec![0.0, v]);
+    }
+
+    #[test]
+    fn delete_element() {
+        let mut world = create_world();
+        let (voltage_source, resistor) = create_circuit(&mut world);
+
+        push(&mut world, Event::DeleteElement(resistor));
+        process_events(&mut world);
+
+        assert!(!world.is_alive(resistor));
+        let solution = world.read_resource_now::<Equation>().solve().unwrap();
+        let v_sources = world.read::<VoltageSource>().pass();
+        let index = v_sources.get(voltage_source).unwrap().index;
+        assert_eq!(solution.currents()[index], 0.0);
+    }
+}
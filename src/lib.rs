@@ -29,5 +29,9 @@ extern crate specs;
 
 pub mod elements;
 pub mod solver;
+pub mod interaction;
+pub mod netlist;
+pub mod analysis;
+pub mod output;
 
 pub type Delta = f64;
@@ -0,0 +1,829 @@
+use std;
+use std::f64::consts::PI;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write;
+use specs;
+use elements::CircuitElement;
+use elements::Nodes;
+use elements::resistor::Resistor;
+use elements::capacitor::Capacitor;
+use elements::inductor::Inductor;
+use elements::diode::Diode;
+use elements::bjt::Bjt;
+use elements::bjt::Polarity;
+use elements::mosfet;
+use elements::mosfet::Mosfet;
+use elements::voltage_source::VoltageSource;
+use elements::current_source::CurrentSource;
+use elements::vcvs::Vcvs;
+use elements::vccs::Vccs;
+use elements::ccvs::Ccvs;
+use elements::cccs::Cccs;
+use elements::opamp;
+use elements::opamp::OpAmp;
+use elements::switch;
+use elements::switch::Switch;
+use elements::waveform::Waveform;
+use elements::wire;
+use elements::ground;
+use netlist::Transient;
+
+// The gain of the voltage-controlled voltage source an ideal op-amp is
+// exported as, as SPICE has no nullor
+const IDEAL_OPAMP_GAIN: f64 = 1e12;
+
+// Assign each element a SPICE designator, e.g. R1, C1, V1.
+//
+// Designators are numbered per element type, in entity order, so are stable
+// as long as no elements are created or deleted. Wires and grounds are not
+// given designators, as they are exported as shared nets.
+pub fn designators(world: &specs::World) -> Vec<(specs::Entity, String)> {
+    use specs::Join;
+    use specs::Gate;
+
+    let entities = world.entities();
+    let elements = world.read::<CircuitElement>().pass();
+    let resistors = world.read::<Resistor>().pass();
+    let capacitors = world.read::<Capacitor>().pass();
+    let inductors = world.read::<Inductor>().pass();
+    let diodes = world.read::<Diode>().pass();
+    let bjts = world.read::<Bjt>().pass();
+    let mosfets = world.read::<Mosfet>().pass();
+    let v_sources = world.read::<VoltageSource>().pass();
+    let c_sources = world.read::<CurrentSource>().pass();
+    let vcvs = world.read::<Vcvs>().pass();
+    let vccs = world.read::<Vccs>().pass();
+    let ccvs = world.read::<Ccvs>().pass();
+    let cccs = world.read::<Cccs>().pass();
+    let opamps = world.read::<OpAmp>().pass();
+    let switches = world.read::<Switch>().pass();
+
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    let mut designators = Vec::new();
+
+    for (entity, element) in (&entities, &elements).join() {
+        let prefix = if resistors.get(entity).is_some() {
+            'R'
+        } else if capacitors.get(entity).is_some() {
+            'C'
+        } else if inductors.get(entity).is_some() {
+            'L'
+        } else if diodes.get(entity).is_some() {
+            'D'
+        } else if bjts.get(entity).is_some() {
+            'Q'
+        } else if mosfets.get(entity).is_some() {
+            'M'
+        } else if vcvs.get(entity).is_some() {
+            'E'
+        } else if vccs.get(entity).is_some() {
+            'G'
+        } else if ccvs.get(entity).is_some() {
+            'H'
+        } else if cccs.get(entity).is_some() {
+            'F'
+        } else if opamps.get(entity).is_some() || switches.get(entity).is_some() {
+            'X'
+        } else if c_sources.get(entity).is_some() {
+            'I'
+        } else if v_sources.get(entity).is_some() && !is_net(element) {
+            'V'
+        } else {
+            continue;
+        };
+
+        let count = counts.entry(prefix).or_insert(0);
+        *count += 1;
+        designators.push((entity, format!("{}{}", prefix, count)));
+    }
+
+    designators
+}
+
+// Elements which are exported as connections between nodes, rather than
+// as 0V voltage sources.
+fn is_net(element: &CircuitElement) -> bool {
+    element.display_name() == wire::NAME || element.display_name() == ground::NAME
+}
+
+// Export the circuit as a SPICE netlist.
+//
+// Nodes connected by wires are merged into a single net, and any net
+// connected to ground (or node index 0) is named `0`. Other nets are numbered
+// in order of node index.
+//
+// Elements with no SPICE equivalent are an error, rather than leaving them
+// out and exporting a different circuit.
+pub fn export(world: &specs::World,
+              title: &str,
+              transient: Option<&Transient>)
+              -> Result<String, ExportError> {
+    use specs::Join;
+    use specs::Gate;
+
+    // Both of these take their own storage locks, so must be done first
+    let nets = nets(world);
+    let designators = designators(world);
+
+    let entities = world.entities();
+    let elements = world.read::<CircuitElement>().pass();
+    for (entity, element) in (&entities, &elements).join() {
+        if !is_net(element) && designators.iter().all(|&(e, _)| e != entity) {
+            return Err(ExportError {
+                entity: entity,
+                display_name: element.display_name(),
+            });
+        }
+    }
+    let names: HashMap<specs::Entity, String> = designators.iter().cloned().collect();
+    // The designator of a current-controlled source's control, which must be
+    // an exported voltage source
+    let control_name = |entity: specs::Entity, control: Option<specs::Entity>| {
+        control.and_then(|control| names.get(&control))
+            .filter(|name| name.starts_with('V'))
+            .ok_or_else(|| {
+                ExportError {
+                    entity: entity,
+                    display_name: elements.get(entity).unwrap().display_name(),
+                }
+            })
+    };
+
+    let nodes_ticket = world.read::<Nodes>().pass();
+    let resistors = world.read::<Resistor>().pass();
+    let capacitors = world.read::<Capacitor>().pass();
+    let inductors = world.read::<Inductor>().pass();
+    let diodes = world.read::<Diode>().pass();
+    let bjts = world.read::<Bjt>().pass();
+    let mosfets = world.read::<Mosfet>().pass();
+    let v_sources = world.read::<VoltageSource>().pass();
+    let c_sources = world.read::<CurrentSource>().pass();
+    let vcvs = world.read::<Vcvs>().pass();
+    let vccs = world.read::<Vccs>().pass();
+    let ccvs = world.read::<Ccvs>().pass();
+    let cccs = world.read::<Cccs>().pass();
+    let opamps = world.read::<OpAmp>().pass();
+    let switches = world.read::<Switch>().pass();
+    let waveforms = world.read::<Waveform>().pass();
+
+    let mut output = String::new();
+    let mut models = String::new();
+    writeln!(output, "{}", title).unwrap();
+
+    for (entity, designator) in designators {
+        let ns = match nodes_ticket.get(entity) {
+            Some(&Nodes(ref ns)) => ns,
+            None => continue,
+        };
+        let net = |i: usize| &nets[&ns[i].index];
+
+        if let Some(r) = resistors.get(entity) {
+            let (n0, n1) = r.node_indexes;
+            writeln!(output, "{} {} {} {}", designator, net(n0), net(n1), r.resistance()).unwrap();
+        } else if let Some(c) = capacitors.get(entity) {
+            let (n0, n1) = c.node_indexes;
+            writeln!(output, "{} {} {} {}", designator, net(n0), net(n1), c.capacitance).unwrap();
+        } else if let Some(l) = inductors.get(entity) {
+            let (n0, n1) = l.node_indexes;
+            writeln!(output, "{} {} {} {}", designator, net(n0), net(n1), l.inductance).unwrap();
+        } else if let Some(d) = diodes.get(entity) {
+            let (n0, n1) = d.node_indexes;
+            let model = format!("{}_model", designator);
+            writeln!(output, "{} {} {} {}", designator, net(n0), net(n1), model).unwrap();
+            writeln!(models,
+                     ".model {} D(IS={} N={})",
+                     model,
+                     d.saturation_current,
+                     d.emission_coefficient)
+                .unwrap();
+        } else if let Some(q) = bjts.get(entity) {
+            let (collector, base, emitter) = q.node_indexes;
+            let model = format!("{}_model", designator);
+            writeln!(output,
+                     "{} {} {} {} {}",
+                     designator,
+                     net(collector),
+                     net(base),
+                     net(emitter),
+                     model)
+                .unwrap();
+
+            let polarity = match q.polarity {
+                Polarity::Npn => "NPN",
+                Polarity::Pnp => "PNP",
+            };
+            let mut parameters = format!("IS={} BF={} BR={}",
+                                         q.saturation_current,
+                                         q.forward_beta,
+                                         q.reverse_beta);
+            // SPICE's default is infinite
+            if q.early_voltage.is_finite() {
+                write!(parameters, " VAF={}", q.early_voltage).unwrap();
+            }
+            write!(parameters,
+                   " CJE={} CJC={}",
+                   q.base_emitter_capacitance.capacitance,
+                   q.base_collector_capacitance.capacitance)
+                .unwrap();
+            writeln!(models, ".model {} {}({})", model, polarity, parameters).unwrap();
+        } else if let Some(m) = mosfets.get(entity) {
+            let (drain, gate, source, body) = m.node_indexes;
+            let model = format!("{}_model", designator);
+            writeln!(output,
+                     "{} {} {} {} {} {} W={} L={}",
+                     designator,
+                     net(drain),
+                     net(gate),
+                     net(source),
+                     net(body),
+                     model,
+                     m.width,
+                     m.length)
+                .unwrap();
+
+            let polarity = match m.polarity {
+                mosfet::Polarity::Nmos => "NMOS",
+                mosfet::Polarity::Pmos => "PMOS",
+            };
+            // SPICE's overlap capacitances are per unit width
+            writeln!(models,
+                     ".model {} {}(LEVEL=1 VTO={} KP={} GAMMA={} PHI={} LAMBDA={} CGSO={} \
+                      CGDO={})",
+                     model,
+                     polarity,
+                     m.threshold_voltage,
+                     m.transconductance,
+                     m.body_effect,
+                     m.surface_potential,
+                     m.channel_length_modulation,
+                     m.gate_source_capacitance.capacitance / m.width,
+                     m.gate_drain_capacitance.capacitance / m.width)
+                .unwrap();
+        } else if let Some(e) = vcvs.get(entity) {
+            // controlling voltages (and the output voltages of E and H
+            // sources) go from n- to n+, as with voltage sources
+            writeln!(output,
+                     "{} {} {} {} {} {}",
+                     designator,
+                     net(e.node_indexes.1),
+                     net(e.node_indexes.0),
+                     net(e.control_node_indexes.1),
+                     net(e.control_node_indexes.0),
+                     e.gain)
+                .unwrap();
+        } else if let Some(g) = vccs.get(entity) {
+            writeln!(output,
+                     "{} {} {} {} {} {}",
+                     designator,
+                     net(g.node_indexes.0),
+                     net(g.node_indexes.1),
+                     net(g.control_node_indexes.1),
+                     net(g.control_node_indexes.0),
+                     g.transconductance)
+                .unwrap();
+        } else if let Some(h) = ccvs.get(entity) {
+            // SPICE's control current flows into the control source's n+
+            // node, the opposite way to a `VoltageSource`'s
+            writeln!(output,
+                     "{} {} {} {} {}",
+                     designator,
+                     net(h.node_indexes.1),
+                     net(h.node_indexes.0),
+                     control_name(entity, h.control)?,
+                     -h.transresistance)
+                .unwrap();
+        } else if let Some(f) = cccs.get(entity) {
+            writeln!(output,
+                     "{} {} {} {} {}",
+                     designator,
+                     net(f.node_indexes.0),
+                     net(f.node_indexes.1),
+                     control_name(entity, f.control)?,
+                     -f.gain)
+                .unwrap();
+        } else if let Some(x) = opamps.get(entity) {
+            let model = format!("{}_model", designator);
+            writeln!(output,
+                     "{} {} {} {} {}",
+                     designator,
+                     net(x.input_node_indexes.0),
+                     net(x.input_node_indexes.1),
+                     net(x.output_node_index),
+                     model)
+                .unwrap();
+            write_opamp_model(&mut models, &model, &x.model);
+        } else if let Some(s) = switches.get(entity) {
+            let model = format!("{}_model", designator);
+            let nodes: Vec<&str> = (0..s.kind.num_nodes()).map(|i| net(i).as_str()).collect();
+            writeln!(output, "{} {} {}", designator, nodes.join(" "), model).unwrap();
+            write_switch_model(&mut models, &model, s);
+        } else if let Some(ci) = c_sources.get(entity) {
+            let value = match waveforms.get(entity) {
+                Some(waveform) => source(waveform),
+                None => format!("DC {}", ci.current),
+            };
+            writeln!(output,
+                     "{} {} {} {}",
+                     designator,
+                     net(ci.node_index_from()),
+                     net(ci.node_index_to()),
+                     value)
+                .unwrap();
+        } else if let Some(vi) = v_sources.get(entity) {
+            let value = match waveforms.get(entity) {
+                Some(waveform) => source(waveform),
+                None => format!("DC {}", vi.voltage),
+            };
+            // SPICE voltage sources go from n+ to n-
+            writeln!(output,
+                     "{} {} {} {}",
+                     designator,
+                     net(vi.node_index_to()),
+                     net(vi.node_index_from()),
+                     value)
+                .unwrap();
+        }
+    }
+
+    output.push_str(&models);
+    if let Some(transient) = transient {
+        writeln!(output, ".tran {} {}", transient.step, transient.stop).unwrap();
+    }
+    output.push_str(".end\n");
+
+    Ok(output)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExportError {
+    pub entity: specs::Entity,
+    pub display_name: &'static str,
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} can't be exported", self.display_name, self.entity.get_id())
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+// Map node indexes to net names, merging nodes connected by wires.
+fn nets(world: &specs::World) -> HashMap<usize, String> {
+    use specs::Join;
+    use specs::Gate;
+
+    let nodes_ticket = world.read::<Nodes>().pass();
+    let elements = world.read::<CircuitElement>().pass();
+
+    let num_nodes = match (&nodes_ticket,)
+        .join()
+        .flat_map(|(&Nodes(ref ns),)| ns.iter())
+        .map(|n| n.index)
+        .max() {
+        Some(index) => index + 1,
+        None => 0,
+    };
+
+    // union-find, with the lowest index as the root
+    let mut parents: Vec<usize> = (0..num_nodes).collect();
+    fn find(parents: &mut Vec<usize>, i: usize) -> usize {
+        let mut root = i;
+        while parents[root] != root {
+            root = parents[root];
+        }
+        parents[i] = root;
+        root
+    }
+
+    for (&Nodes(ref ns), element) in (&nodes_ticket, &elements).join() {
+        if !is_net(element) {
+            continue;
+        }
+        let a = find(&mut parents, ns[0].index);
+        let b = find(&mut parents, ns[1].index);
+        if a < b {
+            parents[b] = a;
+        } else {
+            parents[a] = b;
+        }
+    }
+
+    let mut used = vec![false; num_nodes];
+    for (&Nodes(ref ns),) in (&nodes_ticket,).join() {
+        for node in ns.iter() {
+            used[node.index] = true;
+        }
+    }
+
+    // ground is always named 0
+    let mut names: HashMap<usize, String> = HashMap::new();
+    let mut roots: HashMap<usize, String> = HashMap::new();
+    roots.insert(0, "0".to_owned());
+    for i in (0..num_nodes).filter(|&i| used[i]) {
+        let root = find(&mut parents, i);
+        let next = roots.len();
+        let name = roots.entry(root).or_insert_with(|| next.to_string()).clone();
+        names.insert(i, name);
+    }
+
+    names
+}
+
+// An op-amp as a subcircuit, with its (non-inverting, inverting, output)
+// nodes.
+//
+// The macro model's pole is a transconductance driving an RC, and its rails a
+// behavioural source, as supported by ngspice.
+fn write_opamp_model(models: &mut String, name: &str, model: &opamp::Model) {
+    writeln!(models, ".subckt {} inp inn out", name).unwrap();
+    match *model {
+        opamp::Model::Ideal => {
+            writeln!(models, "E1 out 0 inp inn {}", IDEAL_OPAMP_GAIN).unwrap();
+        }
+        opamp::Model::Macro(ref model) => {
+            if model.input_resistance.is_finite() {
+                writeln!(models, "RIN inp inn {}", model.input_resistance).unwrap();
+            }
+            writeln!(models, "G1 0 pole inp inn {}", model.gain).unwrap();
+            writeln!(models, "RPOLE pole 0 1").unwrap();
+            writeln!(models, "CPOLE pole 0 {}", model.time_constant()).unwrap();
+            let clamp = format!("V=max(min(V(pole), {}), {})",
+                                model.positive_rail,
+                                model.negative_rail);
+            if model.output_resistance > 0.0 {
+                writeln!(models, "BOUT clamped 0 {}", clamp).unwrap();
+                writeln!(models, "ROUT clamped out {}", model.output_resistance).unwrap();
+            } else {
+                writeln!(models, "BOUT out 0 {}", clamp).unwrap();
+            }
+        }
+    }
+    writeln!(models, ".ends").unwrap();
+}
+
+// A switch as a subcircuit, with its common terminal as its first node.
+//
+// Its contacts are voltage-controlled switches, driven by a source which is
+// 1V while it's closed and -1V while it's open.
+fn write_switch_model(models: &mut String, name: &str, s: &Switch) {
+    let control = if s.closed { 1.0 } else { -1.0 };
+    match s.kind {
+        switch::Kind::Spst | switch::Kind::PushButton => {
+            writeln!(models, ".subckt {} common contact", name).unwrap();
+            writeln!(models, "VCONTROL control 0 DC {}", control).unwrap();
+            writeln!(models, "S1 common contact control 0 contact").unwrap();
+        }
+        switch::Kind::Spdt => {
+            writeln!(models, ".subckt {} common open closed", name).unwrap();
+            writeln!(models, "VCONTROL control 0 DC {}", control).unwrap();
+            writeln!(models, "S1 common open 0 control contact").unwrap();
+            writeln!(models, "S2 common closed control 0 contact").unwrap();
+        }
+    }
+    writeln!(models,
+             ".model contact SW(VT=0 RON={} ROFF={})",
+             s.on_resistance,
+             s.off_resistance)
+        .unwrap();
+    writeln!(models, ".ends").unwrap();
+}
+
+fn source(waveform: &Waveform) -> String {
+    match *waveform {
+        Waveform::Sine { amplitude, frequency, phase, offset } => {
+            format!("SIN({} {} {} 0 0 {})",
+                    offset,
+                    amplitude,
+                    frequency,
+                    phase * 180.0 / PI)
+        }
+        Waveform::Square { amplitude, frequency, duty_cycle, offset } => {
+            let period = 1.0 / frequency;
+            format!("PULSE({} {} 0 0 0 {} {})",
+                    offset - amplitude,
+                    offset + amplitude,
+                    duty_cycle * period,
+                    period)
+        }
+        Waveform::Pulse { initial, pulsed, delay, rise, fall, width, period } => {
+            // SPICE has no infinity, but an omitted width or period lasts
+            // for the whole simulation
+            let mut values = vec![initial, pulsed, delay, rise, fall, width, period];
+            while values.last().map_or(false, |value| value.is_infinite()) {
+                values.pop();
+            }
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            format!("PULSE({})", values.join(" "))
+        }
+        Waveform::PiecewiseLinear(ref points) => {
+            let points: Vec<String> = points.iter()
+                .map(|&(t, v)| format!("{} {}", t, v))
+                .collect();
+            format!("PWL({})", points.join(" "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::Gate;
+    use elements::resistor;
+    use elements::voltage_source;
+    use elements::wire;
+    use elements::ground;
+    use netlist::parse;
+    use analysis::tests::create_world;
+
+    fn set_nodes(world: &mut specs::World, entity: specs::Entity, n0: usize, n1: usize) {
+        let mut nodes = world.write::<Nodes>().pass();
+        match nodes.get_mut(entity) {
+            Some(&mut Nodes(ref mut ns)) => {
+                ns[0].index = n0;
+                ns[1].index = n1;
+            }
+            None => panic!("oh no"),
+        }
+    }
+
+    #[test]
+    fn wires_are_collapsed() {
+        let mut world = create_world();
+
+        let voltage_source = voltage_source::create(&mut world);
+        let wire1 = wire::create(&mut world);
+        let resistor = resistor::create(&mut world);
+        let wire2 = wire::create(&mut world);
+        let ground = ground::create(&mut world);
+        set_nodes(&mut world, voltage_source, 1, 2);
+        set_nodes(&mut world, wire1, 2, 3);
+        set_nodes(&mut world, resistor, 3, 4);
+        set_nodes(&mut world, wire2, 4, 1);
+        set_nodes(&mut world, ground, 1, 0);
+
+        let output = export(&world, "Wires", None).unwrap();
+
+        assert_eq!(output, "Wires\nV1 1 0 DC 5\nR1 1 0 1000\n.end\n");
+    }
+
+    #[test]
+    fn designators_are_per_type() {
+        let mut world = create_world();
+
+        let r1 = resistor::create(&mut world);
+        let v1 = voltage_source::create(&mut world);
+        wire::create(&mut world);
+        let r2 = resistor::create(&mut world);
+
+        assert_eq!(designators(&world),
+                   vec![(r1, "R1".to_owned()), (v1, "V1".to_owned()), (r2, "R2".to_owned())]);
+    }
+
+    #[test]
+    fn transistors() {
+        use elements::bjt;
+
+        let mut world = create_world();
+
+        let npn = bjt::create(&mut world, Polarity::Npn);
+        let pnp = bjt::create(&mut world, Polarity::Pnp);
+        {
+            let mut bjts = world.write::<Bjt>().pass();
+            bjts.get_mut(pnp).unwrap().early_voltage = 50.0;
+
+            let mut nodes = world.write::<Nodes>().pass();
+            for &(entity, indexes) in &[(npn, [1, 2, 0]), (pnp, [0, 2, 1])] {
+                match nodes.get_mut(entity) {
+                    Some(&mut Nodes(ref mut ns)) => {
+                        for (node, &index) in ns.iter_mut().zip(&indexes) {
+                            node.index = index;
+                        }
+                    }
+                    None => panic!("oh no"),
+                }
+            }
+        }
+
+        let output = export(&world, "Transistors", None).unwrap();
+
+        assert_eq!(output,
+                   "Transistors
+Q1 1 2 0 Q1_model
+Q2 0 2 1 Q2_model
+.model Q1_model NPN(IS=0.0000000000000001 BF=100 BR=1 CJE=0 CJC=0)
+.model Q2_model PNP(IS=0.0000000000000001 BF=100 BR=1 VAF=50 CJE=0 CJC=0)
+.end
+");
+    }
+
+    #[test]
+    fn mosfets() {
+        let mut world = create_world();
+
+        let nmos = mosfet::create(&mut world, mosfet::Polarity::Nmos);
+        let pmos = mosfet::create(&mut world, mosfet::Polarity::Pmos);
+        {
+            let mut mosfets = world.write::<Mosfet>().pass();
+            let model = mosfets.get_mut(pmos).unwrap();
+            model.threshold_voltage = -1.0;
+            model.width = 0.5;
+            model.gate_source_capacitance.capacitance = 1e-12;
+
+            let mut nodes = world.write::<Nodes>().pass();
+            for &(entity, indexes) in &[(nmos, [1, 2, 0, 0]), (pmos, [1, 2, 3, 3])] {
+                match nodes.get_mut(entity) {
+                    Some(&mut Nodes(ref mut ns)) => {
+                        for (node, &index) in ns.iter_mut().zip(&indexes) {
+                            node.index = index;
+                        }
+                    }
+                    None => panic!("oh no"),
+                }
+            }
+        }
+
+        let output = export(&world, "MOSFETs", None).unwrap();
+
+        assert_eq!(output,
+                   "MOSFETs
+M1 1 2 0 0 M1_model W=0.0001 L=0.0001
+M2 1 2 3 3 M2_model W=0.5 L=0.0001
+.model M1_model NMOS(LEVEL=1 VTO=0 KP=0.00002 GAMMA=0 PHI=0.6 LAMBDA=0 CGSO=0 CGDO=0)
+.model M2_model PMOS(LEVEL=1 VTO=-1 KP=0.00002 GAMMA=0 PHI=0.6 LAMBDA=0 CGSO=0.000000000002 \
+                   CGDO=0)
+.end
+");
+    }
+
+    #[test]
+    fn controlled_sources() {
+        let input = "Controlled sources
+H1 h 0 V1 100
+V1 in 0 1
+E1 e 0 in 0 2
+G1 0 g in 0 1m
+F1 0 f V1 2
+.end
+";
+        let mut world = create_world();
+        let netlist = parse(input, &mut world).unwrap();
+
+        let output = export(&world, &netlist.title, None).unwrap();
+        assert_eq!(output,
+                   "Controlled sources
+H1 1 0 V1 100
+V1 2 0 DC 1
+E1 3 0 2 0 2
+G1 0 4 2 0 0.001
+F1 0 5 V1 2
+.end
+");
+
+        let mut reimported_world = create_world();
+        let reimported = parse(&output, &mut reimported_world).unwrap();
+
+        assert_eq!(export(&reimported_world, &reimported.title, None).unwrap(), output);
+    }
+
+    #[test]
+    fn opamps() {
+        let mut world = create_world();
+
+        let ideal = opamp::create(&mut world);
+        let real = opamp::create(&mut world);
+        {
+            let mut opamps = world.write::<OpAmp>().pass();
+            let mut model = opamp::MacroModel::default();
+            model.gain = 1e5;
+            model.gain_bandwidth = 1e6 / (2.0 * PI);
+            opamps.get_mut(real).unwrap().model = opamp::Model::Macro(model);
+
+            let mut nodes = world.write::<Nodes>().pass();
+            for &(entity, indexes) in &[(ideal, [0, 1, 2]), (real, [2, 3, 3])] {
+                match nodes.get_mut(entity) {
+                    Some(&mut Nodes(ref mut ns)) => {
+                        for (node, &index) in ns.iter_mut().zip(&indexes) {
+                            node.index = index;
+                        }
+                    }
+                    None => panic!("oh no"),
+                }
+            }
+        }
+
+        let output = export(&world, "Op-amps", None).unwrap();
+
+        assert_eq!(output,
+                   "Op-amps
+X1 0 1 2 X1_model
+X2 2 3 3 X2_model
+.subckt X1_model inp inn out
+E1 out 0 inp inn 1000000000000
+.ends
+.subckt X2_model inp inn out
+RIN inp inn 2000000
+G1 0 pole inp inn 100000
+RPOLE pole 0 1
+CPOLE pole 0 0.1
+BOUT clamped 0 V=max(min(V(pole), 15), -15)
+ROUT clamped out 75
+.ends
+.end
+");
+    }
+
+    #[test]
+    fn switches() {
+        let mut world = create_world();
+
+        let spst = switch::create(&mut world, switch::Kind::Spst);
+        let spdt = switch::create(&mut world, switch::Kind::Spdt);
+        {
+            world.write::<Switch>().pass().get_mut(spdt).unwrap().toggle();
+
+            let mut nodes = world.write::<Nodes>().pass();
+            for &(entity, ref indexes) in &[(spst, vec![1, 0]), (spdt, vec![1, 2, 3])] {
+                match nodes.get_mut(entity) {
+                    Some(&mut Nodes(ref mut ns)) => {
+                        for (node, &index) in ns.iter_mut().zip(indexes) {
+                            node.index = index;
+                        }
+                    }
+                    None => panic!("oh no"),
+                }
+            }
+        }
+
+        let output = export(&world, "Switches", None).unwrap();
+
+        assert_eq!(output,
+                   "Switches
+X1 1 0 X1_model
+X2 1 2 3 X2_model
+.subckt X1_model common contact
+VCONTROL control 0 DC -1
+S1 common contact control 0 contact
+.model contact SW(VT=0 RON=1 ROFF=1000000000000)
+.ends
+.subckt X2_model common open closed
+VCONTROL control 0 DC 1
+S1 common open 0 control contact
+S2 common closed control 0 contact
+.model contact SW(VT=0 RON=1 ROFF=1000000000000)
+.ends
+.end
+");
+    }
+
+    #[test]
+    fn uncontrolled_sources_are_errors() {
+        use elements::ccvs;
+
+        let mut world = create_world();
+        let source = ccvs::create(&mut world);
+
+        let error = export(&world, "No control", None).unwrap_err();
+        assert_eq!(error.entity, source);
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "Round trip
+V1 in 0 SIN(1 2 1k)
+R1 in mid 1k
+L1 mid out 1m
+C1 out 0 10u
+I1 out 0 DC 1m
+V2 mid 0 5
+.tran 1e-6 0.001
+.end
+";
+        let mut world = create_world();
+        let netlist = parse(input, &mut world).unwrap();
+
+        let output = export(&world, &netlist.title, netlist.transient.as_ref()).unwrap();
+        assert_eq!(output,
+                   "Round trip
+V1 1 0 SIN(1 2 1000 0 0 0)
+R1 1 2 1000
+L1 2 3 0.001
+C1 3 0 0.00001
+I1 3 0 DC 0.001
+V2 2 0 DC 5
+.tran 0.000001 0.001
+.end
+");
+
+        // and the exported netlist gives the same circuit
+        let mut reimported_world = create_world();
+        let reimported = parse(&output, &mut reimported_world).unwrap();
+
+        assert_eq!(export(&reimported_world,
+                          &reimported.title,
+                          reimported.transient.as_ref())
+                       .unwrap(),
+                   output);
+    }
+}
@@ -0,0 +1,42 @@
+mod parse;
+mod export;
+
+pub use self::export::export;
+pub use self::export::designators;
+pub use self::export::ExportError;
+pub use self::parse::parse;
+pub use self::parse::parse_value;
+pub use self::parse::ParseError;
+
+use specs;
+
+// A SPICE netlist which has been loaded into a world.
+#[derive(Debug)]
+pub struct Netlist {
+    pub title: String,
+    // Element designators (e.g. "R1"), and the entities created for them
+    pub elements: Vec<(String, specs::Entity)>,
+    // Node names, by node index. Index 0 is ground.
+    pub nodes: Vec<String>,
+    pub transient: Option<Transient>,
+}
+
+impl Netlist {
+    pub fn entity(&self, designator: &str) -> Option<specs::Entity> {
+        self.elements
+            .iter()
+            .find(|&&(ref name, _)| name.eq_ignore_ascii_case(designator))
+            .map(|&(_, entity)| entity)
+    }
+
+    pub fn node_index(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|n| n.eq_ignore_ascii_case(name))
+    }
+}
+
+// Settings from a `.tran` control line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transient {
+    pub step: f64,
+    pub stop: f64,
+}
@@ -0,0 +1,669 @@
+use std;
+use std::f64::consts::PI;
+use std::fmt;
+use specs;
+use elements::Nodes;
+use elements::resistor;
+use elements::resistor::Resistor;
+use elements::capacitor;
+use elements::capacitor::Capacitor;
+use elements::inductor;
+use elements::inductor::Inductor;
+use elements::voltage_source;
+use elements::voltage_source::VoltageSource;
+use elements::current_source;
+use elements::current_source::CurrentSource;
+use elements::vcvs;
+use elements::vcvs::Vcvs;
+use elements::vccs;
+use elements::vccs::Vccs;
+use elements::ccvs;
+use elements::ccvs::Ccvs;
+use elements::cccs;
+use elements::cccs::Cccs;
+use elements::waveform;
+use elements::waveform::Waveform;
+use netlist::Netlist;
+use netlist::Transient;
+
+// Parse a SPICE netlist, creating an entity for each element.
+//
+// Supports a subset of SPICE:
+//
+// - R, C, L, V and I elements
+// - E, F, G and H controlled sources, with linear gains
+// - DC, SIN, PULSE and PWL sources
+// - `*` comment lines, `;` end of line comments and `+` continuation lines
+// - `.tran` and `.end` control lines
+//
+// As in SPICE, the first line is the title. Node `0` (or `gnd`) is ground,
+// other nodes are given indexes in the order they first appear.
+pub fn parse(input: &str, world: &mut specs::World) -> Result<Netlist, ParseError> {
+    use specs::Gate;
+
+    let mut netlist = Netlist {
+        title: String::new(),
+        elements: Vec::new(),
+        nodes: vec!["0".to_owned()],
+        transient: None,
+    };
+
+    // The control sources of current-controlled sources, which may come later
+    // in the netlist, by the line they're named on
+    let mut controls: Vec<(usize, specs::Entity, String)> = Vec::new();
+
+    let mut lines = logical_lines(input);
+    if !lines.is_empty() {
+        netlist.title = lines.remove(0).1.trim().to_owned();
+    }
+
+    // Pulse sources' omitted parameters default to the `.tran` settings, and
+    // the `.tran` line is usually last, so find it first
+    for &(line_number, ref line) in &lines {
+        let tokens = tokenise(line);
+        match tokens.first().map(|token| token.to_lowercase()) {
+            Some(ref control) if control == ".end" => break,
+            Some(ref control) if control == ".tran" => {
+                match parse_transient(&tokens) {
+                    Ok(transient) => netlist.transient = Some(transient),
+                    Err(message) => {
+                        return Err(ParseError {
+                            line: line_number,
+                            message: message,
+                        })
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (line_number, line) in lines {
+        let tokens = tokenise(&line);
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let result = if tokens[0].starts_with('.') {
+            match &*tokens[0].to_lowercase() {
+                ".end" => break,
+                ".tran" => Ok(()),
+                _ => Err(format!("Unsupported control line: {}", tokens[0])),
+            }
+        } else {
+            parse_element(&tokens, &mut netlist, world).map(|control| {
+                if let Some((entity, name)) = control {
+                    controls.push((line_number, entity, name));
+                }
+            })
+        };
+
+        if let Err(message) = result {
+            return Err(ParseError {
+                line: line_number,
+                message: message,
+            });
+        }
+    }
+
+    for (line_number, entity, name) in controls {
+        let control = match netlist.entity(&name) {
+            Some(control) if world.read::<VoltageSource>().pass().get(control).is_some() => {
+                control
+            }
+            _ => {
+                return Err(ParseError {
+                    line: line_number,
+                    message: format!("Unknown control source: {}", name),
+                })
+            }
+        };
+        if let Some(source) = world.write::<Ccvs>().pass().get_mut(entity) {
+            source.control = Some(control);
+        }
+        if let Some(source) = world.write::<Cccs>().pass().get_mut(entity) {
+            source.control = Some(control);
+        }
+    }
+
+    Ok(netlist)
+}
+
+// Parse a number with an optional engineering suffix, e.g. `1k`, `10u`,
+// `4.7meg`. Any letters after the suffix (e.g. units) are ignored.
+pub fn parse_value(value: &str) -> Option<f64> {
+    let value = value.to_lowercase();
+    let split = value.find(|c: char| c.is_alphabetic() && c != 'e').unwrap_or(value.len());
+
+    // 'e' could be an exponent or the start of a suffix, so let the number
+    // parser decide, backing off if it fails
+    let (number, suffix) = match value[..split].parse::<f64>() {
+        Ok(number) => (number, &value[split..]),
+        Err(_) => {
+            let split = value.find(|c: char| c.is_alphabetic()).unwrap_or(value.len());
+            match value[..split].parse::<f64>() {
+                Ok(number) => (number, &value[split..]),
+                Err(_) => return None,
+            }
+        }
+    };
+
+    let exponent = if suffix.starts_with("meg") {
+        6
+    } else if suffix.starts_with("mil") {
+        return Some(number * 25.4e-6);
+    } else {
+        match suffix.chars().next() {
+            Some('t') => 12,
+            Some('g') => 9,
+            Some('k') => 3,
+            Some('m') => -3,
+            Some('u') => -6,
+            Some('n') => -9,
+            Some('p') => -12,
+            Some('f') => -15,
+            _ => 0,
+        }
+    };
+
+    // scale by adjusting the exponent, to avoid rounding errors (e.g. 10u
+    // should be exactly 10e-6)
+    let number_end = value.len() - suffix.len();
+    match format!("{}e{}", &value[..number_end], exponent).parse::<f64>() {
+        Ok(scaled) => Some(scaled),
+        Err(_) => Some(number * 10f64.powi(exponent)),
+    }
+}
+
+// Join continuation lines and strip comments, keeping the (1-based) line
+// number each logical line started on.
+fn logical_lines(input: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+
+    for (i, line) in input.lines().enumerate() {
+        let line = match line.find(';') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        if i == 0 {
+            // title
+            lines.push((1, line.to_owned()));
+        } else if line.trim().starts_with('*') {
+            continue;
+        } else if line.starts_with('+') && lines.len() > 1 {
+            let last = lines.len() - 1;
+            lines[last].1.push(' ');
+            lines[last].1.push_str(&line[1..]);
+        } else {
+            lines.push((i + 1, line.to_owned()));
+        }
+    }
+
+    lines
+}
+
+fn tokenise(line: &str) -> Vec<String> {
+    line.split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ',')
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_owned())
+        .collect()
+}
+
+fn value(token: &str) -> Result<f64, String> {
+    parse_value(token).ok_or_else(|| format!("Invalid value: {}", token))
+}
+
+fn parse_transient(tokens: &[String]) -> Result<Transient, String> {
+    if tokens.len() < 3 {
+        return Err("Expected .tran <step> <stop>".to_owned());
+    }
+    Ok(Transient {
+        step: value(&tokens[1])?,
+        stop: value(&tokens[2])?,
+    })
+}
+
+// Returns the entity and the name of the control source for current-controlled
+// sources, as it may not have been created yet.
+fn parse_element(tokens: &[String],
+                 netlist: &mut Netlist,
+                 world: &mut specs::World)
+                 -> Result<Option<(specs::Entity, String)>, String> {
+    use specs::Gate;
+
+    let designator = tokens[0].clone();
+    if netlist.entity(&designator).is_some() {
+        return Err(format!("Duplicate element: {}", designator));
+    }
+    if tokens.len() < 4 {
+        return Err(format!("Expected {} <node> <node> <value>", designator));
+    }
+
+    let n0 = node_index(netlist, &tokens[1]);
+    let n1 = node_index(netlist, &tokens[2]);
+    let params = &tokens[3..];
+    let transient = netlist.transient;
+    let mut control = None;
+
+    // SPICE voltage sources are from n- to n+, unlike the other elements
+    let (entity, node_indexes) = match designator.chars().next().map(|c| c.to_ascii_uppercase()) {
+        Some('R') => {
+            let resistance = single_value(params)?;
+            let entity = resistor::create(world);
+            world.write::<Resistor>().pass().get_mut(entity).unwrap().set_resistance(resistance);
+            (entity, vec![n0, n1])
+        }
+        Some('C') => {
+            let capacitance = single_value(params)?;
+            let entity = capacitor::create(world);
+            world.write::<Capacitor>().pass().get_mut(entity).unwrap().capacitance = capacitance;
+            (entity, vec![n0, n1])
+        }
+        Some('L') => {
+            let inductance = single_value(params)?;
+            let entity = inductor::create(world);
+            world.write::<Inductor>().pass().get_mut(entity).unwrap().inductance = inductance;
+            (entity, vec![n0, n1])
+        }
+        Some('V') => {
+            let entity = match source(params, transient)? {
+                Source::Dc(voltage) => {
+                    let entity = voltage_source::create(world);
+                    world.write::<VoltageSource>().pass().get_mut(entity).unwrap().voltage =
+                        voltage;
+                    entity
+                }
+                Source::TimeVarying(waveform) => waveform::create_voltage_source(world, waveform),
+            };
+            (entity, vec![n1, n0])
+        }
+        Some('I') => {
+            let entity = match source(params, transient)? {
+                Source::Dc(current) => {
+                    let entity = current_source::create(world);
+                    world.write::<CurrentSource>().pass().get_mut(entity).unwrap().current =
+                        current;
+                    entity
+                }
+                Source::TimeVarying(waveform) => waveform::create_current_source(world, waveform),
+            };
+            (entity, vec![n0, n1])
+        }
+        // As with voltage sources, controlling voltages (and the output
+        // voltages of E and H sources) are from n- to n+
+        Some('E') => {
+            if tokens.len() != 6 {
+                return Err(format!("Expected {} <node> <node> <node> <node> <gain>",
+                                   designator));
+            }
+            let nc0 = node_index(netlist, &tokens[3]);
+            let nc1 = node_index(netlist, &tokens[4]);
+            let gain = value(&tokens[5])?;
+            let entity = vcvs::create(world);
+            world.write::<Vcvs>().pass().get_mut(entity).unwrap().gain = gain;
+            (entity, vec![n1, n0, nc1, nc0])
+        }
+        Some('G') => {
+            if tokens.len() != 6 {
+                return Err(format!("Expected {} <node> <node> <node> <node> <transconductance>",
+                                   designator));
+            }
+            let nc0 = node_index(netlist, &tokens[3]);
+            let nc1 = node_index(netlist, &tokens[4]);
+            let transconductance = value(&tokens[5])?;
+            let entity = vccs::create(world);
+            world.write::<Vccs>().pass().get_mut(entity).unwrap().transconductance =
+                transconductance;
+            (entity, vec![n0, n1, nc1, nc0])
+        }
+        // SPICE's control current flows into the control source's n+ node, the
+        // opposite way to a `VoltageSource`'s
+        Some('H') => {
+            if tokens.len() != 5 {
+                return Err(format!("Expected {} <node> <node> <source> <transresistance>",
+                                   designator));
+            }
+            let transresistance = value(&tokens[4])?;
+            let entity = ccvs::create(world);
+            world.write::<Ccvs>().pass().get_mut(entity).unwrap().transresistance =
+                -transresistance;
+            control = Some((entity, tokens[3].clone()));
+            (entity, vec![n1, n0])
+        }
+        Some('F') => {
+            if tokens.len() != 5 {
+                return Err(format!("Expected {} <node> <node> <source> <gain>", designator));
+            }
+            let gain = value(&tokens[4])?;
+            let entity = cccs::create(world);
+            world.write::<Cccs>().pass().get_mut(entity).unwrap().gain = -gain;
+            control = Some((entity, tokens[3].clone()));
+            (entity, vec![n0, n1])
+        }
+        _ => return Err(format!("Unsupported element: {}", designator)),
+    };
+
+    match world.write::<Nodes>().pass().get_mut(entity) {
+        Some(&mut Nodes(ref mut ns)) => {
+            for (node, &index) in ns.iter_mut().zip(&node_indexes) {
+                node.index = index;
+            }
+        }
+        None => unreachable!(),
+    }
+
+    netlist.elements.push((designator, entity));
+    Ok(control)
+}
+
+fn node_index(netlist: &mut Netlist, name: &str) -> usize {
+    if name.eq_ignore_ascii_case("gnd") {
+        return 0;
+    }
+    match netlist.node_index(name) {
+        Some(index) => index,
+        None => {
+            netlist.nodes.push(name.to_owned());
+            netlist.nodes.len() - 1
+        }
+    }
+}
+
+fn single_value(params: &[String]) -> Result<f64, String> {
+    if params.len() != 1 {
+        return Err(format!("Expected a single value, found {}", params.join(" ")));
+    }
+    value(&params[0])
+}
+
+enum Source {
+    Dc(f64),
+    TimeVarying(Waveform),
+}
+
+fn source(params: &[String], transient: Option<Transient>) -> Result<Source, String> {
+    let kind = params[0].to_lowercase();
+    let args = &params[1..];
+
+    let values = |min: usize, max: usize| -> Result<Vec<f64>, String> {
+        if args.len() < min || args.len() > max {
+            return Err(format!("Expected {} to {} values for {}", min, max, kind));
+        }
+        args.iter().map(|a| value(a)).collect()
+    };
+
+    match &*kind {
+        "dc" => single_value(args).map(Source::Dc),
+        "sin" => {
+            // VO VA FREQ TD THETA PHASE
+            let vs = values(3, 6)?;
+            if vs.get(3).map_or(false, |&td| td != 0.0) ||
+               vs.get(4).map_or(false, |&theta| theta != 0.0) {
+                return Err("Delayed and damped SIN sources are not supported".to_owned());
+            }
+            Ok(Source::TimeVarying(Waveform::Sine {
+                offset: vs[0],
+                amplitude: vs[1],
+                frequency: vs[2],
+                phase: vs.get(5).map_or(0.0, |&degrees| degrees * PI / 180.0),
+            }))
+        }
+        "pulse" => {
+            // V1 V2 TD TR TF PW PER
+            let vs = values(2, 7)?;
+            // As in SPICE, omitted rise and fall times default to the
+            // timestep, and the width and period to the stop time. Without a
+            // `.tran` line the pulse is a single step.
+            let (step, stop) = match transient {
+                Some(transient) => (transient.step, transient.stop),
+                None => (0.0, std::f64::INFINITY),
+            };
+            let v = |i: usize, default: f64| vs.get(i).cloned().unwrap_or(default);
+            Ok(Source::TimeVarying(Waveform::Pulse {
+                initial: v(0, 0.0),
+                pulsed: v(1, 0.0),
+                delay: v(2, 0.0),
+                rise: v(3, step),
+                fall: v(4, step),
+                width: v(5, stop),
+                period: v(6, stop),
+            }))
+        }
+        "pwl" => {
+            // T1 V1 T2 V2 ...
+            let vs = values(2, std::usize::MAX)?;
+            if vs.len() % 2 != 0 {
+                return Err("Expected pairs of values for pwl".to_owned());
+            }
+            let points = vs.chunks(2).map(|p| (p[0], p[1])).collect();
+            Ok(Source::TimeVarying(Waveform::PiecewiseLinear(points)))
+        }
+        _ => single_value(params).map(Source::Dc),
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::Gate;
+    use solver;
+    use analysis::tests::create_world;
+
+    fn node_indexes(world: &specs::World, entity: specs::Entity) -> Vec<usize> {
+        let nodes = world.read::<Nodes>().pass();
+        match nodes.get(entity) {
+            Some(&Nodes(ref ns)) => ns.iter().map(|n| n.index).collect(),
+            None => panic!("oh no"),
+        }
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_value("10"), Some(10.0));
+        assert_eq!(parse_value("-2.5"), Some(-2.5));
+        assert_eq!(parse_value("1e3"), Some(1e3));
+        assert_eq!(parse_value("1k"), Some(1e3));
+        assert_eq!(parse_value("4.7MEG"), Some(4.7e6));
+        assert_eq!(parse_value("10u"), Some(10e-6));
+        assert_eq!(parse_value("10uF"), Some(10e-6));
+        assert_eq!(parse_value("3m"), Some(3e-3));
+        assert_eq!(parse_value("100p"), Some(100e-12));
+        assert_eq!(parse_value("5V"), Some(5.0));
+        assert_eq!(parse_value("k"), None);
+        assert_eq!(parse_value("abc"), None);
+    }
+
+    #[test]
+    fn resistor_capacitor() {
+        let mut world = create_world();
+        let netlist = parse("RC circuit
+* a comment
+V1 in 0 DC 5
+R1 in out 1k ; another comment
+C1 out 0
++ 10u
+.tran 5u 1m
+.end
+R2 this is ignored",
+                            &mut world)
+            .unwrap();
+
+        assert_eq!(netlist.title, "RC circuit");
+        assert_eq!(netlist.elements.len(), 3);
+        assert_eq!(netlist.nodes, vec!["0", "in", "out"]);
+        assert_eq!(netlist.transient,
+                   Some(Transient {
+                       step: 5e-6,
+                       stop: 1e-3,
+                   }));
+
+        let v1 = netlist.entity("V1").unwrap();
+        let r1 = netlist.entity("r1").unwrap();
+        let c1 = netlist.entity("C1").unwrap();
+
+        assert_eq!(world.read::<VoltageSource>().pass().get(v1).unwrap().voltage, 5.0);
+        assert_eq!(world.read::<Resistor>().pass().get(r1).unwrap().resistance(), 1e3);
+        assert_eq!(world.read::<Capacitor>().pass().get(c1).unwrap().capacitance, 10e-6);
+
+        // voltage sources go from n- to n+
+        assert_eq!(node_indexes(&world, v1), vec![0, 1]);
+        assert_eq!(node_indexes(&world, r1), vec![1, 2]);
+        assert_eq!(node_indexes(&world, c1), vec![2, 0]);
+    }
+
+    #[test]
+    fn voltage_divider() {
+        let mut world = create_world();
+        let netlist = parse("Voltage divider
+V1 1 0 10
+R1 1 2 3k
+R2 2 GND 1k
+I1 2 0 1m
+",
+                            &mut world)
+            .unwrap();
+
+        let solution = solver::create_static_equation(&mut world).solve().unwrap();
+
+        let n1 = netlist.node_index("1").unwrap();
+        let n2 = netlist.node_index("2").unwrap();
+        assert_approx_eq!(solution.voltages()[n1], 10.0);
+        // 2.5V from the divider, less 0.75V from the current source
+        assert_approx_eq!(solution.voltages()[n2], 1.75);
+    }
+
+    #[test]
+    fn controlled_sources() {
+        let mut world = create_world();
+        let netlist = parse("Controlled sources
+* control sources can come after the sources they control
+H1 h 0 V1 100
+V1 in 0 1
+R1 in 0 100
+E1 e 0 in 0 2
+R2 e 0 1k
+G1 0 g in 0 1m
+R3 g 0 1k
+F1 0 f V1 2
+R4 f 0 100
+R5 h 0 1k
+",
+                            &mut world)
+            .unwrap();
+
+        let solution = solver::create_static_equation(&mut world).solve().unwrap();
+        let voltage = |node: &str| solution.voltages()[netlist.node_index(node).unwrap()];
+
+        assert_approx_eq!(voltage("e"), 2.0);
+        // 1mA from ground into g
+        assert_approx_eq!(voltage("g"), 1.0);
+        // SPICE's current through V1 is -10mA, as it flows out of n+
+        assert_approx_eq!(voltage("h"), -1.0);
+        assert_approx_eq!(voltage("f"), -2.0);
+    }
+
+    #[test]
+    fn time_varying_sources() {
+        let mut world = create_world();
+        let netlist = parse("Sources
+V1 1 0 SIN(0 5 1k)
+V2 2 0 PULSE(0 5 1u 1n 1n 10u 20u)
+I1 3 0 PWL(0 0 1m 1m
++ 2m 0)
+R1 1 2 1k
+R2 2 3 1k
+",
+                            &mut world)
+            .unwrap();
+
+        let waveforms = world.read::<Waveform>().pass();
+        match waveforms.get(netlist.entity("V1").unwrap()) {
+            Some(&Waveform::Sine { amplitude, frequency, .. }) => {
+                assert_eq!(amplitude, 5.0);
+                assert_eq!(frequency, 1e3);
+            }
+            _ => panic!("expected a sine source"),
+        }
+        match waveforms.get(netlist.entity("V2").unwrap()) {
+            Some(&Waveform::Pulse { width, period, .. }) => {
+                assert_eq!(width, 10e-6);
+                assert_eq!(period, 20e-6);
+            }
+            _ => panic!("expected a pulse source"),
+        }
+        match waveforms.get(netlist.entity("I1").unwrap()) {
+            Some(&Waveform::PiecewiseLinear(ref points)) => {
+                assert_eq!(points, &vec![(0.0, 0.0), (1e-3, 1e-3), (2e-3, 0.0)]);
+            }
+            _ => panic!("expected a piecewise linear source"),
+        }
+    }
+
+    #[test]
+    fn pulse_defaults() {
+        let mut world = create_world();
+        let netlist = parse("Pulse
+V1 1 0 PULSE(0 5 1u)
+R1 1 0 1k
+.tran 1n 1m
+",
+                            &mut world)
+            .unwrap();
+
+        match world.read::<Waveform>().pass().get(netlist.entity("V1").unwrap()) {
+            Some(&Waveform::Pulse { delay, rise, fall, width, period, .. }) => {
+                assert_eq!(delay, 1e-6);
+                assert_eq!((rise, fall), (1e-9, 1e-9));
+                assert_eq!((width, period), (1e-3, 1e-3));
+            }
+            _ => panic!("expected a pulse source"),
+        }
+
+        let netlist = parse("No .tran\nV1 1 0 PULSE(0 5)\nR1 1 0 1k\n", &mut world).unwrap();
+        match world.read::<Waveform>().pass().get(netlist.entity("V1").unwrap()) {
+            Some(&Waveform::Pulse { delay, rise, fall, width, period, .. }) => {
+                assert_eq!((delay, rise, fall), (0.0, 0.0, 0.0));
+                assert_eq!((width, period), (std::f64::INFINITY, std::f64::INFINITY));
+            }
+            _ => panic!("expected a pulse source"),
+        };
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let mut world = create_world();
+
+        let error = parse("Bad value\nR1 1 0 1k\n\nR2 1 0 abc\n", &mut world).unwrap_err();
+        assert_eq!(error.line, 4);
+
+        let error = parse("Bad element\n* comment\nQ1 1 2 3 model\n", &mut world).unwrap_err();
+        assert_eq!(error.line, 3);
+
+        let error = parse("Too few nodes\nR1 1\n+ 0 1k\nR2 1\n", &mut world).unwrap_err();
+        assert_eq!(error.line, 4);
+
+        let error = parse("Unsupported\nR1 1 0 1k\n.ac dec 10 1 1k\n", &mut world).unwrap_err();
+        assert_eq!(error.line, 3);
+
+        let error = parse("Duplicate\nR1 1 0 1k\nr1 1 0 2k\n", &mut world).unwrap_err();
+        assert_eq!(error.line, 3);
+
+        let error = parse("Unknown control\nH1 1 0 R1 1k\nR1 1 0 1k\n", &mut world).unwrap_err();
+        assert_eq!(error.line, 2);
+    }
+}
@@ -0,0 +1,66 @@
+use std::io;
+use specs;
+use output::TimeUnit;
+use output::signal_names;
+use solver::recorder::Recorder;
+
+// Write recorded samples as CSV, with a column for time followed by one for
+// each probe.
+pub fn write_csv<W: io::Write>(writer: &mut W,
+                               world: &specs::World,
+                               recorder: &Recorder,
+                               time_unit: TimeUnit)
+                               -> io::Result<()> {
+    let names = signal_names(world, recorder.probes());
+
+    write!(writer, "time ({})", time_unit.symbol())?;
+    for name in &names {
+        write!(writer, ",{}", quote(name))?;
+    }
+    writeln!(writer)?;
+
+    for sample in recorder.samples() {
+        write!(writer, "{}", sample.time * time_unit.per_second())?;
+        for value in &sample.values {
+            write!(writer, ",{}", value)?;
+        }
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+fn quote(field: &str) -> String {
+    if field.contains(',') || field.contains('"') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use output::tests::create_recorded_world;
+
+    #[test]
+    fn write() {
+        let (world, recorder) = create_recorded_world();
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &world, &recorder, TimeUnit::Microseconds).unwrap();
+
+        assert_eq!(String::from_utf8(csv).unwrap(),
+                   "time (us),v(2),i(Resistor R2)
+0,0,0
+1.5,2.5,0.0025
+3,2.5,0.0025
+");
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("v(1)"), "v(1)");
+        assert_eq!(quote("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}
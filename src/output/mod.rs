@@ -0,0 +1,125 @@
+mod csv;
+mod vcd;
+mod raw;
+
+pub use self::csv::write_csv;
+pub use self::vcd::write_vcd;
+pub use self::raw::write_raw;
+pub use self::raw::Plot;
+pub use self::raw::Format;
+pub use self::raw::Variable;
+pub use self::raw::VariableType;
+
+use std::collections::HashMap;
+use specs;
+use elements::CircuitElement;
+use netlist;
+use solver::recorder::Probe;
+
+// The unit of time used when writing results.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeUnit {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+    Picoseconds,
+    Femtoseconds,
+}
+
+impl TimeUnit {
+    // How many of this unit there are in a second.
+    pub fn per_second(&self) -> f64 {
+        match *self {
+            TimeUnit::Seconds => 1.0,
+            TimeUnit::Milliseconds => 1e3,
+            TimeUnit::Microseconds => 1e6,
+            TimeUnit::Nanoseconds => 1e9,
+            TimeUnit::Picoseconds => 1e12,
+            TimeUnit::Femtoseconds => 1e15,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            TimeUnit::Seconds => "s",
+            TimeUnit::Milliseconds => "ms",
+            TimeUnit::Microseconds => "us",
+            TimeUnit::Nanoseconds => "ns",
+            TimeUnit::Picoseconds => "ps",
+            TimeUnit::Femtoseconds => "fs",
+        }
+    }
+}
+
+impl Default for TimeUnit {
+    fn default() -> Self {
+        TimeUnit::Seconds
+    }
+}
+
+// A name for each probe's signal, e.g. `v(2)` for the voltage at node 2, or
+// `i(Resistor R1)` for the current through a resistor.
+//
+// Elements without a designator (e.g. wires) are named by their entity ID.
+pub fn signal_names(world: &specs::World, probes: &[Probe]) -> Vec<String> {
+    use specs::Gate;
+
+    let designators: HashMap<specs::Entity, String> =
+        netlist::designators(world).into_iter().collect();
+    let elements = world.read::<CircuitElement>().pass();
+
+    probes.iter()
+        .map(|&probe| match probe {
+            Probe::Voltage(node) => format!("v({})", node),
+            Probe::Current(entity) => {
+                let display_name = elements.get(entity)
+                    .map(|element| element.display_name())
+                    .unwrap_or("Element");
+                match designators.get(&entity) {
+                    Some(designator) => format!("i({} {})", display_name, designator),
+                    None => format!("i({} {})", display_name, entity.get_id()),
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use analysis::tests::create_world;
+    use solver::recorder::Capacity;
+    use solver::recorder::Recorder;
+
+    // A world with a voltage and current probe, recorded at 0, 1.5 and 3µs.
+    pub fn create_recorded_world() -> (specs::World, Recorder) {
+        let mut world = create_world();
+        let netlist = netlist::parse("Divider
+V1 in 0 5
+R1 in out 1k
+R2 out 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+        let probes = vec![Probe::Voltage(netlist.node_index("out").unwrap()),
+                          Probe::Current(netlist.entity("R2").unwrap())];
+
+        let mut recorder = Recorder::new(probes, Capacity::Unbounded);
+        for &(time, voltage) in &[(0.0, 0.0), (1.5e-6, 2.5), (3e-6, 2.5)] {
+            recorder.record(time, |probe| match probe {
+                Probe::Voltage(_) => Some(voltage),
+                Probe::Current(_) => Some(voltage / 1e3),
+            });
+        }
+        (world, recorder)
+    }
+
+    #[test]
+    fn names() {
+        let (world, recorder) = create_recorded_world();
+
+        assert_eq!(signal_names(&world, recorder.probes()),
+                   vec!["v(2)".to_owned(), "i(Resistor R2)".to_owned()]);
+    }
+}
@@ -0,0 +1,403 @@
+use std::io;
+use specs;
+use elements::voltage_source::VoltageSource;
+use elements::capacitor::Capacitor;
+use elements::vcvs::Vcvs;
+use elements::ccvs::Ccvs;
+use elements::opamp::OpAmp;
+use analysis::OperatingPoint;
+use analysis::dc;
+use solver::recorder::Probe;
+use solver::recorder::Recorder;
+use output::signal_names;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Ascii,
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VariableType {
+    Time,
+    Voltage,
+    Current,
+    Impedance,
+}
+
+impl VariableType {
+    fn name(&self) -> &'static str {
+        match *self {
+            VariableType::Time => "time",
+            VariableType::Voltage => "voltage",
+            VariableType::Current => "current",
+            VariableType::Impedance => "impedance",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub variable_type: VariableType,
+}
+
+impl Variable {
+    fn new(name: String, variable_type: VariableType) -> Self {
+        Variable {
+            name: name,
+            variable_type: variable_type,
+        }
+    }
+}
+
+// A single plot in a SPICE raw file.
+//
+// Node voltages are named `v(n)` by node index, and the currents of elements
+// with branches (e.g. voltage sources) `i(vn)` by the branch index assigned by
+// `create_static_equation` (or `assign_branches`).
+#[derive(Debug, Clone)]
+pub struct Plot {
+    pub title: String,
+    pub name: String,
+    pub variables: Vec<Variable>,
+    // The value of every variable at each point
+    pub points: Vec<Vec<f64>>,
+}
+
+impl Plot {
+    // Transient results, with a variable for time followed by each probe.
+    pub fn transient(world: &specs::World, title: &str, recorder: &Recorder) -> Self {
+        let branches = branch_indexes(world);
+        let names = signal_names(world, recorder.probes());
+
+        let mut variables = vec![Variable::new("time".to_owned(), VariableType::Time)];
+        for (&probe, name) in recorder.probes().iter().zip(names) {
+            variables.push(match probe {
+                Probe::Voltage(_) => Variable::new(name, VariableType::Voltage),
+                Probe::Current(entity) => {
+                    let name = match branches.iter().find(|&&(e, _)| e == entity) {
+                        Some(&(_, index)) => current_name(index),
+                        None => name.replace(char::is_whitespace, "_"),
+                    };
+                    Variable::new(name, VariableType::Current)
+                }
+            });
+        }
+
+        let points = recorder.samples()
+            .iter()
+            .map(|sample| {
+                let mut point = vec![sample.time];
+                point.extend_from_slice(&sample.values);
+                point
+            })
+            .collect();
+
+        Plot {
+            title: title.to_owned(),
+            name: "Transient Analysis".to_owned(),
+            variables: variables,
+            points: points,
+        }
+    }
+
+    // DC sweep results, with a variable for each swept parameter followed by
+    // every node voltage and branch current.
+    pub fn dc_sweep(world: &specs::World, title: &str, table: &dc::Table) -> Self {
+        let branches = branch_indexes(world);
+        let num_nodes = table.rows.first().map_or(0, |row| row.voltages.len());
+
+        let mut variables: Vec<Variable> = table.parameters
+            .iter()
+            .map(|&parameter| match parameter {
+                dc::Parameter::Voltage(_) => {
+                    Variable::new("v-sweep".to_owned(), VariableType::Voltage)
+                }
+                dc::Parameter::Current(_) => {
+                    Variable::new("i-sweep".to_owned(), VariableType::Current)
+                }
+                dc::Parameter::Resistance(_) => {
+                    Variable::new("res-sweep".to_owned(), VariableType::Impedance)
+                }
+            })
+            .collect();
+        variables.extend(voltage_variables(num_nodes));
+        variables.extend(current_variables(&branches));
+
+        let points = table.rows
+            .iter()
+            .map(|row| {
+                let mut point = row.values.clone();
+                point.extend_from_slice(without_ground(&row.voltages));
+                point.extend(branches.iter().map(|&(entity, _)| row.currents[&entity]));
+                point
+            })
+            .collect();
+
+        Plot {
+            title: title.to_owned(),
+            name: "DC transfer characteristic".to_owned(),
+            variables: variables,
+            points: points,
+        }
+    }
+
+    // The operating point, as a single point with every node voltage and
+    // branch current.
+    pub fn operating_point(world: &specs::World, title: &str, op: &OperatingPoint) -> Self {
+        let branches = branch_indexes(world);
+
+        let mut variables = voltage_variables(op.voltages.len());
+        variables.extend(current_variables(&branches));
+
+        let mut point = without_ground(&op.voltages).to_vec();
+        point.extend(branches.iter().map(|&(entity, _)| {
+            op.element(entity).and_then(|state| state.current).unwrap_or(0.0)
+        }));
+
+        Plot {
+            title: title.to_owned(),
+            name: "Operating Point".to_owned(),
+            variables: variables,
+            points: vec![point],
+        }
+    }
+}
+
+// Every element with a branch, and its branch index, in index order.
+fn branch_indexes(world: &specs::World) -> Vec<(specs::Entity, usize)> {
+    use specs::Join;
+    use specs::Gate;
+
+    let entities = world.entities();
+    let v_sources = world.read::<VoltageSource>().pass();
+    let capacitors = world.read::<Capacitor>().pass();
+    let vcvs = world.read::<Vcvs>().pass();
+    let ccvs = world.read::<Ccvs>().pass();
+    let opamps = world.read::<OpAmp>().pass();
+
+    let mut branches: Vec<(specs::Entity, usize)> = (&entities, &v_sources)
+        .join()
+        .map(|(entity, vi)| (entity, vi.index))
+        .collect();
+    branches.extend((&entities, &capacitors)
+        .join()
+        .filter(|&(_, cap)| cap.uses_branch())
+        .map(|(entity, cap)| (entity, cap.voltage_source.index)));
+    branches.extend((&entities, &vcvs).join().map(|(entity, source)| (entity, source.index)));
+    branches.extend((&entities, &ccvs).join().map(|(entity, source)| (entity, source.index)));
+    branches.extend((&entities, &opamps).join().map(|(entity, opamp)| (entity, opamp.index)));
+    branches.sort_by_key(|&(_, index)| index);
+    branches
+}
+
+fn current_name(branch_index: usize) -> String {
+    format!("i(v{})", branch_index)
+}
+
+// Every node except ground.
+// Node voltages other than ground's, which may be missing if there are no
+// nodes at all.
+fn without_ground(voltages: &[f64]) -> &[f64] {
+    voltages.get(1..).unwrap_or(&[])
+}
+
+fn voltage_variables(num_nodes: usize) -> Vec<Variable> {
+    (1..num_nodes)
+        .map(|node| Variable::new(format!("v({})", node), VariableType::Voltage))
+        .collect()
+}
+
+fn current_variables(branches: &[(specs::Entity, usize)]) -> Vec<Variable> {
+    branches.iter()
+        .map(|&(_, index)| Variable::new(current_name(index), VariableType::Current))
+        .collect()
+}
+
+// Write a plot in the SPICE raw format read by ngspice and other tools.
+pub fn write_raw<W: io::Write>(writer: &mut W, plot: &Plot, format: Format) -> io::Result<()> {
+    writeln!(writer, "Title: {}", plot.title)?;
+    writeln!(writer, "Plotname: {}", plot.name)?;
+    writeln!(writer, "Flags: real")?;
+    writeln!(writer, "No. Variables: {}", plot.variables.len())?;
+    writeln!(writer, "No. Points: {}", plot.points.len())?;
+    writeln!(writer, "Variables:")?;
+    for (i, variable) in plot.variables.iter().enumerate() {
+        writeln!(writer,
+                 "\t{}\t{}\t{}",
+                 i,
+                 variable.name,
+                 variable.variable_type.name())?;
+    }
+
+    match format {
+        Format::Ascii => {
+            writeln!(writer, "Values:")?;
+            for (i, point) in plot.points.iter().enumerate() {
+                write!(writer, " {}", i)?;
+                for value in point {
+                    writeln!(writer, "\t{:.15e}", value)?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        Format::Binary => {
+            writeln!(writer, "Binary:")?;
+            for point in &plot.points {
+                for value in point {
+                    let bits = value.to_bits();
+                    let bytes: Vec<u8> = (0..8).map(|i| (bits >> (8 * i)) as u8).collect();
+                    writer.write_all(&bytes)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analysis::operating_point;
+    use analysis::dc::dc_sweep;
+    use analysis::tests::create_world;
+    use output::tests::create_recorded_world;
+    use netlist;
+
+    #[test]
+    fn transient_ascii() {
+        let (world, recorder) = create_recorded_world();
+        let plot = Plot::transient(&world, "Divider", &recorder);
+
+        let mut raw = Vec::new();
+        write_raw(&mut raw, &plot, Format::Ascii).unwrap();
+
+        assert_eq!(String::from_utf8(raw).unwrap(),
+                   "Title: Divider
+Plotname: Transient Analysis
+Flags: real
+No. Variables: 3
+No. Points: 3
+Variables:
+\t0\ttime\ttime
+\t1\tv(2)\tvoltage
+\t2\ti(Resistor_R2)\tcurrent
+Values:
+ 0\t0.000000000000000e0
+\t0.000000000000000e0
+\t0.000000000000000e0
+
+ 1\t1.500000000000000e-6
+\t2.500000000000000e0
+\t2.500000000000000e-3
+
+ 2\t3.000000000000000e-6
+\t2.500000000000000e0
+\t2.500000000000000e-3
+
+");
+    }
+
+    #[test]
+    fn binary() {
+        let (world, recorder) = create_recorded_world();
+        let plot = Plot::transient(&world, "Divider", &recorder);
+
+        let mut raw = Vec::new();
+        write_raw(&mut raw, &plot, Format::Binary).unwrap();
+
+        let header = b"Binary:\n";
+        let values_start = raw.windows(header.len()).position(|w| w == header).unwrap() +
+                           header.len();
+        let values = &raw[values_start..];
+        assert_eq!(values.len(), 3 * 3 * 8);
+
+        // the voltage at the second point, little-endian
+        let bits = values[32..40]
+            .iter()
+            .enumerate()
+            .fold(0u64, |bits, (i, &byte)| bits | (u64::from(byte) << (8 * i)));
+        assert_eq!(f64::from_bits(bits), 2.5);
+    }
+
+    #[test]
+    fn operating_point_and_dc_sweep() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Divider
+V1 in 0 5
+R1 in out 1k
+R2 out 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+
+        let op = operating_point(&mut world).unwrap();
+        let plot = Plot::operating_point(&world, "Divider", &op);
+        let names: Vec<&str> = plot.variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["v(1)", "v(2)", "i(v0)"]);
+        assert_approx_eq!(plot.points[0][1], 2.5);
+        assert_approx_eq!(plot.points[0][2], 2.5e-3);
+
+        let sweep = dc::Sweep::new(dc::Parameter::Voltage(netlist.entity("V1").unwrap()),
+                                   dc::Values::List(vec![2.0, 4.0]));
+        let table = dc_sweep(&mut world, &sweep, None).unwrap();
+        let plot = Plot::dc_sweep(&world, "Divider", &table);
+        let names: Vec<&str> = plot.variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["v-sweep", "v(1)", "v(2)", "i(v0)"]);
+        assert_eq!(plot.points.len(), 2);
+        assert_approx_eq!(plot.points[1][2], 2.0);
+    }
+
+    #[test]
+    fn no_nodes() {
+        let world = create_world();
+
+        let op = OperatingPoint {
+            voltages: vec![],
+            elements: Default::default(),
+        };
+        let plot = Plot::operating_point(&world, "Empty", &op);
+        assert!(plot.variables.is_empty());
+        assert_eq!(plot.points, vec![Vec::<f64>::new()]);
+
+        let table = dc::Table {
+            parameters: vec![],
+            rows: vec![dc::Row {
+                           values: vec![],
+                           voltages: vec![],
+                           currents: Default::default(),
+                       }],
+        };
+        let plot = Plot::dc_sweep(&world, "Empty", &table);
+        assert!(plot.variables.is_empty());
+        assert_eq!(plot.points, vec![Vec::<f64>::new()]);
+    }
+
+    #[test]
+    fn every_branch_has_a_current() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Amplifier
+V1 in 0 1
+E1 out 0 in 0 2
+R1 out 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+
+        let op = operating_point(&mut world).unwrap();
+        let plot = Plot::operating_point(&world, "Amplifier", &op);
+        let names: Vec<&str> = plot.variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["v(1)", "v(2)", "i(v0)", "i(v1)"]);
+        assert_eq!(plot.points[0].len(), names.len());
+        assert_approx_eq!(plot.points[0][3], 2e-3);
+
+        let sweep = dc::Sweep::new(dc::Parameter::Voltage(netlist.entity("V1").unwrap()),
+                                   dc::Values::List(vec![1.0, 2.0]));
+        let table = dc_sweep(&mut world, &sweep, None).unwrap();
+        let plot = Plot::dc_sweep(&world, "Amplifier", &table);
+        assert_eq!(plot.variables.len(), 5);
+        assert_approx_eq!(plot.points[1][4], 4e-3);
+    }
+}
@@ -0,0 +1,146 @@
+use std::io;
+use specs;
+use output::TimeUnit;
+use output::signal_names;
+use solver::recorder::Recorder;
+
+// Write recorded samples as a Value Change Dump, with a real-valued variable
+// for each probe.
+//
+// Times are rounded to whole multiples of `time_unit`. Only values which
+// change are written after the first sample, and samples where nothing
+// changed are left out. Values which weren't recorded (i.e. NaN) are written
+// as unknown.
+pub fn write_vcd<W: io::Write>(writer: &mut W,
+                               world: &specs::World,
+                               recorder: &Recorder,
+                               time_unit: TimeUnit)
+                               -> io::Result<()> {
+    let names = signal_names(world, recorder.probes());
+
+    writeln!(writer, "$version rusty_circuit $end")?;
+    writeln!(writer, "$timescale 1 {} $end", time_unit.symbol())?;
+    writeln!(writer, "$scope module circuit $end")?;
+    for (i, name) in names.iter().enumerate() {
+        writeln!(writer,
+                 "$var real 64 {} {} $end",
+                 identifier(i),
+                 name.replace(char::is_whitespace, "_"))?;
+    }
+    writeln!(writer, "$upscope $end")?;
+    writeln!(writer, "$enddefinitions $end")?;
+
+    let mut prev_time = None;
+    let mut prev_values: Vec<Option<f64>> = vec![None; names.len()];
+    for sample in recorder.samples() {
+        let changes: Vec<(usize, f64)> = sample.values
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|&(i, value)| prev_values[i].map_or(true, |prev| changed(prev, value)))
+            .collect();
+        if changes.is_empty() {
+            continue;
+        }
+
+        let time = (sample.time * time_unit.per_second()).round() as u64;
+        if prev_time.map_or(true, |prev| time > prev) {
+            writeln!(writer, "#{}", time)?;
+            prev_time = Some(time);
+        }
+
+        for (i, value) in changes {
+            if value.is_nan() {
+                writeln!(writer, "rx {}", identifier(i))?;
+            } else {
+                writeln!(writer, "r{} {}", value, identifier(i))?;
+            }
+            prev_values[i] = Some(value);
+        }
+    }
+
+    Ok(())
+}
+
+// Unknown values (NaN) are the same as each other, unlike with `!=`.
+fn changed(prev: f64, value: f64) -> bool {
+    if prev.is_nan() || value.is_nan() {
+        prev.is_nan() != value.is_nan()
+    } else {
+        prev != value
+    }
+}
+
+// A short identifier code, made up of printable ASCII characters.
+fn identifier(index: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - b'!') as usize + 1;
+
+    let mut code = String::new();
+    let mut index = index;
+    loop {
+        code.push((FIRST + (index % COUNT) as u8) as char);
+        index /= COUNT;
+        if index == 0 {
+            break;
+        }
+        index -= 1;
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use output::tests::create_recorded_world;
+    use solver::recorder::Probe;
+    use solver::recorder::Capacity;
+
+    #[test]
+    fn write() {
+        let (world, recorder) = create_recorded_world();
+
+        let mut vcd = Vec::new();
+        write_vcd(&mut vcd, &world, &recorder, TimeUnit::Nanoseconds).unwrap();
+
+        assert_eq!(String::from_utf8(vcd).unwrap(),
+                   "$version rusty_circuit $end
+$timescale 1 ns $end
+$scope module circuit $end
+$var real 64 ! v(2) $end
+$var real 64 \" i(Resistor_R2) $end
+$upscope $end
+$enddefinitions $end
+#0
+r0 !
+r0 \"
+#1500
+r2.5 !
+r0.0025 \"
+");
+    }
+
+    #[test]
+    fn unknown_values() {
+        let (world, _) = create_recorded_world();
+        let mut recorder = Recorder::new(vec![Probe::Voltage(1)], Capacity::Unbounded);
+        for &(time, voltage) in &[(0.0, None), (1e-9, None), (2e-9, Some(1.0)), (3e-9, None)] {
+            recorder.record(time, |_| voltage);
+        }
+
+        let mut vcd = Vec::new();
+        write_vcd(&mut vcd, &world, &recorder, TimeUnit::Nanoseconds).unwrap();
+
+        let vcd = String::from_utf8(vcd).unwrap();
+        let values = &vcd[vcd.find("#0").unwrap()..];
+        assert_eq!(values, "#0\nrx !\n#2\nr1 !\n#3\nrx !\n");
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(identifier(0), "!");
+        assert_eq!(identifier(93), "~");
+        assert_eq!(identifier(94), "!!");
+        assert_eq!(identifier(95), "\"!");
+    }
+}
@@ -0,0 +1,150 @@
+pub const DEFAULT_RELTOL: f64 = 1e-3;
+pub const DEFAULT_VNTOL: f64 = 1e-6; // 1µV
+pub const DEFAULT_ABSTOL: f64 = 1e-12; // 1pA
+pub const DEFAULT_MIN_STEP: f64 = 1e-9; // 1ns
+pub const DEFAULT_MAX_STEP: f64 = 1e-3; // 1ms
+
+// How much a timestep can change by in one go.
+const MAX_SHRINK: f64 = 0.1;
+const MAX_GROWTH: f64 = 2.0;
+// Aim a little under the tolerance, so the next step is less likely to be
+// rejected.
+const SAFETY: f64 = 0.9;
+
+// Settings for adaptive timestep control.
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    // A step is accepted when the estimated local truncation error of every
+    // capacitor voltage is under `reltol * |v| + vntol`, and of every
+    // inductor current is under `reltol * |i| + abstol`
+    pub reltol: f64,
+    pub vntol: f64,
+    pub abstol: f64,
+
+    pub min_step: f64,
+    pub max_step: f64,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            reltol: DEFAULT_RELTOL,
+            vntol: DEFAULT_VNTOL,
+            abstol: DEFAULT_ABSTOL,
+
+            min_step: DEFAULT_MIN_STEP,
+            max_step: DEFAULT_MAX_STEP,
+        }
+    }
+}
+
+impl Settings {
+    pub fn clamp(&self, timestep: f64) -> f64 {
+        timestep.max(self.min_step).min(self.max_step)
+    }
+}
+
+// Estimate the local truncation error of a step as a fraction of the
+// tolerance, i.e. the step should be rejected if it's over 1.
+//
+// The error is estimated from the change in the state's derivative over the
+// step, as `h/2 * |dx/dt(n+1) - dx/dt(n)|`. This is the error of a first
+// order method, so is conservative for trapezoidal integration.
+fn error_ratio(previous: f64,
+               value: f64,
+               previous_derivative: f64,
+               derivative: f64,
+               timestep: f64,
+               absolute_tolerance: f64,
+               settings: &Settings)
+               -> f64 {
+    let error = (timestep / 2.0) * (derivative - previous_derivative).abs();
+    let tolerance = settings.reltol * previous.abs().max(value.abs()) + absolute_tolerance;
+
+    error / tolerance
+}
+
+// For a capacitor, the state is its voltage and `dV/dt = I/C`.
+pub fn capacitor_error(settings: &Settings,
+                       capacitance: f64,
+                       (previous_voltage, voltage): (f64, f64),
+                       (previous_current, current): (f64, f64),
+                       timestep: f64)
+                       -> f64 {
+    error_ratio(previous_voltage,
+                voltage,
+                previous_current / capacitance,
+                current / capacitance,
+                timestep,
+                settings.vntol,
+                settings)
+}
+
+// For an inductor, the state is its current and `dI/dt = V/L`.
+pub fn inductor_error(settings: &Settings,
+                      inductance: f64,
+                      (previous_voltage, voltage): (f64, f64),
+                      (previous_current, current): (f64, f64),
+                      timestep: f64)
+                      -> f64 {
+    error_ratio(previous_current,
+                current,
+                previous_voltage / inductance,
+                voltage / inductance,
+                timestep,
+                settings.abstol,
+                settings)
+}
+
+// The timestep to try next, given the error ratio of the last attempt.
+//
+// The estimated error scales with the square of the timestep.
+pub fn next_timestep(settings: &Settings, timestep: f64, error_ratio: f64) -> f64 {
+    let factor = if error_ratio > 0.0 {
+        (SAFETY / error_ratio.sqrt()).max(MAX_SHRINK).min(MAX_GROWTH)
+    } else {
+        MAX_GROWTH
+    };
+
+    settings.clamp(timestep * factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_derivative_has_no_error() {
+        let settings = Settings::default();
+        let error = capacitor_error(&settings, 1e-6, (1.0, 2.0), (1e-3, 1e-3), 1e-3);
+
+        assert_eq!(error, 0.0);
+        assert_eq!(next_timestep(&settings, 1e-4, error), 2e-4);
+    }
+
+    #[test]
+    fn changing_derivative() {
+        let settings = Settings::default();
+        // dV/dt goes from 1000 to 1100 V/s over 1ms, an error of 0.05V
+        let error = capacitor_error(&settings, 1e-6, (1.0, 2.0), (1e-3, 1.1e-3), 1e-3);
+
+        assert_approx_eq!(error, 0.05 / (2e-3 + 1e-6));
+        assert!(next_timestep(&settings, 1e-3, error) < 1e-3);
+    }
+
+    #[test]
+    fn inductor_tolerance_is_on_current() {
+        let settings = Settings::default();
+        let error = inductor_error(&settings, 1e-3, (1.0, 1.1), (1.0, 1.0), 1e-6);
+
+        // dI/dt changes by 100A/s, an error of 50µA
+        assert_approx_eq!(error, 50e-6 / (1e-3 + 1e-12));
+    }
+
+    #[test]
+    fn timesteps_are_clamped() {
+        let settings = Settings::default();
+
+        assert_eq!(next_timestep(&settings, settings.max_step, 0.0), settings.max_step);
+        assert_eq!(next_timestep(&settings, settings.min_step, 100.0), settings.min_step);
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use specs;
+use elements::Nodes;
+use elements::Position;
+use elements::Terminals;
+
+// Assign node indexes to every element with `Terminals`, based on the
+// positions of its connectors.
+//
+// Connectors at the same position share a node. Nodes without a connector
+// (e.g. the reference side of `Ground`) are given index 0, the reference
+// node. If there are no such nodes, the first connector's node is used as
+// the reference instead.
+//
+// Elements without `Terminals` are left untouched.
+//
+// Should be called before `create_static_equation` whenever the circuit is
+// modified.
+pub fn assign_nodes(world: &mut specs::World) {
+    use specs::Join;
+    use specs::Gate;
+
+    let terminals = world.read::<Terminals>().pass();
+    let mut nodes_ticket = world.write::<Nodes>().pass();
+
+    let has_reference = (&terminals, &nodes_ticket)
+        .join()
+        .any(|(&Terminals(ref ps), &Nodes(ref ns))| ps.len() < ns.len());
+
+    let mut next_index = if has_reference { 1 } else { 0 };
+    let mut indexes: HashMap<Position, usize> = HashMap::new();
+
+    for (&Terminals(ref ps), &mut Nodes(ref mut ns)) in (&terminals, &mut nodes_ticket).join() {
+        for (i, node) in ns.iter_mut().enumerate() {
+            node.index = match ps.get(i) {
+                Some(position) => {
+                    *indexes.entry(*position).or_insert_with(|| {
+                        let index = next_index;
+                        next_index += 1;
+                        index
+                    })
+                }
+                None => 0,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::Gate;
+    use analysis::tests::create_world;
+
+    fn create_element(world: &mut specs::World,
+                      terminals: Vec<Position>,
+                      num_nodes: usize)
+                      -> specs::Entity {
+        world.create_now()
+            .with(Nodes::new(num_nodes))
+            .with(Terminals(terminals))
+            .build()
+    }
+
+    fn indexes(world: &specs::World, entity: specs::Entity) -> Vec<usize> {
+        let nodes = world.read::<Nodes>().pass();
+        match nodes.get(entity) {
+            Some(&Nodes(ref ns)) => ns.iter().map(|n| n.index).collect(),
+            None => panic!("oh no"),
+        }
+    }
+
+    #[test]
+    fn coincident_terminals_share_a_node() {
+        let mut world = create_world();
+        let a = create_element(&mut world, vec![Position::new(0, 0), Position::new(0, 1)], 2);
+        let b = create_element(&mut world, vec![Position::new(0, 1), Position::new(0, 2)], 2);
+        let c = create_element(&mut world, vec![Position::new(0, 2), Position::new(0, 0)], 2);
+
+        assign_nodes(&mut world);
+
+        assert_eq!(indexes(&world, a), vec![0, 1]);
+        assert_eq!(indexes(&world, b), vec![1, 2]);
+        assert_eq!(indexes(&world, c), vec![2, 0]);
+    }
+
+    #[test]
+    fn unconnected_nodes_are_reference() {
+        let mut world = create_world();
+        let a = create_element(&mut world, vec![Position::new(0, 0), Position::new(0, 1)], 2);
+        let ground = create_element(&mut world, vec![Position::new(0, 1)], 2);
+
+        assign_nodes(&mut world);
+
+        assert_eq!(indexes(&world, a), vec![1, 2]);
+        assert_eq!(indexes(&world, ground), vec![2, 0]);
+    }
+
+    #[test]
+    fn elements_without_terminals_are_untouched() {
+        let mut world = create_world();
+        let a = create_element(&mut world, vec![Position::new(0, 0), Position::new(0, 1)], 2);
+        let b = world.create_now().with(Nodes::new(2)).build();
+        {
+            let mut nodes = world.write::<Nodes>().pass();
+            match nodes.get_mut(b) {
+                Some(&mut Nodes(ref mut ns)) => {
+                    ns[0].index = 5;
+                    ns[1].index = 6;
+                }
+                None => panic!("oh no"),
+            }
+        }
+
+        assign_nodes(&mut world);
+
+        assert_eq!(indexes(&world, a), vec![0, 1]);
+        assert_eq!(indexes(&world, b), vec![5, 6]);
+    }
+}
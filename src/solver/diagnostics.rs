@@ -0,0 +1,518 @@
+use std::collections::VecDeque;
+use specs;
+use elements::Nodes;
+use elements::resistor::Resistor;
+use elements::capacitor::Capacitor;
+use elements::inductor::Inductor;
+use elements::diode::Diode;
+use elements::bjt::Bjt;
+use elements::mosfet::Mosfet;
+use elements::voltage_source::VoltageSource;
+use elements::vcvs::Vcvs;
+use elements::ccvs::Ccvs;
+use elements::opamp::OpAmp;
+use elements::opamp::Model;
+use elements::switch::Switch;
+use solver::equation::Error;
+use solver::count_nodes;
+
+// How an element conducts at DC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conduction {
+    VoltageSource,
+    // e.g. resistors, inductors and diodes
+    Conducting,
+    // e.g. capacitors and current sources
+    Open,
+}
+
+// An element's terminals, by node index, and how it conducts between the
+// first two of them. Any other terminals (e.g. the control terminals of a
+// controlled source) don't conduct.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub entity: specs::Entity,
+    pub nodes: Vec<usize>,
+    pub conduction: Conduction,
+}
+
+impl Connection {
+    pub fn new(entity: specs::Entity, nodes: &Nodes, conduction: Conduction) -> Self {
+        let &Nodes(ref ns) = nodes;
+        Connection {
+            entity: entity,
+            nodes: ns.iter().map(|node| node.index).collect(),
+            conduction: conduction,
+        }
+    }
+
+    // A controlled source, which conducts between its output terminals (by
+    // position in `Nodes`). Its control terminals, if any, don't conduct.
+    pub fn controlled_source(entity: specs::Entity,
+                             nodes: &Nodes,
+                             node_indexes: (usize, usize),
+                             conduction: Conduction)
+                             -> Self {
+        let &Nodes(ref ns) = nodes;
+        let outputs = [node_indexes.0, node_indexes.1];
+        let controls = (0..ns.len()).filter(|i| !outputs.contains(i));
+        Connection {
+            entity: entity,
+            nodes: outputs.iter().cloned().chain(controls).map(|i| ns[i].index).collect(),
+            conduction: conduction,
+        }
+    }
+
+    // An op-amp's output, which is driven from ground. Its inputs don't
+    // conduct.
+    pub fn opamp(entity: specs::Entity, nodes: &Nodes, opamp: &OpAmp) -> Self {
+        let &Nodes(ref ns) = nodes;
+        let conduction = match opamp.model {
+            Model::Macro(ref model) if model.output_resistance > 0.0 => Conduction::Conducting,
+            _ => Conduction::VoltageSource,
+        };
+        Connection {
+            entity: entity,
+            nodes: vec![0,
+                        ns[opamp.output_node_index].index,
+                        ns[opamp.input_node_indexes.0].index,
+                        ns[opamp.input_node_indexes.1].index],
+            conduction: conduction,
+        }
+    }
+
+    // A transistor's junctions, which conduct from its base to each of its
+    // other terminals.
+    pub fn bjt(entity: specs::Entity, nodes: &Nodes, bjt: &Bjt) -> Vec<Self> {
+        let &Nodes(ref ns) = nodes;
+        let collector = ns[bjt.node_indexes.0].index;
+        let base = ns[bjt.node_indexes.1].index;
+        let emitter = ns[bjt.node_indexes.2].index;
+
+        [collector, emitter]
+            .iter()
+            .map(|&node| {
+                Connection {
+                    entity: entity,
+                    nodes: vec![base, node],
+                    conduction: Conduction::Conducting,
+                }
+            })
+            .collect()
+    }
+
+    // A MOSFET's channel, and the body, which conduct to its source. Its gate
+    // doesn't conduct.
+    pub fn mosfet(entity: specs::Entity, nodes: &Nodes, mosfet: &Mosfet) -> Vec<Self> {
+        let &Nodes(ref ns) = nodes;
+        let drain = ns[mosfet.node_indexes.0].index;
+        let gate = ns[mosfet.node_indexes.1].index;
+        let source = ns[mosfet.node_indexes.2].index;
+        let body = ns[mosfet.node_indexes.3].index;
+
+        [(drain, Conduction::Conducting), (body, Conduction::Conducting), (gate, Conduction::Open)]
+            .iter()
+            .map(|&(node, conduction)| {
+                Connection {
+                    entity: entity,
+                    nodes: vec![node, source],
+                    conduction: conduction,
+                }
+            })
+            .collect()
+    }
+
+    // A switch's contacts, which conduct from its common terminal whether
+    // they're made or not.
+    pub fn switch(entity: specs::Entity, nodes: &Nodes, switch: &Switch) -> Vec<Self> {
+        let &Nodes(ref ns) = nodes;
+        switch.contacts()
+            .iter()
+            .map(|&(node, _)| {
+                Connection {
+                    entity: entity,
+                    nodes: vec![ns[0].index, ns[node].index],
+                    conduction: Conduction::Conducting,
+                }
+            })
+            .collect()
+    }
+}
+
+// Whether capacitors and inductors are open and shorted, as at DC, or
+// conduct through their companion models, as during a transient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Analysis {
+    Dc,
+    Transient,
+}
+
+// The components of an element which decide how it conducts, if it has them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Element<'a> {
+    pub voltage_source: Option<&'a VoltageSource>,
+    pub resistor: Option<&'a Resistor>,
+    pub capacitor: Option<&'a Capacitor>,
+    pub inductor: Option<&'a Inductor>,
+    pub diode: Option<&'a Diode>,
+    pub vcvs: Option<&'a Vcvs>,
+    pub ccvs: Option<&'a Ccvs>,
+    pub opamp: Option<&'a OpAmp>,
+    pub bjt: Option<&'a Bjt>,
+    pub mosfet: Option<&'a Mosfet>,
+    pub switch: Option<&'a Switch>,
+}
+
+impl<'a> Element<'a> {
+    // Anything else, e.g. current sources and the outputs of voltage
+    // controlled current sources, is open.
+    pub fn connections(&self,
+                       entity: specs::Entity,
+                       nodes: &Nodes,
+                       analysis: Analysis)
+                       -> Vec<Connection> {
+        if let Some(opamp) = self.opamp {
+            return vec![Connection::opamp(entity, nodes, opamp)];
+        }
+        if let Some(bjt) = self.bjt {
+            return Connection::bjt(entity, nodes, bjt);
+        }
+        if let Some(mosfet) = self.mosfet {
+            return Connection::mosfet(entity, nodes, mosfet);
+        }
+        if let Some(switch) = self.switch {
+            return Connection::switch(entity, nodes, switch);
+        }
+        if let Some(source) = self.vcvs {
+            return vec![Connection::controlled_source(entity,
+                                                      nodes,
+                                                      source.node_indexes,
+                                                      Conduction::VoltageSource)];
+        }
+        if let Some(source) = self.ccvs {
+            return vec![Connection::controlled_source(entity,
+                                                      nodes,
+                                                      source.node_indexes,
+                                                      Conduction::VoltageSource)];
+        }
+        let conduction = if self.voltage_source.is_some() {
+            Conduction::VoltageSource
+        } else if self.resistor.is_some() || self.inductor.is_some() || self.diode.is_some() ||
+                  (self.capacitor.is_some() && analysis == Analysis::Transient) {
+            Conduction::Conducting
+        } else {
+            Conduction::Open
+        };
+        vec![Connection::new(entity, nodes, conduction)]
+    }
+}
+
+// Find structural problems which make the circuit equation singular:
+//
+// - nodes with no DC path to ground, i.e. only connected through capacitors
+//   or current sources, or not at all
+// - loops made up only of voltage sources (including wires)
+//
+// Returns an `Error::FloatingNodes` for all floating nodes, and an
+// `Error::VoltageSourceLoop` for each loop. Node indexes must already be
+// assigned.
+pub fn diagnose(world: &specs::World) -> Vec<Error> {
+    use specs::Join;
+    use specs::Gate;
+
+    let num_nodes = count_nodes(world);
+
+    let entities = world.entities();
+    let nodes_ticket = world.read::<Nodes>().pass();
+    let v_sources = world.read::<VoltageSource>().pass();
+    let resistors = world.read::<Resistor>().pass();
+    let capacitors = world.read::<Capacitor>().pass();
+    let inductors = world.read::<Inductor>().pass();
+    let diodes = world.read::<Diode>().pass();
+    let vcvs = world.read::<Vcvs>().pass();
+    let ccvs = world.read::<Ccvs>().pass();
+    let opamps = world.read::<OpAmp>().pass();
+    let bjts = world.read::<Bjt>().pass();
+    let mosfets = world.read::<Mosfet>().pass();
+    let switches = world.read::<Switch>().pass();
+
+    let connections: Vec<Connection> = (&entities, &nodes_ticket)
+        .join()
+        .flat_map(|(entity, nodes)| {
+            let element = Element {
+                voltage_source: v_sources.get(entity),
+                resistor: resistors.get(entity),
+                capacitor: capacitors.get(entity),
+                inductor: inductors.get(entity),
+                diode: diodes.get(entity),
+                vcvs: vcvs.get(entity),
+                ccvs: ccvs.get(entity),
+                opamp: opamps.get(entity),
+                bjt: bjts.get(entity),
+                mosfet: mosfets.get(entity),
+                switch: switches.get(entity),
+            };
+            element.connections(entity, nodes, Analysis::Dc)
+        })
+        .collect();
+
+    diagnose_connections(num_nodes, &connections)
+}
+
+// As `diagnose`, for when the world isn't available, e.g. inside a System.
+pub fn diagnose_connections(num_nodes: usize, connections: &[Connection]) -> Vec<Error> {
+    let mut errors = Vec::new();
+
+    // Voltage sources, tracking the spanning forest they make so that any
+    // loop can be traced back through it
+    let mut sources = DisjointSet::new(num_nodes);
+    let mut forest: Vec<Vec<(usize, specs::Entity)>> = vec![Vec::new(); num_nodes];
+    for connection in connections {
+        if connection.conduction != Conduction::VoltageSource {
+            continue;
+        }
+        let from = connection.nodes[0];
+        let to = connection.nodes[1];
+
+        if sources.union(from, to) {
+            forest[from].push((to, connection.entity));
+            forest[to].push((from, connection.entity));
+        } else {
+            let mut loop_entities = path(&forest, from, to);
+            loop_entities.push(connection.entity);
+            errors.push(Error::VoltageSourceLoop { entities: loop_entities });
+        }
+    }
+
+    // Everything which conducts at DC
+    let mut conducting = DisjointSet::new(num_nodes);
+    for connection in connections {
+        if connection.conduction != Conduction::Open {
+            conducting.union(connection.nodes[0], connection.nodes[1]);
+        }
+    }
+
+    let floating: Vec<usize> = (1..num_nodes)
+        .filter(|&node| conducting.find(node) != conducting.find(0))
+        .collect();
+    if !floating.is_empty() {
+        let mut floating_entities: Vec<specs::Entity> = connections.iter()
+            .filter(|connection| connection.nodes.iter().any(|node| floating.contains(node)))
+            .map(|connection| connection.entity)
+            .collect();
+        // elements with several connections are listed once
+        floating_entities.dedup();
+        errors.insert(0,
+                      Error::FloatingNodes {
+                          nodes: floating,
+                          entities: floating_entities,
+                      });
+    }
+
+    errors
+}
+
+// Replace an unsolvable circuit error with a more specific one, if the
+// circuit has any structural problems.
+pub fn explain(world: &specs::World, error: Error) -> Error {
+    match error {
+        Error::Unsolvable(_) => diagnose(world).into_iter().next().unwrap_or(error),
+        _ => error,
+    }
+}
+
+// The entities along the path between two nodes in a forest.
+fn path(forest: &[Vec<(usize, specs::Entity)>],
+        from: usize,
+        to: usize)
+        -> Vec<specs::Entity> {
+    let mut previous: Vec<Option<(usize, specs::Entity)>> = vec![None; forest.len()];
+    let mut visited = vec![false; forest.len()];
+    let mut queue = VecDeque::new();
+    visited[from] = true;
+    queue.push_back(from);
+
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            break;
+        }
+        for &(next, entity) in &forest[node] {
+            if !visited[next] {
+                visited[next] = true;
+                previous[next] = Some((node, entity));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut entities = Vec::new();
+    let mut node = to;
+    while let Some((prev, entity)) = previous[node] {
+        entities.push(entity);
+        node = prev;
+    }
+    entities.reverse();
+    entities
+}
+
+#[derive(Debug)]
+struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(size: usize) -> Self {
+        DisjointSet { parents: (0..size).collect() }
+    }
+
+    fn find(&mut self, x: usize) -> usize {
+        let mut root = x;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut x = x;
+        while self.parents[x] != root {
+            let next = self.parents[x];
+            self.parents[x] = root;
+            x = next;
+        }
+        root
+    }
+
+    // Returns false if `a` and `b` were already in the same set.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let root_a = self.find(a);
+        let root_b = self.find(b);
+        if root_a == root_b {
+            return false;
+        }
+        self.parents[root_a] = root_b;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analysis::tests::create_world;
+    use netlist;
+
+    #[test]
+    fn no_problems() {
+        let mut world = create_world();
+        netlist::parse("Divider
+V1 in 0 5
+R1 in out 1k
+R2 out 0 1k
+C1 out 0 1u
+.end",
+                       &mut world)
+            .unwrap();
+
+        assert!(diagnose(&world).is_empty());
+    }
+
+    #[test]
+    fn floating_nodes() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Floating
+V1 in 0 5
+C1 in a 1u
+R1 a b 1k
+I1 0 c 1m
+.end",
+                                     &mut world)
+            .unwrap();
+
+        match diagnose(&world).as_slice() {
+            &[Error::FloatingNodes { ref nodes, ref entities }] => {
+                let node = |name| netlist.node_index(name).unwrap();
+                assert_eq!(*nodes, vec![node("a"), node("b"), node("c")]);
+
+                let entity = |name| netlist.entity(name).unwrap();
+                assert_eq!(entities.len(), 3);
+                for name in &["C1", "R1", "I1"] {
+                    assert!(entities.contains(&entity(name)));
+                }
+            }
+            errors => panic!("Unexpected diagnosis: {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn voltage_source_loop() {
+        let mut world = create_world();
+        let netlist = netlist::parse("Loop
+V1 a 0 5
+V2 a b 1
+V3 b 0 4
+R1 a 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+
+        match diagnose(&world).as_slice() {
+            &[Error::VoltageSourceLoop { ref entities }] => {
+                let entity = |name| netlist.entity(name).unwrap();
+                assert_eq!(entities.len(), 3);
+                for name in &["V1", "V2", "V3"] {
+                    assert!(entities.contains(&entity(name)));
+                }
+            }
+            errors => panic!("Unexpected diagnosis: {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn controlled_sources_conduct_between_their_outputs() {
+        use specs::Gate;
+
+        let mut world = create_world();
+        let netlist = netlist::parse("Amplifier
+V1 in 0 5
+E1 out 0 in 0 2
+R1 out 0 1k
+.end",
+                                     &mut world)
+            .unwrap();
+
+        // swap the amplifier's output and control terminals around
+        let amplifier = netlist.entity("E1").unwrap();
+        {
+            let mut vcvs = world.write::<Vcvs>().pass();
+            let source = vcvs.get_mut(amplifier).unwrap();
+            source.node_indexes = (2, 3);
+            source.control_node_indexes = (0, 1);
+
+            let mut nodes = world.write::<Nodes>().pass();
+            match nodes.get_mut(amplifier) {
+                Some(&mut Nodes(ref mut ns)) => {
+                    ns.swap(0, 2);
+                    ns.swap(1, 3);
+                }
+                None => panic!("oh no"),
+            }
+        }
+
+        assert!(diagnose(&world).is_empty());
+    }
+
+    #[test]
+    fn explain_unsolvable() {
+        use analysis::operating_point;
+
+        let mut world = create_world();
+        netlist::parse("Parallel sources
+V1 a 0 5
+V2 a 0 5
+R1 a 0 1k
+.end",
+                       &mut world)
+            .unwrap();
+
+        match operating_point(&mut world) {
+            Err(Error::VoltageSourceLoop { ref entities }) => assert_eq!(entities.len(), 2),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
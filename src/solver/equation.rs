@@ -1,9 +1,11 @@
 use std;
 use std::fmt;
 use rulinalg;
+use specs;
 use rulinalg::matrix::Matrix;
 use rulinalg::matrix::decomposition::PartialPivLu;
 use rulinalg::vector::Vector;
+use solver::sparse;
 
 #[derive(Debug)]
 pub struct Solution {
@@ -20,38 +22,77 @@ impl Solution {
     }
 }
 
+// How the nodal admittance matrix is stored and solved.
+//
+// Dense is faster for small circuits, sparse scales to thousands of nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Dense,
+    Sparse,
+}
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Dense
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Equation {
-    nodal_admittances: Matrix<f64>,
+    // Stamped as triplets for either backend, so they can be compared with
+    // the last factorisation's without building the matrix
+    nodal_admittances: sparse::Triplets,
+    backend: Backend,
     inputs: Vector<f64>,
 
     nodes: usize,
     voltage_sources: usize,
     voltage_sources_stamped: usize,
+
+    // Current-controlled sources which couldn't be stamped
+    unresolved_controls: Vec<specs::Entity>,
 }
 
 impl Equation {
     pub fn new(nodes: usize, voltage_sources: usize) -> Self {
+        Equation::with_backend(nodes, voltage_sources, Backend::Dense)
+    }
+
+    pub fn with_backend(nodes: usize, voltage_sources: usize, backend: Backend) -> Self {
         let size = nodes + voltage_sources - 1;
         Equation {
-            nodal_admittances: Matrix::<f64>::zeros(size, size),
+            nodal_admittances: sparse::Triplets::new(size),
+            backend: backend,
             inputs: Vector::<f64>::zeros(size),
 
             nodes: nodes,
             voltage_sources: voltage_sources,
             voltage_sources_stamped: 0,
+
+            unresolved_controls: Vec::new(),
         }
     }
 
+    pub fn num_nodes(&self) -> usize {
+        self.nodes
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
     fn stamp_nodal_admittance(&mut self, row: usize, col: usize, x: f64) {
         if row != 0 && col != 0 {
             // ignore ground node
             let row = row - 1;
             let col = col - 1;
-            self.nodal_admittances[[row, col]] += x;
+            self.nodal_admittances.add(row, col, x);
         }
     }
 
+    fn nodal_admittances(&self) -> Matrix<f64> {
+        dense(&self.nodal_admittances)
+    }
+
     fn stamp_input(&mut self, row: usize, x: f64) {
         if row != 0 {
             let row = row - 1;
@@ -93,6 +134,37 @@ impl Equation {
         self
     }
 
+    // A voltage source in series with a resistance, i.e. a Thevenin equivalent.
+    pub fn stamp_voltage_source_with_resistance(&mut self,
+                                                voltage: f64,
+                                                resistance: f64,
+                                                from_node: usize,
+                                                to_node: usize,
+                                                v_num: usize)
+                                                -> &mut Self {
+        self.stamp_voltage_source(voltage, from_node, to_node, v_num);
+        if self.voltage_sources_stamped > self.voltage_sources {
+            return self;
+        }
+
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, v_index, resistance);
+        self
+    }
+
+    // A branch which carries no current, for an element which has a branch
+    // in the equation but is open circuit, e.g. a capacitor at DC.
+    pub fn stamp_open_branch(&mut self, v_num: usize) -> &mut Self {
+        self.voltage_sources_stamped += 1;
+        if self.voltage_sources_stamped > self.voltage_sources {
+            return self;
+        }
+
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, v_index, 1.0);
+        self
+    }
+
     pub fn stamp_current_source(&mut self,
                                 current: f64,
                                 from_node: usize,
@@ -103,20 +175,138 @@ impl Equation {
         self
     }
 
-    fn solve_internal(eq: Equation) -> Result<Solution, Error> {
+    // A voltage-controlled voltage source: V(to) - V(from) is `gain` times
+    // V(control_to) - V(control_from).
+    pub fn stamp_vcvs(&mut self,
+                      gain: f64,
+                      from_node: usize,
+                      to_node: usize,
+                      control_from_node: usize,
+                      control_to_node: usize,
+                      v_num: usize)
+                      -> &mut Self {
+        self.stamp_voltage_source(0.0, from_node, to_node, v_num);
+        self.stamp_voltage_gain(gain, control_from_node, control_to_node, v_num);
+        self
+    }
+
+    // Add `gain` times V(control_to) - V(control_from) to the voltage of an
+    // already stamped voltage source.
+    pub fn stamp_voltage_gain(&mut self,
+                              gain: f64,
+                              control_from_node: usize,
+                              control_to_node: usize,
+                              v_num: usize)
+                              -> &mut Self {
+        if v_num >= self.voltage_sources {
+            return self;
+        }
+
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, control_from_node, gain);
+        self.stamp_nodal_admittance(v_index, control_to_node, -gain);
+        self
+    }
+
+    // An ideal op-amp (a nullor): whatever current is needed flows from
+    // `output_from` to `output_to` through the branch to make V(input_to)
+    // equal V(input_from). No current flows into the inputs.
+    pub fn stamp_nullor(&mut self,
+                        input_from_node: usize,
+                        input_to_node: usize,
+                        output_from_node: usize,
+                        output_to_node: usize,
+                        v_num: usize)
+                        -> &mut Self {
+        self.voltage_sources_stamped += 1;
+        if self.voltage_sources_stamped > self.voltage_sources {
+            return self;
+        }
+
+        let v_index = self.nodes + v_num;
+        self.stamp_nodal_admittance(v_index, input_from_node, -1.0);
+        self.stamp_nodal_admittance(v_index, input_to_node, 1.0);
+        self.stamp_nodal_admittance(output_from_node, v_index, 1.0);
+        self.stamp_nodal_admittance(output_to_node, v_index, -1.0);
+        self
+    }
+
+    // A voltage-controlled current source: a current of `transconductance`
+    // times V(control_to) - V(control_from) flows from `from_node` to
+    // `to_node` through the source.
+    pub fn stamp_vccs(&mut self,
+                      transconductance: f64,
+                      from_node: usize,
+                      to_node: usize,
+                      control_from_node: usize,
+                      control_to_node: usize)
+                      -> &mut Self {
+        self.stamp_nodal_admittance(from_node, control_from_node, -transconductance);
+        self.stamp_nodal_admittance(from_node, control_to_node, transconductance);
+        self.stamp_nodal_admittance(to_node, control_from_node, transconductance);
+        self.stamp_nodal_admittance(to_node, control_to_node, -transconductance);
+        self
+    }
+
+    // A current-controlled voltage source: V(to) - V(from) is
+    // `transresistance` times the current through the voltage source with
+    // index `control_v_num`.
+    pub fn stamp_ccvs(&mut self,
+                      transresistance: f64,
+                      from_node: usize,
+                      to_node: usize,
+                      control_v_num: usize,
+                      v_num: usize)
+                      -> &mut Self {
+        self.stamp_voltage_source(0.0, from_node, to_node, v_num);
+        if self.voltage_sources_stamped > self.voltage_sources {
+            return self;
+        }
+
+        let v_index = self.nodes + v_num;
+        let control_index = self.nodes + control_v_num;
+        self.stamp_nodal_admittance(v_index, control_index, -transresistance);
+        self
+    }
+
+    // A current-controlled current source: `gain` times the current through
+    // the voltage source with index `control_v_num` flows from `from_node` to
+    // `to_node` through the source.
+    pub fn stamp_cccs(&mut self,
+                      gain: f64,
+                      from_node: usize,
+                      to_node: usize,
+                      control_v_num: usize)
+                      -> &mut Self {
+        let control_index = self.nodes + control_v_num;
+        self.stamp_nodal_admittance(from_node, control_index, gain);
+        self.stamp_nodal_admittance(to_node, control_index, -gain);
+        self
+    }
+
+    // A current-controlled source whose control isn't a voltage source, so
+    // can't be stamped. The equation can't be solved until it's resolved.
+    pub fn stamp_unresolved_control(&mut self, entity: specs::Entity) -> &mut Self {
+        self.unresolved_controls.push(entity);
+        self
+    }
+
+    fn solve_internal(eq: &Equation,
+                      factorisation: &mut Factorisation)
+                      -> Result<Solution, Error> {
+        if !eq.unresolved_controls.is_empty() {
+            return Err(Error::UnresolvedControl { entities: eq.unresolved_controls.clone() });
+        }
         if eq.voltage_sources != eq.voltage_sources_stamped {
-            return Err(Error::IncorrectNumberOfVoltageSources(
-                format!("Expected {} voltage sources, stamped {}",
-                               eq.voltage_sources,
-                               eq.voltage_sources_stamped).to_owned()));
+            return Err(Error::IncorrectNumberOfVoltageSources {
+                expected: eq.voltage_sources,
+                stamped: eq.voltage_sources_stamped,
+            });
         }
 
-        let lu = PartialPivLu::decompose(eq.nodal_admittances)?;
-        let solution = lu.solve(eq.inputs)?;
+        let solution = factorisation.solve(&eq.nodal_admittances, eq.backend, &eq.inputs)?;
 
-        let (voltages, currents) = solution.data()
-            .as_slice()
-            .split_at(eq.nodes - 1);
+        let (voltages, currents) = solution.split_at(eq.nodes - 1);
 
         let mut vs = voltages.to_vec();
         vs.insert(0, 0.0); // ground node
@@ -128,7 +318,106 @@ impl Equation {
     }
 
     pub fn solve(&self) -> Result<Solution, Error> {
-        Equation::solve_internal(self.clone())
+        Equation::solve_internal(self, &mut Factorisation::new())
+    }
+
+    // Solve, reusing the LU factorisation from the last time `factorisation`
+    // was used if the nodal admittances haven't changed since, i.e. only the
+    // inputs are different.
+    pub fn solve_with(&self, factorisation: &mut Factorisation) -> Result<Solution, Error> {
+        Equation::solve_internal(self, factorisation)
+    }
+}
+
+fn dense(triplets: &sparse::Triplets) -> Matrix<f64> {
+    let size = triplets.size();
+    let data: Vec<f64> = triplets.to_dense().into_iter().flat_map(|row| row).collect();
+    Matrix::new(size, size, data)
+}
+
+#[derive(Debug, Clone)]
+enum Factorised {
+    Dense(PartialPivLu<f64>),
+    Sparse(sparse::Lu),
+}
+
+impl Factorised {
+    fn solve(&self, inputs: &Vector<f64>) -> Result<Vec<f64>, Error> {
+        match *self {
+            Factorised::Dense(ref lu) => Ok(lu.solve(inputs.clone())?.into_vec()),
+            Factorised::Sparse(ref lu) => Ok(lu.solve(inputs.data())),
+        }
+    }
+}
+
+// A cached LU factorisation of the nodal admittances.
+//
+// For a linear circuit with a fixed timestep the admittances are the same
+// every step, so only the forward/back substitution needs redoing. Any change
+// to the admittances (e.g. a resistance, timestep or topology change) is
+// detected by comparing the stamps, and the matrix is refactorised.
+//
+// The sparse backend also keeps the symbolic factorisation (the sparsity
+// pattern and column ordering) for as long as the stamps land in the same
+// places, so changing values only redoes the numeric factorisation. A
+// topology change stamps different places, so is analysed afresh.
+#[derive(Debug, Clone, Default)]
+pub struct Factorisation {
+    factorised: Option<(sparse::Triplets, Factorised)>,
+    symbolic: Option<sparse::Symbolic>,
+    factorisations: usize,
+    analyses: usize,
+}
+
+impl Factorisation {
+    pub fn new() -> Self {
+        Factorisation::default()
+    }
+
+    pub fn invalidate(&mut self) {
+        self.factorised = None;
+        self.symbolic = None;
+    }
+
+    // How many times a matrix has been factorised, rather than reused.
+    pub fn factorisations(&self) -> usize {
+        self.factorisations
+    }
+
+    // How many times the sparse backend has found a sparsity pattern and
+    // column ordering, rather than reused them.
+    pub fn analyses(&self) -> usize {
+        self.analyses
+    }
+
+    fn solve(&mut self,
+             admittances: &sparse::Triplets,
+             backend: Backend,
+             inputs: &Vector<f64>)
+             -> Result<Vec<f64>, Error> {
+        if let Some((ref cached, ref factorised)) = self.factorised {
+            if cached == admittances {
+                return factorised.solve(inputs);
+            }
+        }
+
+        let factorised = match backend {
+            Backend::Dense => Factorised::Dense(PartialPivLu::decompose(dense(admittances))?),
+            Backend::Sparse => {
+                if !self.symbolic.as_ref().map_or(false, |symbolic| symbolic.matches(admittances)) {
+                    self.symbolic = Some(sparse::Symbolic::analyse(admittances));
+                    self.analyses += 1;
+                }
+                let symbolic = self.symbolic.as_ref().unwrap();
+                let matrix = symbolic.assemble(admittances);
+                Factorised::Sparse(sparse::Lu::decompose_ordered(&matrix,
+                                                                 symbolic.col_order().to_vec())?)
+            }
+        };
+        let solution = factorised.solve(inputs)?;
+        self.factorised = Some((admittances.clone(), factorised));
+        self.factorisations += 1;
+        Ok(solution)
     }
 }
 
@@ -146,38 +435,59 @@ Inputs:
                self.voltage_sources,
                self.voltage_sources_stamped,
                self.nodes,
-               self.nodal_admittances,
+               self.nodal_admittances(),
                self.inputs)
     }
 }
 
 #[derive(Debug)]
 pub enum Error {
-    IncorrectNumberOfVoltageSources(String),
+    IncorrectNumberOfVoltageSources { expected: usize, stamped: usize },
     Unsolvable(rulinalg::error::Error),
+    NotConverged(usize),
+    // Nodes with no DC path to ground, and the elements connected to them
+    FloatingNodes {
+        nodes: Vec<usize>,
+        entities: Vec<specs::Entity>,
+    },
+    // A loop made up only of voltage sources (including wires)
+    VoltageSourceLoop { entities: Vec<specs::Entity> },
+    // Current-controlled sources whose control isn't a voltage source
+    UnresolvedControl { entities: Vec<specs::Entity> },
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match *self {
             Error::Unsolvable(ref err) => write!(f, "{}", err),
-            Error::IncorrectNumberOfVoltageSources(ref s) => write!(f, "{}", s),
+            Error::IncorrectNumberOfVoltageSources { expected, stamped } => {
+                write!(f, "Expected {} voltage sources, stamped {}", expected, stamped)
+            }
+            Error::NotConverged(iterations) => {
+                write!(f, "Did not converge after {} iterations", iterations)
+            }
+            Error::FloatingNodes { ref nodes, .. } => {
+                write!(f, "Nodes with no DC path to ground: {:?}", nodes)
+            }
+            Error::VoltageSourceLoop { ref entities } => {
+                write!(f, "Loop of {} voltage sources", entities.len())
+            }
+            Error::UnresolvedControl { ref entities } => {
+                write!(f, "{} controlled sources without a control source", entities.len())
+            }
         }
     }
 }
 
 impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::Unsolvable(ref err) => err.description(),
-            Error::IncorrectNumberOfVoltageSources(ref s) => s,
-        }
-    }
-
-    fn cause(&self) -> Option<&std::error::Error> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
             Error::Unsolvable(ref err) => Some(err),
-            Error::IncorrectNumberOfVoltageSources(_) => None,
+            Error::IncorrectNumberOfVoltageSources { .. } |
+            Error::NotConverged(_) |
+            Error::FloatingNodes { .. } |
+            Error::VoltageSourceLoop { .. } |
+            Error::UnresolvedControl { .. } => None,
         }
     }
 }
@@ -202,7 +512,7 @@ mod tests {
         let expected = matrix![0.2, -0.2;
                                -0.2, 0.2];
 
-        assert_matrix_eq!(equation.nodal_admittances, expected);
+        assert_matrix_eq!(equation.nodal_admittances(), expected);
     }
 
     #[test]
@@ -214,7 +524,7 @@ mod tests {
         let expected = matrix![0.2, -0.2;
                                -0.2, 0.4];
 
-        assert_matrix_eq!(equation.nodal_admittances, expected);
+        assert_matrix_eq!(equation.nodal_admittances(), expected);
     }
 
     #[test]
@@ -228,11 +538,52 @@ mod tests {
         let expected_admittances = matrix![0.0, 0.0, 1.0;
                                            0.0, 0.0, -1.0;
                                            -1.0, 1.0, 0.0];
-        assert_matrix_eq!(equation.nodal_admittances,
+        assert_matrix_eq!(equation.nodal_admittances(),
+                          expected_admittances,
+                          comp = float);
+    }
+
+    #[test]
+    fn stamp_voltage_source_with_resistance() {
+        let mut equation = Equation::new(3, 1);
+        equation.stamp_voltage_source_with_resistance(5.0, 2.0, 1, 2, 0);
+
+        let expected_inputs = vector![0.0, 0.0, 5.0];
+        assert_vector_eq!(equation.inputs, expected_inputs);
+
+        let expected_admittances = matrix![0.0, 0.0, 1.0;
+                                           0.0, 0.0, -1.0;
+                                           -1.0, 1.0, 2.0];
+        assert_matrix_eq!(equation.nodal_admittances(),
                           expected_admittances,
                           comp = float);
     }
 
+    #[test]
+    fn solve_voltage_source_with_resistance() {
+        let mut equation = Equation::new(2, 1);
+        equation.stamp_voltage_source_with_resistance(10.0, 5.0, 0, 1, 0);
+        equation.stamp_resistor(15.0, 1, 0);
+
+        let solution = equation.solve().unwrap();
+
+        assert_approx_eq!(solution.voltages()[1], 7.5);
+        assert_approx_eq!(solution.currents()[0], 0.5);
+    }
+
+    #[test]
+    fn solve_open_branch() {
+        let mut equation = Equation::new(2, 1);
+        equation.stamp_current_source(1.0, 0, 1);
+        equation.stamp_resistor(100.0, 1, 0);
+        equation.stamp_open_branch(0);
+
+        let solution = equation.solve().unwrap();
+
+        assert_approx_eq!(solution.voltages()[1], 100.0);
+        assert_approx_eq!(solution.currents()[0], 0.0);
+    }
+
     #[test]
     fn stamp_current_source() {
         let mut equation = Equation::new(3, 0);
@@ -242,6 +593,81 @@ mod tests {
         assert_vector_eq!(equation.inputs, expected_inputs);
     }
 
+    #[test]
+    fn solve_vcvs() {
+        // 1V across a divider, amplified by 10 from the middle
+        let mut equation = Equation::new(4, 2);
+        equation.stamp_voltage_source(1.0, 0, 1, 0);
+        equation.stamp_resistor(100.0, 1, 2);
+        equation.stamp_resistor(100.0, 2, 0);
+        equation.stamp_vcvs(10.0, 0, 3, 0, 2, 1);
+        equation.stamp_resistor(1000.0, 3, 0);
+
+        let solution = equation.solve().unwrap();
+
+        assert_approx_eq!(solution.voltages()[3], 5.0);
+        assert_approx_eq!(solution.currents()[1], 5e-3);
+    }
+
+    #[test]
+    fn solve_vccs() {
+        let mut equation = Equation::new(3, 1);
+        equation.stamp_voltage_source(2.0, 0, 1, 0);
+        equation.stamp_vccs(1e-3, 0, 2, 0, 1);
+        equation.stamp_resistor(1000.0, 2, 0);
+
+        let solution = equation.solve().unwrap();
+
+        // 2mA into node 2
+        assert_approx_eq!(solution.voltages()[2], 2.0);
+    }
+
+    #[test]
+    fn solve_ccvs() {
+        // 10mA through the controlling source's branch
+        let mut equation = Equation::new(3, 2);
+        equation.stamp_voltage_source(1.0, 0, 1, 0);
+        equation.stamp_resistor(100.0, 1, 0);
+        equation.stamp_ccvs(100.0, 0, 2, 0, 1);
+        equation.stamp_resistor(1000.0, 2, 0);
+
+        let solution = equation.solve().unwrap();
+
+        assert_approx_eq!(solution.voltages()[2], 1.0);
+    }
+
+    #[test]
+    fn solve_cccs() {
+        let mut equation = Equation::new(3, 1);
+        equation.stamp_voltage_source(1.0, 0, 1, 0);
+        equation.stamp_resistor(100.0, 1, 0);
+        equation.stamp_cccs(2.0, 0, 2, 0);
+        equation.stamp_resistor(100.0, 2, 0);
+
+        let solution = equation.solve().unwrap();
+
+        // 20mA into node 2
+        assert_approx_eq!(solution.voltages()[2], 2.0);
+    }
+
+    #[test]
+    fn solve_nullor() {
+        // inverting amplifier: 1V through 1k into the inverting input (2),
+        // with 2k of feedback from the output (3)
+        let mut equation = Equation::new(4, 2);
+        equation.stamp_voltage_source(1.0, 0, 1, 0);
+        equation.stamp_resistor(1000.0, 1, 2);
+        equation.stamp_resistor(2000.0, 2, 3);
+        equation.stamp_nullor(2, 0, 0, 3, 1);
+
+        let solution = equation.solve().unwrap();
+
+        assert_approx_eq!(solution.voltages()[2], 0.0);
+        assert_approx_eq!(solution.voltages()[3], -2.0);
+        // all of the input current flows into the output
+        assert_approx_eq!(solution.currents()[1], -1e-3);
+    }
+
     #[test]
     fn stamp_too_many_voltage_sources() {
         let mut equation = Equation::new(3, 0);
@@ -293,8 +719,126 @@ mod tests {
         assert_eq!(solution.currents(), &expected_currents);
     }
 
+    #[test]
+    fn solve_simple_circuit_with_wire_sparse() {
+        let mut equation = Equation::with_backend(3, 1, Backend::Sparse);
+        equation.stamp_current_source(1.0, 0, 1);
+        equation.stamp_voltage_source(0.0, 1, 2, 0);
+        equation.stamp_resistor(100.0, 2, 0);
+
+        let solution = equation.solve().unwrap();
+
+        assert_approx_eq!(solution.voltages()[1], 100.0);
+        assert_approx_eq!(solution.voltages()[2], 100.0);
+        assert_approx_eq!(solution.currents()[0], 1.0);
+    }
+
+    #[test]
+    fn stamp_voltage_source_sparse() {
+        let mut equation = Equation::with_backend(3, 1, Backend::Sparse);
+        equation.stamp_voltage_source(5.0, 1, 2, 0);
+        equation.stamp_resistor(5.0, 1, 2);
+
+        let expected_admittances = matrix![0.2, -0.2, 1.0;
+                                           -0.2, 0.2, -1.0;
+                                           -1.0, 1.0, 0.0];
+        assert_matrix_eq!(equation.nodal_admittances(),
+                          expected_admittances,
+                          comp = float);
+    }
+
+    #[test]
+    fn reuse_factorisation() {
+        for &backend in &[Backend::Dense, Backend::Sparse] {
+            let mut factorisation = Factorisation::new();
+
+            let mut equation = Equation::with_backend(2, 0, backend);
+            equation.stamp_resistor(100.0, 1, 0);
+
+            // only the inputs change
+            let mut first = equation.clone();
+            first.stamp_current_source(1.0, 0, 1);
+            let mut second = equation.clone();
+            second.stamp_current_source(2.0, 0, 1);
+
+            let solution = first.solve_with(&mut factorisation).unwrap();
+            assert_approx_eq!(solution.voltages()[1], 100.0);
+            let solution = second.solve_with(&mut factorisation).unwrap();
+            assert_approx_eq!(solution.voltages()[1], 200.0);
+            assert_eq!(factorisation.factorisations(), 1);
+
+            // the conductance changes
+            second.stamp_resistor(100.0, 1, 0);
+            let solution = second.solve_with(&mut factorisation).unwrap();
+            assert_approx_eq!(solution.voltages()[1], 100.0);
+            assert_eq!(factorisation.factorisations(), 2);
+
+            factorisation.invalidate();
+            second.solve_with(&mut factorisation).unwrap();
+            assert_eq!(factorisation.factorisations(), 3);
+        }
+    }
+
+    #[test]
+    fn reuse_symbolic_factorisation() {
+        let mut factorisation = Factorisation::new();
+
+        let equation = |resistance| {
+            let mut equation = Equation::with_backend(3, 1, Backend::Sparse);
+            equation.stamp_voltage_source(1.0, 0, 1, 0)
+                .stamp_resistor(resistance, 1, 2)
+                .stamp_resistor(100.0, 2, 0);
+            equation
+        };
+
+        // only the values change
+        let solution = equation(100.0).solve_with(&mut factorisation).unwrap();
+        assert_approx_eq!(solution.voltages()[2], 0.5);
+        let solution = equation(300.0).solve_with(&mut factorisation).unwrap();
+        assert_approx_eq!(solution.voltages()[2], 0.25);
+        assert_eq!(factorisation.factorisations(), 2);
+        assert_eq!(factorisation.analyses(), 1);
+
+        // the structure changes
+        let mut changed = equation(300.0);
+        changed.stamp_resistor(100.0, 1, 0);
+        let solution = changed.solve_with(&mut factorisation).unwrap();
+        assert_approx_eq!(solution.voltages()[2], 0.25);
+        assert_eq!(factorisation.factorisations(), 3);
+        assert_eq!(factorisation.analyses(), 2);
+    }
+
+    #[test]
+    fn sparse_matches_dense() {
+        let dense = resistor_ladder(100, Backend::Dense).solve().unwrap();
+        let sparse = resistor_ladder(100, Backend::Sparse).solve().unwrap();
+
+        for (d, s) in dense.voltages().iter().zip(sparse.voltages()) {
+            assert_approx_eq!(d, s);
+        }
+        assert_approx_eq!(dense.currents()[0], sparse.currents()[0]);
+    }
+
+    // A voltage source feeding a ladder of resistors, with `nodes` nodes
+    // including ground.
+    fn resistor_ladder(nodes: usize, backend: Backend) -> Equation {
+        let mut equation = Equation::with_backend(nodes, 1, backend);
+        equation.stamp_voltage_source(10.0, 0, 1, 0);
+        for node in 1..(nodes - 1) {
+            equation.stamp_resistor(10.0, node, node + 1);
+            equation.stamp_resistor(1000.0, node + 1, 0);
+        }
+        equation
+    }
+
     #[bench]
     fn bench_solve(b: &mut Bencher) {
         b.iter(|| solve_simple_circuit_with_voltage_source());
     }
+
+    #[bench]
+    fn bench_solve_1000_nodes_sparse(b: &mut Bencher) {
+        let equation = resistor_ladder(1000, Backend::Sparse);
+        b.iter(|| equation.solve().unwrap());
+    }
 }
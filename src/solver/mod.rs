@@ -1,9 +1,22 @@
 
 pub mod equation;
+pub mod newton;
+pub mod adaptive;
+pub mod sparse;
+mod assign_nodes;
 mod stamp_static;
+pub mod stamp_dynamic;
 
 pub mod solve;
+pub mod recorder;
+pub mod diagnostics;
+pub use self::assign_nodes::assign_nodes;
 pub use self::stamp_static::create_static_equation;
+pub use self::stamp_static::create_static_equation_with_backend;
+pub use self::stamp_static::assign_branches;
+pub use self::stamp_static::count_nodes;
+pub use self::stamp_static::stamp_static_elements;
+pub use self::diagnostics::diagnose;
 
 #[cfg(test)]
 mod tests;
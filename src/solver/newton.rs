@@ -0,0 +1,182 @@
+use solver::equation::Equation;
+use solver::equation::Solution;
+use solver::equation::Error;
+use solver::equation::Factorisation;
+
+pub const DEFAULT_MAX_ITERATIONS: usize = 100;
+pub const DEFAULT_RELTOL: f64 = 1e-3;
+pub const DEFAULT_VNTOL: f64 = 1e-6; // 1µV
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub max_iterations: usize,
+    // Converged when every node voltage changes by less than
+    // `reltol * |v| + vntol` between iterations
+    pub reltol: f64,
+    pub vntol: f64,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            reltol: DEFAULT_RELTOL,
+            vntol: DEFAULT_VNTOL,
+        }
+    }
+}
+
+// How the non-linear elements were stamped for an iteration.
+//
+// Ordered so that combining the results for several elements is `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Linearisation {
+    // Nothing non-linear was stamped
+    Linear,
+    // Linearised around the previous iteration's voltages
+    Exact,
+    // Linearised around voltages which were limited to help convergence, so
+    // the iteration can't have converged
+    Limited,
+}
+
+// Solve a (possibly) non-linear circuit using Newton-Raphson iteration.
+//
+// `equation` should have all linear elements stamped. On each iteration it
+// is cloned and `stamp_nonlinear` is called with the node voltages from the
+// previous iteration (starting with `initial_voltages`) to stamp the
+// linearised non-linear elements. If nothing was stamped the circuit is
+// linear, and the first solution is used.
+pub fn solve<F>(equation: &Equation,
+                settings: &Settings,
+                initial_voltages: &[f64],
+                stamp_nonlinear: F)
+                -> Result<Solution, Error>
+    where F: FnMut(&mut Equation, &[f64]) -> Linearisation
+{
+    solve_with(equation,
+               &mut Factorisation::new(),
+               settings,
+               initial_voltages,
+               stamp_nonlinear)
+}
+
+// As `solve`, reusing `factorisation` between iterations (and calls) where
+// the nodal admittances are unchanged.
+pub fn solve_with<F>(equation: &Equation,
+                     factorisation: &mut Factorisation,
+                     settings: &Settings,
+                     initial_voltages: &[f64],
+                     mut stamp_nonlinear: F)
+                     -> Result<Solution, Error>
+    where F: FnMut(&mut Equation, &[f64]) -> Linearisation
+{
+    let mut voltages = initial_voltages.to_vec();
+
+    for _ in 0..settings.max_iterations {
+        let mut iteration = equation.clone();
+        let linearisation = stamp_nonlinear(&mut iteration, &voltages);
+
+        let solution = iteration.solve_with(factorisation)?;
+        match linearisation {
+            Linearisation::Linear => return Ok(solution),
+            Linearisation::Exact if has_converged(settings, &voltages, solution.voltages()) => {
+                return Ok(solution)
+            }
+            _ => {}
+        }
+
+        voltages = solution.voltages().clone();
+    }
+
+    Err(Error::NotConverged(settings.max_iterations))
+}
+
+fn has_converged(settings: &Settings, previous: &[f64], next: &[f64]) -> bool {
+    previous.len() == next.len() &&
+    previous.iter().zip(next.iter()).all(|(&v0, &v1)| {
+        let tolerance = settings.reltol * v0.abs().max(v1.abs()) + settings.vntol;
+        (v1 - v0).abs() <= tolerance
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements::diode::Diode;
+
+    // V -- R -- D -- ground
+    fn stamp_diode(equation: &mut Equation,
+                   diode: &mut Diode,
+                   voltages: &[f64])
+                   -> Linearisation {
+        let v = diode.limit_voltage(voltages[2], diode.junction_voltage);
+        diode.junction_voltage = v;
+
+        let conductance = diode.conductance(v);
+        let current = diode.current(v) - conductance * v;
+        equation.stamp_conductance(conductance, 2, 0);
+        equation.stamp_current_source(current, 2, 0);
+
+        if v == voltages[2] {
+            Linearisation::Exact
+        } else {
+            Linearisation::Limited
+        }
+    }
+
+    #[test]
+    fn solve_diode_circuit() {
+        let mut equation = Equation::new(3, 1);
+        equation.stamp_voltage_source(5.0, 0, 1, 0);
+        equation.stamp_resistor(1000.0, 1, 2);
+
+        let mut diode = Diode::default();
+        let solution = solve(&equation,
+                             &Settings::default(),
+                             &[0.0, 0.0, 0.0],
+                             |eq, vs| stamp_diode(eq, &mut diode, vs))
+            .unwrap();
+
+        let vd = solution.voltages()[2];
+        assert!(vd > 0.6 && vd < 0.7);
+
+        let resistor_current = (5.0 - vd) / 1000.0;
+        assert_approx_eq!(diode.current(vd), resistor_current, resistor_current * 1e-3);
+    }
+
+    #[test]
+    fn solve_linear_circuit() {
+        let mut equation = Equation::new(2, 0);
+        equation.stamp_current_source(1.0, 0, 1);
+        equation.stamp_resistor(100.0, 1, 0);
+
+        let mut iterations = 0;
+        let solution = solve(&equation, &Settings::default(), &[0.0, 0.0], |_, _| {
+                iterations += 1;
+                Linearisation::Linear
+            })
+            .unwrap();
+
+        assert_eq!(iterations, 1);
+        assert_eq!(solution.voltages(), &vec![0.0, 100.0]);
+    }
+
+    #[test]
+    fn iteration_limit() {
+        let mut equation = Equation::new(3, 1);
+        equation.stamp_voltage_source(5.0, 0, 1, 0);
+        equation.stamp_resistor(1000.0, 1, 2);
+
+        let mut diode = Diode::default();
+        let settings = Settings { max_iterations: 1, ..Settings::default() };
+        let solution = solve(&equation,
+                             &settings,
+                             &[0.0, 0.0, 0.0],
+                             |eq, vs| stamp_diode(eq, &mut diode, vs));
+
+        match solution {
+            Err(Error::NotConverged(1)) => {}
+            _ => panic!("expected not to converge"),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::f64;
+use specs;
+
+// Something to record at every timestep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Probe {
+    // The voltage at a node, by node index
+    Voltage(usize),
+    // An element's `CalculatedCurrent` or `DerivedCurrent`
+    Current(specs::Entity),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capacity {
+    // Keep every sample, e.g. for batch runs
+    Unbounded,
+    // Keep only the most recent samples, e.g. for a live display
+    Bounded(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub time: f64,
+    // The value of each probe, in the order they were given to the recorder.
+    // NaN for a probe which didn't match anything.
+    pub values: Vec<f64>,
+}
+
+// Records probe values at each timestep the solver takes, added to the world
+// as a resource.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    probes: Vec<Probe>,
+    capacity: Capacity,
+    decimation: usize,
+
+    steps_until_sample: usize,
+    samples: VecDeque<Sample>,
+}
+
+impl Recorder {
+    pub fn new(probes: Vec<Probe>, capacity: Capacity) -> Self {
+        Recorder {
+            probes: probes,
+            capacity: capacity,
+            decimation: 1,
+
+            steps_until_sample: 0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    // Only record every `decimation`th timestep.
+    pub fn with_decimation(mut self, decimation: usize) -> Self {
+        self.decimation = decimation.max(1);
+        self
+    }
+
+    pub fn probes(&self) -> &[Probe] {
+        &self.probes
+    }
+
+    // Samples in time order, oldest first.
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        &self.samples
+    }
+
+    // The (time, value) pairs recorded for a single probe.
+    pub fn trace(&self, probe: usize) -> Vec<(f64, f64)> {
+        self.samples.iter().map(|sample| (sample.time, sample.values[probe])).collect()
+    }
+
+    // Remove and return all samples recorded so far.
+    pub fn drain(&mut self) -> Vec<Sample> {
+        self.samples.drain(..).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    // Called by the solver after each successful timestep, with a function to
+    // look up each probe's value.
+    pub fn record<F>(&mut self, time: f64, value: F)
+        where F: Fn(Probe) -> Option<f64>
+    {
+        if self.probes.is_empty() {
+            return;
+        }
+        if self.steps_until_sample > 0 {
+            self.steps_until_sample -= 1;
+            return;
+        }
+        self.steps_until_sample = self.decimation - 1;
+
+        if let Capacity::Bounded(capacity) = self.capacity {
+            if capacity == 0 {
+                return;
+            }
+            while self.samples.len() >= capacity {
+                self.samples.pop_front();
+            }
+        }
+
+        let values = self.probes.iter().map(|&probe| value(probe).unwrap_or(f64::NAN));
+        self.samples.push_back(Sample {
+            time: time,
+            values: values.collect(),
+        });
+    }
+}
+
+// Records nothing until it has some probes.
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new(Vec::new(), Capacity::Unbounded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_steps(recorder: &mut Recorder, steps: usize) {
+        for step in 0..steps {
+            recorder.record(step as f64, |probe| match probe {
+                Probe::Voltage(node) => Some((step * node) as f64),
+                Probe::Current(_) => None,
+            });
+        }
+    }
+
+    #[test]
+    fn unbounded() {
+        let mut recorder = Recorder::new(vec![Probe::Voltage(2)], Capacity::Unbounded);
+
+        record_steps(&mut recorder, 100);
+
+        assert_eq!(recorder.samples().len(), 100);
+        assert_eq!(recorder.trace(0)[10], (10.0, 20.0));
+    }
+
+    #[test]
+    fn bounded_keeps_latest() {
+        let mut recorder = Recorder::new(vec![Probe::Voltage(1)], Capacity::Bounded(5));
+
+        record_steps(&mut recorder, 100);
+
+        let times: Vec<f64> = recorder.samples().iter().map(|sample| sample.time).collect();
+        assert_eq!(times, vec![95.0, 96.0, 97.0, 98.0, 99.0]);
+    }
+
+    #[test]
+    fn decimation() {
+        let mut recorder = Recorder::new(vec![Probe::Voltage(1)], Capacity::Unbounded)
+            .with_decimation(10);
+
+        record_steps(&mut recorder, 100);
+
+        assert_eq!(recorder.samples().len(), 10);
+        assert_eq!(recorder.samples()[1].time, 10.0);
+    }
+
+    #[test]
+    fn drain() {
+        let mut recorder = Recorder::new(vec![Probe::Voltage(1)], Capacity::Unbounded);
+        record_steps(&mut recorder, 3);
+
+        assert_eq!(recorder.drain().len(), 3);
+        assert!(recorder.samples().is_empty());
+    }
+
+    #[test]
+    fn no_probes() {
+        let mut recorder = Recorder::default();
+
+        record_steps(&mut recorder, 3);
+
+        assert!(recorder.samples().is_empty());
+    }
+}
@@ -1,20 +1,139 @@
+use std::cmp;
 use specs;
 use elements::Nodes;
 use elements::CalculatedCurrent;
 use elements::DerivedCurrent;
 use elements::voltage_source::VoltageSource;
+use elements::current_source::CurrentSource;
+use elements::waveform::Waveform;
+use elements::resistor::Resistor;
 use elements::capacitor::Capacitor;
+use elements::inductor::Inductor;
+use elements::diode::Diode;
+use elements::bjt::Bjt;
+use elements::mosfet::Mosfet;
+use elements::vcvs::Vcvs;
+use elements::ccvs::Ccvs;
+use elements::opamp::OpAmp;
+use elements::switch::Switch;
 use solver::equation;
+use solver::newton;
+use solver::newton::Linearisation;
+use solver::adaptive;
+use solver::stamp_dynamic;
+use solver::recorder::Probe;
+use solver::recorder::Recorder;
+use solver::diagnostics;
+use solver::diagnostics::Analysis;
+use solver::diagnostics::Connection;
+use solver::diagnostics::Element;
 use Delta;
 
-// Run the simulation 1000x slower than reality
+// By default, run the simulation 1000x slower than reality
 pub const SIM_TIME_PER_SEC: f64 = 1.0 / 1000.0;
 pub const SIM_TIMESTEP: f64 = 5e-6; // 5µs (seconds)
 
-#[derive(Debug, Clone, Copy)]
+// Breakpoints this close to the current time, as a fraction of the minimum
+// step, have already been landed on and are only out by rounding.
+const BREAKPOINT_TOLERANCE: f64 = 1e-3;
+
+// Runtime control of the simulation, added to the world as a resource.
+//
+// Anything set here takes precedence over the settings the System was
+// created with.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Control {
+    // Stop simulating in step with real time. Steps requested with `step` or
+    // `run_steps` still run while paused.
+    pub paused: bool,
+    // Ignored unless positive and finite
+    pub sim_time_per_sec: Option<f64>,
+    // Ignored unless positive and finite
+    pub timestep: Option<f64>,
+
+    pending_steps: usize,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Control::default()
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    // Run a single timestep on the next update.
+    pub fn step(&mut self) {
+        self.run_steps(1);
+    }
+
+    // Run `steps` timesteps on the next update, in addition to any which are
+    // due in real time.
+    pub fn run_steps(&mut self, steps: usize) {
+        self.pending_steps += steps;
+    }
+
+    pub fn pending_steps(&self) -> usize {
+        self.pending_steps
+    }
+}
+
+// The outcome of the simulation so far, added to the world as a resource.
+#[derive(Debug, Default)]
+pub struct Status {
+    // The most recent error, if any step has failed
+    pub last_error: Option<equation::Error>,
+    // The sim time of the step which caused `last_error`
+    pub failed_at: Option<f64>,
+    // How many steps have been solved
+    pub steps: usize,
+    // Whether element states are out of date, because the last step failed
+    pub stale: bool,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Status::default()
+    }
+}
+
+// Gates a resource which may not have been added to the world.
+#[derive(Debug)]
+struct Optional<G>(Option<G>);
+
+impl<G: specs::Gate> specs::Gate for Optional<G> {
+    type Target = Option<G::Target>;
+
+    fn pass(self) -> Self::Target {
+        self.0.map(specs::Gate::pass)
+    }
+}
+
+// Simulates the circuit in step with real time.
+//
+// Requires the static equation to be in the world. A `Control` resource,
+// `Recorder` and `Status` resources are used if they're there too.
+//
+// Without `Control`, the simulation runs in real time with the settings the
+// System was created with.
+#[derive(Debug, Clone)]
 pub struct System {
     prev_unsimulated_time: f64,
     sim_time: f64,
+
+    sim_time_per_sec: f64,
+    timestep: f64,
+    newton: newton::Settings,
+
+    adaptive: Option<adaptive::Settings>,
+    next_timestep: Option<f64>,
+
+    factorisation: equation::Factorisation,
 }
 
 impl System {
@@ -22,10 +141,49 @@ impl System {
         System {
             prev_unsimulated_time: 0f64,
             sim_time: 0f64,
+
+            sim_time_per_sec: SIM_TIME_PER_SEC,
+            timestep: SIM_TIMESTEP,
+            newton: newton::Settings::default(),
+
+            adaptive: None,
+            next_timestep: None,
+
+            factorisation: equation::Factorisation::new(),
+        }
+    }
+
+    // How many seconds of circuit time to simulate per second of real time.
+    // Ignored unless positive and finite.
+    pub fn with_sim_time_per_sec(mut self, sim_time_per_sec: f64) -> Self {
+        if is_positive(sim_time_per_sec) {
+            self.sim_time_per_sec = sim_time_per_sec;
+        }
+        self
+    }
+
+    // The fixed timestep, or the first timestep when adaptive. Ignored unless
+    // positive and finite.
+    pub fn with_timestep(mut self, timestep: f64) -> Self {
+        if is_positive(timestep) {
+            self.timestep = timestep;
         }
+        self
+    }
+
+    // Vary the timestep to keep the estimated local truncation error within
+    // the given tolerances.
+    pub fn with_adaptive_timestep(mut self, settings: adaptive::Settings) -> Self {
+        self.adaptive = Some(settings);
+        self
     }
 }
 
+// Whether a setting can be used as a time or rate.
+fn is_positive(value: f64) -> bool {
+    value > 0.0 && value.is_finite()
+}
+
 impl Default for System {
     fn default() -> Self {
         System::new()
@@ -36,60 +194,258 @@ impl specs::System<Delta> for System {
     fn run(&mut self, arg: specs::RunArg, delta: Delta) {
         use specs::Join;
 
-        let (mut nodes_ticket,
-             mut calc_currents,
-             mut derived_currents,
-             v_inputs,
-             mut capacitors,
-             static_equation) = arg.fetch(|w| {
-            (w.write::<Nodes>(),
-             w.write::<CalculatedCurrent>(),
-             w.write::<DerivedCurrent>(),
-             w.read::<VoltageSource>(),
-             w.write::<Capacitor>(),
-             w.read_resource::<equation::Equation>())
+        let ((entities, mut nodes_ticket, mut calc_currents, mut derived_currents),
+             (mut v_inputs, mut c_inputs, waveforms, switches),
+             (resistors, vcvs, ccvs, mut opamps),
+             (mut capacitors, mut inductors, mut diodes, mut bjts, mut mosfets),
+             (static_equation, mut control, mut recorder, mut status)) = arg.fetch(|w| {
+            let control = Optional(if w.has_resource::<Control>() {
+                Some(w.write_resource::<Control>())
+            } else {
+                None
+            });
+            let recorder = Optional(if w.has_resource::<Recorder>() {
+                Some(w.write_resource::<Recorder>())
+            } else {
+                None
+            });
+            let status = Optional(if w.has_resource::<Status>() {
+                Some(w.write_resource::<Status>())
+            } else {
+                None
+            });
+            ((w.entities(),
+              w.write::<Nodes>(),
+              w.write::<CalculatedCurrent>(),
+              w.write::<DerivedCurrent>()),
+             (w.write::<VoltageSource>(),
+              w.write::<CurrentSource>(),
+              w.read::<Waveform>(),
+              w.read::<Switch>()),
+             (w.read::<Resistor>(), w.read::<Vcvs>(), w.read::<Ccvs>(), w.write::<OpAmp>()),
+             (w.write::<Capacitor>(),
+              w.write::<Inductor>(),
+              w.write::<Diode>(),
+              w.write::<Bjt>(),
+              w.write::<Mosfet>()),
+             (w.read_resource::<equation::Equation>(),
+              control,
+              recorder,
+              status))
         });
 
-        // convert into the slower circuit time
-        // TODO override SIM_TIME_PER_SEC
-        let sim_delta = (delta * SIM_TIME_PER_SEC) + self.prev_unsimulated_time;
-        let mut time_to_simulate = sim_delta;
+        let mut default_control = Control::default();
+        let control = match control {
+            Some(ref mut control) => &mut **control,
+            None => &mut default_control,
+        };
+        let mut default_status = Status::default();
+        let status = match status {
+            Some(ref mut status) => &mut **status,
+            None => &mut default_status,
+        };
 
-        if time_to_simulate < SIM_TIMESTEP {
-            return;
+        // a timestep or rate which isn't positive would never finish, so is ignored
+        let sim_time_per_sec = control.sim_time_per_sec
+            .filter(|&sim_time_per_sec| is_positive(sim_time_per_sec))
+            .unwrap_or(self.sim_time_per_sec);
+        let timestep = control.timestep
+            .filter(|&timestep| is_positive(timestep))
+            .unwrap_or(self.timestep);
+
+        let mut steps = control.pending_steps;
+        control.pending_steps = 0;
+
+        let mut time_to_simulate = 0.0;
+        if !control.paused {
+            // convert into the slower circuit time
+            time_to_simulate = (delta * sim_time_per_sec) + self.prev_unsimulated_time;
+            if self.adaptive.is_none() {
+                while time_to_simulate > timestep {
+                    steps += 1;
+                    time_to_simulate -= timestep;
+                }
+            }
         }
 
-        while time_to_simulate > SIM_TIMESTEP {
-            self.sim_time += SIM_TIMESTEP;
-            time_to_simulate -= SIM_TIMESTEP;
+        loop {
+            let mut breakpoint = None;
+            let timestep = match self.adaptive {
+                None => {
+                    if steps == 0 {
+                        break;
+                    }
+                    timestep
+                }
+                Some(settings) => {
+                    let mut next = settings.clamp(self.next_timestep.unwrap_or(timestep));
+                    if steps == 0 {
+                        if time_to_simulate < settings.min_step {
+                            break;
+                        }
+                        next = next.min(time_to_simulate);
+                    }
+
+                    // land exactly on any corners in the sources, even if
+                    // that's a step shorter than the minimum, so they can't
+                    // be stepped over
+                    let now = self.sim_time + BREAKPOINT_TOLERANCE * settings.min_step;
+                    for (waveform,) in (&waveforms,).join() {
+                        if let Some(corner) = waveform.next_breakpoint(now) {
+                            let until_corner = corner - self.sim_time;
+                            if until_corner <= next {
+                                next = until_corner;
+                                breakpoint = Some(corner);
+                            }
+                        }
+                    }
+                    next
+                }
+            };
+            let time = breakpoint.unwrap_or(self.sim_time + timestep);
 
             let mut equation = static_equation.clone();
 
+            // Time-varying sources
+            for (nodes, vi, waveform) in (&nodes_ticket, &mut v_inputs, &waveforms).join() {
+                stamp_dynamic::stamp_voltage_source(&mut equation, vi, waveform, nodes, time);
+            }
+            for (nodes, ci, waveform) in (&nodes_ticket, &mut c_inputs, &waveforms).join() {
+                stamp_dynamic::stamp_current_source(&mut equation, ci, waveform, nodes, time);
+            }
+
+            // Switches, which may have been toggled since the last step
+            for (nodes, switch) in (&nodes_ticket, &switches).join() {
+                stamp_dynamic::stamp_switch(&mut equation, switch, nodes);
+            }
+
             // Capacitors
-            for (nodes, prev_current, mut cap) in
+            for (nodes, prev_current, cap) in
                 (&nodes_ticket, &derived_currents, &mut capacitors).join() {
+                stamp_dynamic::stamp_capacitor(&mut equation,
+                                               cap,
+                                               nodes,
+                                               prev_current.0,
+                                               timestep);
+            }
 
-                let conductance = (2.0 * cap.capacitance) / SIM_TIMESTEP;
-                cap.resistor.set_resistance(1.0 / conductance);
+            // Inductors
+            for (nodes, prev_current, ind) in
+                (&nodes_ticket, &derived_currents, &mut inductors).join() {
+                stamp_dynamic::stamp_inductor(&mut equation,
+                                              ind,
+                                              nodes,
+                                              prev_current.0,
+                                              timestep);
+            }
+
+            // Transistor junction capacitances
+            for (nodes, bjt) in (&nodes_ticket, &mut bjts).join() {
+                stamp_dynamic::stamp_bjt_capacitors(&mut equation, bjt, nodes, timestep);
+            }
 
-                let &Nodes(ref ns) = nodes;
-                let n0 = ns[cap.node_indexes.0];
-                let n1 = ns[cap.node_indexes.1];
-                let previous_voltage = n0.voltage - n1.voltage;
-                let current = prev_current.0 + (conductance * previous_voltage);
-                cap.current_source.current = current;
+            // MOSFET gate capacitances
+            for (nodes, mosfet) in (&nodes_ticket, &mut mosfets).join() {
+                stamp_dynamic::stamp_mosfet_capacitors(&mut equation, mosfet, nodes, timestep);
+            }
 
-                equation.stamp_conductance(cap.resistor.conductance(), n0.index, n1.index);
-                equation.stamp_current_source(cap.current_source.current, n1.index, n0.index);
+            // Start the Newton-Raphson iteration from the previous timestep
+            let mut initial_voltages = vec![0f64; equation.num_nodes()];
+            for (&Nodes(ref ns),) in (&nodes_ticket,).join() {
+                for node in ns.iter() {
+                    initial_voltages[node.index] = node.voltage;
+                }
             }
 
             // Solve the circuit equation, and update all circuit elements with their
             // calculated state.
-            match equation.solve() {
+            let result = newton::solve_with(&equation,
+                                            &mut self.factorisation,
+                                            &self.newton,
+                                            &initial_voltages,
+                                            |equation, voltages| {
+                let mut linearisation = Linearisation::Linear;
+                for (nodes, diode) in (&nodes_ticket, &mut diodes).join() {
+                    linearisation = cmp::max(linearisation,
+                                             stamp_dynamic::stamp_diode(equation,
+                                                                        diode,
+                                                                        nodes,
+                                                                        voltages));
+                }
+                for (nodes, bjt) in (&nodes_ticket, &mut bjts).join() {
+                    linearisation = cmp::max(linearisation,
+                                             stamp_dynamic::stamp_bjt(equation,
+                                                                      bjt,
+                                                                      nodes,
+                                                                      voltages));
+                }
+                for (nodes, mosfet) in (&nodes_ticket, &mut mosfets).join() {
+                    linearisation = cmp::max(linearisation,
+                                             stamp_dynamic::stamp_mosfet(equation,
+                                                                         mosfet,
+                                                                         nodes,
+                                                                         voltages));
+                }
+                for (nodes, opamp) in (&nodes_ticket, &opamps).join() {
+                    linearisation = cmp::max(linearisation,
+                                             stamp_dynamic::stamp_opamp(equation,
+                                                                        opamp,
+                                                                        nodes,
+                                                                        voltages,
+                                                                        Some(timestep)));
+                }
+                linearisation
+            });
+            match result {
                 Ok(solution) => {
                     let voltages = solution.voltages();
                     let currents = solution.currents();
 
+                    if let Some(settings) = self.adaptive {
+                        // compare the error in each reactive element's state
+                        // against the tolerances, and pick the next timestep
+                        let mut error = 0f64;
+                        for (nodes, prev_current, cap) in
+                            (&nodes_ticket, &derived_currents, &capacitors).join() {
+                            let &Nodes(ref ns) = nodes;
+                            let n0 = ns[cap.node_indexes.0];
+                            let n1 = ns[cap.node_indexes.1];
+                            let voltage = voltages[n0.index] - voltages[n1.index];
+                            let current =
+                                stamp_dynamic::capacitor_current(cap, nodes, voltages, currents);
+                            let cap_error =
+                                adaptive::capacitor_error(&settings,
+                                                          cap.capacitance,
+                                                          (n0.voltage - n1.voltage, voltage),
+                                                          (prev_current.0, current),
+                                                          timestep);
+                            error = error.max(cap_error);
+                        }
+                        for (nodes, prev_current, ind) in
+                            (&nodes_ticket, &derived_currents, &inductors).join() {
+                            let &Nodes(ref ns) = nodes;
+                            let n0 = ns[ind.node_indexes.0];
+                            let n1 = ns[ind.node_indexes.1];
+                            let voltage = voltages[n0.index] - voltages[n1.index];
+                            let current = stamp_dynamic::inductor_current(ind, nodes, voltages);
+                            let ind_error =
+                                adaptive::inductor_error(&settings,
+                                                         ind.inductance,
+                                                         (n0.voltage - n1.voltage, voltage),
+                                                         (prev_current.0, current),
+                                                         timestep);
+                            error = error.max(ind_error);
+                        }
+
+                        self.next_timestep = Some(adaptive::next_timestep(&settings,
+                                                                          timestep,
+                                                                          error));
+                        if error > 1.0 && timestep > settings.min_step {
+                            // reject the step, and try again with a smaller one
+                            continue;
+                        }
+                    }
+
                     // update circuit element states
                     for (nodes,) in (&mut nodes_ticket,).join() {
                         let &mut Nodes(ref mut ns) = nodes;
@@ -101,25 +457,118 @@ impl specs::System<Delta> for System {
                         let &mut CalculatedCurrent(ref mut current) = calc_current;
                         *current = currents[v_input.index];
                     }
+                    for (source, calc_current) in (&vcvs, &mut calc_currents).join() {
+                        calc_current.0 = currents[source.index];
+                    }
+                    for (source, calc_current) in (&ccvs, &mut calc_currents).join() {
+                        calc_current.0 = currents[source.index];
+                    }
+                    for (opamp, calc_current) in (&opamps, &mut calc_currents).join() {
+                        calc_current.0 = currents[opamp.index];
+                    }
 
                     // update any derived state
-                    for (nodes, mut current, capacitor) in
+                    for (nodes, current, capacitor) in
                         (&nodes_ticket, &mut derived_currents, &capacitors).join() {
-                        let &Nodes(ref ns) = nodes;
-                        let n0 = ns[capacitor.node_indexes.0];
-                        let n1 = ns[capacitor.node_indexes.1];
+                        current.0 =
+                            stamp_dynamic::capacitor_current(capacitor, nodes, voltages, currents);
+                    }
+                    for (nodes, current, inductor) in
+                        (&nodes_ticket, &mut derived_currents, &inductors).join() {
+                        current.0 = stamp_dynamic::inductor_current(inductor, nodes, voltages);
+                    }
+                    for (nodes, current, diode) in
+                        (&nodes_ticket, &mut derived_currents, &diodes).join() {
+                        current.0 = stamp_dynamic::diode_current(diode, nodes);
+                    }
+                    for (nodes, current, switch) in
+                        (&nodes_ticket, &mut derived_currents, &switches).join() {
+                        current.0 = stamp_dynamic::switch_current(switch, nodes, voltages);
+                    }
+                    for (nodes, current, bjt) in
+                        (&nodes_ticket, &mut derived_currents, &mut bjts).join() {
+                        stamp_dynamic::update_bjt_capacitor_currents(bjt, nodes, voltages);
+                        current.0 = stamp_dynamic::bjt_collector_current(bjt, nodes);
+                    }
+                    for (nodes, current, mosfet) in
+                        (&nodes_ticket, &mut derived_currents, &mut mosfets).join() {
+                        stamp_dynamic::update_mosfet_capacitor_currents(mosfet, nodes, voltages);
+                        current.0 = stamp_dynamic::mosfet_drain_current(mosfet, nodes);
+                    }
+                    for (nodes, opamp) in (&nodes_ticket, &mut opamps).join() {
+                        opamp.internal_voltage =
+                            stamp_dynamic::opamp_internal_voltage(opamp, nodes, Some(timestep));
+                    }
 
-                        let resistor_current = (n0.voltage - n1.voltage) *
-                                               capacitor.resistor.conductance();
+                    status.steps += 1;
+                    status.stale = false;
 
-                        current.0 = resistor_current - capacitor.current_source.current;
+                    if let Some(ref mut recorder) = recorder {
+                        recorder.record(time, |probe| match probe {
+                            Probe::Voltage(node) => voltages.get(node).cloned(),
+                            Probe::Current(entity) => {
+                                calc_currents.get(entity)
+                                    .map(|current| current.0)
+                                    .or(derived_currents.get(entity).map(|current| current.0))
+                            }
+                        });
+                    }
+                }
+                Err(error) => {
+                    if let Some(settings) = self.adaptive {
+                        if timestep > settings.min_step {
+                            // try again with a much smaller step
+                            self.next_timestep = Some(settings.clamp(timestep / 8.0));
+                            continue;
+                        }
                     }
+
+                    // find out which elements are to blame, if we can
+                    let error = match error {
+                        equation::Error::Unsolvable(_) => {
+                            let connections: Vec<Connection> = (&entities, &nodes_ticket)
+                                .join()
+                                .flat_map(|(entity, nodes)| {
+                                    let element = Element {
+                                        voltage_source: v_inputs.get(entity),
+                                        resistor: resistors.get(entity),
+                                        capacitor: capacitors.get(entity),
+                                        inductor: inductors.get(entity),
+                                        diode: diodes.get(entity),
+                                        vcvs: vcvs.get(entity),
+                                        ccvs: ccvs.get(entity),
+                                        opamp: opamps.get(entity),
+                                        bjt: bjts.get(entity),
+                                        mosfet: mosfets.get(entity),
+                                        switch: switches.get(entity),
+                                    };
+                                    element.connections(entity, nodes, Analysis::Transient)
+                                })
+                                .collect();
+                            diagnostics::diagnose_connections(equation.num_nodes(), &connections)
+                                .into_iter()
+                                .next()
+                                .unwrap_or(error)
+                        }
+                        _ => error,
+                    };
+                    status.last_error = Some(error);
+                    status.failed_at = Some(time);
+                    status.stale = true;
                 }
-                Err(error) => println!("Unsolvable circuit: {}", error),
+            }
+
+            self.sim_time = time;
+            if steps > 0 {
+                steps -= 1;
+            } else {
+                time_to_simulate -= timestep;
             }
         }
 
-        // for next time
-        self.prev_unsimulated_time = time_to_simulate;
+        if !control.paused {
+            // for next time
+            self.prev_unsimulated_time = time_to_simulate;
+        }
     }
 }
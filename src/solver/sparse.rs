@@ -0,0 +1,559 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+use rulinalg;
+use rulinalg::error::ErrorKind;
+
+// Prefer the diagonal as the pivot, unless it's much smaller than the
+// largest entry in the column.
+const PIVOT_TOLERANCE: f64 = 0.1;
+
+// A square sparse matrix being assembled, as (row, column, value) triplets.
+//
+// Entries at the same position are summed, so stamping is just a push.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triplets {
+    size: usize,
+    entries: Vec<(usize, usize, f64)>,
+}
+
+impl Triplets {
+    pub fn new(size: usize) -> Self {
+        Triplets {
+            size: size,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn add(&mut self, row: usize, col: usize, x: f64) {
+        self.entries.push((row, col, x));
+    }
+
+    pub fn to_dense(&self) -> Vec<Vec<f64>> {
+        let mut dense = vec![vec![0f64; self.size]; self.size];
+        for &(row, col, x) in &self.entries {
+            dense[row][col] += x;
+        }
+        dense
+    }
+}
+
+// Compressed sparse column matrix. Row indexes within each column are
+// sorted, and there are no duplicates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CscMatrix {
+    size: usize,
+    col_ptrs: Vec<usize>,
+    row_indexes: Vec<usize>,
+    values: Vec<f64>,
+}
+
+impl CscMatrix {
+    pub fn from_triplets(triplets: &Triplets) -> Self {
+        let size = triplets.size;
+
+        let mut sorted = triplets.entries.clone();
+        sorted.sort_by(|&(r1, c1, _), &(r2, c2, _)| (c1, r1).cmp(&(c2, r2)));
+
+        let mut col_ptrs = vec![0; size + 1];
+        let mut row_indexes: Vec<usize> = Vec::with_capacity(sorted.len());
+        let mut values: Vec<f64> = Vec::with_capacity(sorted.len());
+        let mut last = None;
+        for (row, col, x) in sorted {
+            if last == Some((row, col)) {
+                *values.last_mut().unwrap() += x;
+            } else {
+                row_indexes.push(row);
+                values.push(x);
+                col_ptrs[col + 1] += 1;
+                last = Some((row, col));
+            }
+        }
+        for col in 0..size {
+            col_ptrs[col + 1] += col_ptrs[col];
+        }
+
+        CscMatrix {
+            size: size,
+            col_ptrs: col_ptrs,
+            row_indexes: row_indexes,
+            values: values,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn non_zeros(&self) -> usize {
+        self.values.len()
+    }
+
+    fn col(&self, col: usize) -> Range<usize> {
+        self.col_ptrs[col]..self.col_ptrs[col + 1]
+    }
+}
+
+// Order the columns to reduce fill-in during factorisation, using the
+// minimum degree heuristic on the pattern of A + A'.
+pub fn minimum_degree(matrix: &CscMatrix) -> Vec<usize> {
+    let size = matrix.size;
+
+    let mut adjacent: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); size];
+    for col in 0..size {
+        for p in matrix.col(col) {
+            let row = matrix.row_indexes[p];
+            if row != col {
+                adjacent[row].insert(col);
+                adjacent[col].insert(row);
+            }
+        }
+    }
+
+    // (degree, node) for every node not yet eliminated
+    let mut degrees: BTreeSet<(usize, usize)> =
+        (0..size).map(|node| (adjacent[node].len(), node)).collect();
+
+    let mut order = Vec::with_capacity(size);
+    while let Some(&(degree, node)) = degrees.iter().next() {
+        degrees.remove(&(degree, node));
+        order.push(node);
+
+        // eliminating a node joins all of its neighbours together
+        let neighbours: Vec<usize> = adjacent[node].iter().cloned().collect();
+        for &a in &neighbours {
+            degrees.remove(&(adjacent[a].len(), a));
+            adjacent[a].remove(&node);
+            for &b in &neighbours {
+                if a != b {
+                    adjacent[a].insert(b);
+                }
+            }
+            degrees.insert((adjacent[a].len(), a));
+        }
+        adjacent[node].clear();
+    }
+
+    order
+}
+
+// The sparsity pattern of a matrix stamped as triplets, and a fill-reducing
+// column ordering for it.
+//
+// This is the symbolic part of factorisation. It only depends on where
+// entries are stamped, so can be reused for any matrix stamped at the same
+// positions in the same order, leaving just the numeric factorisation to do.
+#[derive(Debug, Clone)]
+pub struct Symbolic {
+    size: usize,
+    // The position of each triplet, in the order they were stamped
+    positions: Vec<(usize, usize)>,
+    // The index of each triplet's value in the matrix
+    slots: Vec<usize>,
+    col_ptrs: Vec<usize>,
+    row_indexes: Vec<usize>,
+    col_order: Vec<usize>,
+}
+
+impl Symbolic {
+    pub fn analyse(triplets: &Triplets) -> Self {
+        let size = triplets.size;
+        let positions: Vec<(usize, usize)> =
+            triplets.entries.iter().map(|&(row, col, _)| (row, col)).collect();
+
+        let mut sorted: Vec<usize> = (0..positions.len()).collect();
+        sorted.sort_by_key(|&i| (positions[i].1, positions[i].0));
+
+        let mut slots = vec![0; positions.len()];
+        let mut col_ptrs = vec![0; size + 1];
+        let mut row_indexes: Vec<usize> = Vec::with_capacity(positions.len());
+        let mut last = None;
+        for i in sorted {
+            let (row, col) = positions[i];
+            if last != Some((row, col)) {
+                row_indexes.push(row);
+                col_ptrs[col + 1] += 1;
+                last = Some((row, col));
+            }
+            slots[i] = row_indexes.len() - 1;
+        }
+        for col in 0..size {
+            col_ptrs[col + 1] += col_ptrs[col];
+        }
+
+        let col_order = minimum_degree(&CscMatrix {
+            size: size,
+            col_ptrs: col_ptrs.clone(),
+            row_indexes: row_indexes.clone(),
+            values: vec![0f64; row_indexes.len()],
+        });
+
+        Symbolic {
+            size: size,
+            positions: positions,
+            slots: slots,
+            col_ptrs: col_ptrs,
+            row_indexes: row_indexes,
+            col_order: col_order,
+        }
+    }
+
+    // Whether the triplets were stamped at the same positions, in the same
+    // order, as the ones analysed.
+    pub fn matches(&self, triplets: &Triplets) -> bool {
+        self.size == triplets.size && self.positions.len() == triplets.entries.len() &&
+        self.positions
+            .iter()
+            .zip(&triplets.entries)
+            .all(|(&position, &(row, col, _))| position == (row, col))
+    }
+
+    // Sum the values of matching triplets into the pattern.
+    pub fn assemble(&self, triplets: &Triplets) -> CscMatrix {
+        let mut values = vec![0f64; self.row_indexes.len()];
+        for (&slot, &(_, _, x)) in self.slots.iter().zip(&triplets.entries) {
+            values[slot] += x;
+        }
+
+        CscMatrix {
+            size: self.size,
+            col_ptrs: self.col_ptrs.clone(),
+            row_indexes: self.row_indexes.clone(),
+            values: values,
+        }
+    }
+
+    pub fn col_order(&self) -> &[usize] {
+        &self.col_order
+    }
+}
+
+// A sparse LU factorisation, `P A Q = L U`, where `Q` is a fill-reducing
+// column ordering and `P` is chosen by threshold partial pivoting.
+#[derive(Debug, Clone)]
+pub struct Lu {
+    // L has a unit diagonal stored first in each column, U has its diagonal
+    // stored last. Both use pivoted row indexes.
+    l: CscMatrix,
+    u: CscMatrix,
+    // row -> pivot position
+    row_pivots: Vec<usize>,
+    // pivot position -> column
+    col_order: Vec<usize>,
+}
+
+impl Lu {
+    // Left-looking (Gilbert-Peierls) factorisation: each column is found by
+    // a sparse triangular solve with the columns of L found so far.
+    pub fn decompose(matrix: &CscMatrix) -> Result<Lu, rulinalg::error::Error> {
+        Lu::decompose_ordered(matrix, minimum_degree(matrix))
+    }
+
+    // As `decompose`, with a column ordering already found for the matrix's
+    // pattern, e.g. by `Symbolic`.
+    pub fn decompose_ordered(matrix: &CscMatrix,
+                             col_order: Vec<usize>)
+                             -> Result<Lu, rulinalg::error::Error> {
+        let size = matrix.size;
+
+        let mut l = CscMatrix {
+            size: size,
+            col_ptrs: Vec::with_capacity(size + 1),
+            row_indexes: Vec::new(),
+            values: Vec::new(),
+        };
+        let mut u = l.clone();
+
+        let mut row_pivots: Vec<Option<usize>> = vec![None; size];
+        let mut x = vec![0f64; size];
+        let mut reach = Reach::new(size);
+
+        for k in 0..size {
+            l.col_ptrs.push(l.values.len());
+            u.col_ptrs.push(u.values.len());
+            let col = col_order[k];
+
+            // x = L \ A(:, col)
+            let pattern = reach.find(&l, matrix, col, &row_pivots);
+            for p in matrix.col(col) {
+                x[matrix.row_indexes[p]] = matrix.values[p];
+            }
+            for &j in pattern {
+                if let Some(pivot) = row_pivots[j] {
+                    // skip the unit diagonal
+                    for p in (l.col_ptrs[pivot] + 1)..l.col_ptrs[pivot + 1] {
+                        x[l.row_indexes[p]] -= l.values[p] * x[j];
+                    }
+                }
+            }
+
+            // rows already pivoted go in U, pick a pivot from the rest
+            let mut pivot_row = None;
+            let mut largest = 0f64;
+            for &i in pattern {
+                match row_pivots[i] {
+                    Some(pivot) => {
+                        u.row_indexes.push(pivot);
+                        u.values.push(x[i]);
+                    }
+                    None => {
+                        if x[i].abs() > largest {
+                            largest = x[i].abs();
+                            pivot_row = Some(i);
+                        }
+                    }
+                }
+            }
+            let mut pivot_row = match pivot_row {
+                Some(row) => row,
+                None => {
+                    return Err(rulinalg::error::Error::new(ErrorKind::DecompFailure,
+                                                           "Matrix is singular"))
+                }
+            };
+            if row_pivots[col].is_none() && x[col].abs() >= largest * PIVOT_TOLERANCE {
+                pivot_row = col;
+            }
+
+            let pivot = x[pivot_row];
+            u.row_indexes.push(k);
+            u.values.push(pivot);
+            row_pivots[pivot_row] = Some(k);
+
+            l.row_indexes.push(pivot_row);
+            l.values.push(1.0);
+            for &i in pattern {
+                if row_pivots[i].is_none() {
+                    l.row_indexes.push(i);
+                    l.values.push(x[i] / pivot);
+                }
+                x[i] = 0.0;
+            }
+        }
+        l.col_ptrs.push(l.values.len());
+        u.col_ptrs.push(u.values.len());
+
+        let row_pivots: Vec<usize> = row_pivots.into_iter().map(|p| p.unwrap()).collect();
+        for row in l.row_indexes.iter_mut() {
+            *row = row_pivots[*row];
+        }
+
+        Ok(Lu {
+            l: l,
+            u: u,
+            row_pivots: row_pivots,
+            col_order: col_order,
+        })
+    }
+
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let size = self.l.size;
+
+        let mut y = vec![0f64; size];
+        for (row, &x) in b.iter().enumerate() {
+            y[self.row_pivots[row]] = x;
+        }
+
+        // L y = P b
+        for j in 0..size {
+            for p in (self.l.col_ptrs[j] + 1)..self.l.col_ptrs[j + 1] {
+                y[self.l.row_indexes[p]] -= self.l.values[p] * y[j];
+            }
+        }
+
+        // U z = y
+        for j in (0..size).rev() {
+            let diagonal = self.u.col_ptrs[j + 1] - 1;
+            y[j] /= self.u.values[diagonal];
+            for p in self.u.col_ptrs[j]..diagonal {
+                y[self.u.row_indexes[p]] -= self.u.values[p] * y[j];
+            }
+        }
+
+        let mut x = vec![0f64; size];
+        for (k, &col) in self.col_order.iter().enumerate() {
+            x[col] = y[k];
+        }
+        x
+    }
+
+    // Number of non-zeros in L and U, as a measure of fill-in.
+    pub fn non_zeros(&self) -> usize {
+        self.l.non_zeros() + self.u.non_zeros()
+    }
+}
+
+// Finds the non-zero pattern of `L \ A(:, col)`, in topological order, by
+// depth-first search through the columns of L.
+#[derive(Debug)]
+struct Reach {
+    pattern: Vec<usize>,
+    visited: Vec<bool>,
+    stack: Vec<(usize, usize)>,
+}
+
+impl Reach {
+    fn new(size: usize) -> Self {
+        Reach {
+            pattern: Vec::with_capacity(size),
+            visited: vec![false; size],
+            stack: Vec::with_capacity(size),
+        }
+    }
+
+    fn find(&mut self,
+            l: &CscMatrix,
+            matrix: &CscMatrix,
+            col: usize,
+            row_pivots: &[Option<usize>])
+            -> &[usize] {
+        for &i in &self.pattern {
+            self.visited[i] = false;
+        }
+        self.pattern.clear();
+
+        for p in matrix.col(col) {
+            let start = matrix.row_indexes[p];
+            if self.visited[start] {
+                continue;
+            }
+            self.visited[start] = true;
+            self.stack.push((start, Reach::first_child(l, row_pivots, start)));
+
+            while let Some(&(j, next)) = self.stack.last() {
+                let end = match row_pivots[j] {
+                    Some(pivot) => l.col_ptrs[pivot + 1],
+                    None => next,
+                };
+                match (next..end).find(|&p| !self.visited[l.row_indexes[p]]) {
+                    Some(p) => {
+                        let child = l.row_indexes[p];
+                        self.visited[child] = true;
+                        self.stack.last_mut().unwrap().1 = p + 1;
+                        self.stack.push((child, Reach::first_child(l, row_pivots, child)));
+                    }
+                    None => {
+                        self.stack.pop();
+                        self.pattern.push(j);
+                    }
+                }
+            }
+        }
+
+        // finished in reverse topological order
+        self.pattern.reverse();
+        &self.pattern
+    }
+
+    fn first_child(l: &CscMatrix, row_pivots: &[Option<usize>], row: usize) -> usize {
+        match row_pivots[row] {
+            Some(pivot) => l.col_ptrs[pivot],
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn csc(size: usize, entries: &[(usize, usize, f64)]) -> CscMatrix {
+        let mut triplets = Triplets::new(size);
+        for &(row, col, x) in entries {
+            triplets.add(row, col, x);
+        }
+        CscMatrix::from_triplets(&triplets)
+    }
+
+    #[test]
+    fn duplicates_are_summed() {
+        let matrix = csc(2, &[(0, 0, 1.0), (1, 0, 2.0), (0, 0, 3.0), (1, 1, 4.0)]);
+
+        assert_eq!(matrix.col_ptrs, vec![0, 2, 3]);
+        assert_eq!(matrix.row_indexes, vec![0, 1, 1]);
+        assert_eq!(matrix.values, vec![4.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn minimum_degree() {
+        // a star, with node 0 in the middle
+        let matrix = csc(4,
+                         &[(0, 0, 3.0),
+                           (0, 1, -1.0),
+                           (1, 0, -1.0),
+                           (0, 2, -1.0),
+                           (2, 0, -1.0),
+                           (0, 3, -1.0),
+                           (3, 0, -1.0),
+                           (1, 1, 1.0),
+                           (2, 2, 1.0),
+                           (3, 3, 1.0)]);
+
+        // the middle can't go first, or everything else would be connected
+        assert_eq!(super::minimum_degree(&matrix), vec![1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn solve_with_zero_diagonal() {
+        // the same shape as a voltage source stamped into a circuit equation
+        let matrix = csc(3,
+                         &[(0, 0, 0.1),
+                           (0, 2, 1.0),
+                           (1, 1, 0.2),
+                           (1, 2, -1.0),
+                           (2, 0, 1.0),
+                           (2, 1, -1.0)]);
+
+        let lu = Lu::decompose(&matrix).unwrap();
+        let x = lu.solve(&[0.0, 0.0, 3.0]);
+
+        // check A x = b
+        let dense = {
+            let mut triplets = Triplets::new(3);
+            for col in 0..3 {
+                for p in matrix.col(col) {
+                    triplets.add(matrix.row_indexes[p], col, matrix.values[p]);
+                }
+            }
+            triplets.to_dense()
+        };
+        let b: Vec<f64> = dense.iter()
+            .map(|row| row.iter().zip(x.iter()).map(|(a, x)| a * x).sum())
+            .collect();
+        assert_approx_eq!(b[0], 0.0);
+        assert_approx_eq!(b[1], 0.0);
+        assert_approx_eq!(b[2], 3.0);
+    }
+
+    #[test]
+    fn symbolic() {
+        let mut first = Triplets::new(2);
+        for &(row, col, x) in &[(1, 1, 1.0), (0, 0, 2.0), (1, 0, 3.0), (1, 1, 4.0)] {
+            first.add(row, col, x);
+        }
+        let symbolic = Symbolic::analyse(&first);
+        assert_eq!(symbolic.assemble(&first), CscMatrix::from_triplets(&first));
+
+        // the same positions, with different values
+        let mut second = Triplets::new(2);
+        for &(row, col, x) in &[(1, 1, 5.0), (0, 0, 6.0), (1, 0, 7.0), (1, 1, 8.0)] {
+            second.add(row, col, x);
+        }
+        assert!(symbolic.matches(&second));
+        assert_eq!(symbolic.assemble(&second), CscMatrix::from_triplets(&second));
+
+        // a different structure
+        second.add(0, 1, 1.0);
+        assert!(!symbolic.matches(&second));
+    }
+
+    #[test]
+    fn singular() {
+        let matrix = csc(2, &[(0, 0, 1.0), (0, 1, 1.0), (1, 0, 1.0), (1, 1, 1.0)]);
+
+        assert!(Lu::decompose(&matrix).is_err());
+    }
+}
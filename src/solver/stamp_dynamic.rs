@@ -0,0 +1,492 @@
+use elements::Nodes;
+use elements::capacitor::Capacitor;
+use elements::capacitor::InternalCapacitance;
+use elements::capacitor::CompanionModel;
+use elements::capacitor::IntegrationMethod;
+use elements::inductor::Inductor;
+use elements::diode;
+use elements::diode::Diode;
+use elements::bjt::Bjt;
+use elements::mosfet::Mosfet;
+use elements::opamp::OpAmp;
+use elements::opamp::Model;
+use elements::switch::Switch;
+use elements::voltage_source::VoltageSource;
+use elements::current_source::CurrentSource;
+use elements::waveform::Waveform;
+use solver::equation::Equation;
+use solver::newton::Linearisation;
+
+// Stamp a voltage source with its waveform's value at the given time.
+pub fn stamp_voltage_source(equation: &mut Equation,
+                            vi: &mut VoltageSource,
+                            waveform: &Waveform,
+                            nodes: &Nodes,
+                            time: f64) {
+    vi.voltage = waveform.value(time);
+
+    let &Nodes(ref ns) = nodes;
+    let from_node = ns[vi.node_index_from()];
+    let to_node = ns[vi.node_index_to()];
+
+    equation.stamp_voltage_source(vi.voltage, from_node.index, to_node.index, vi.index);
+}
+
+// Stamp a current source with its waveform's value at the given time.
+pub fn stamp_current_source(equation: &mut Equation,
+                            ci: &mut CurrentSource,
+                            waveform: &Waveform,
+                            nodes: &Nodes,
+                            time: f64) {
+    ci.current = waveform.value(time);
+
+    let &Nodes(ref ns) = nodes;
+    let from_node = ns[ci.node_index_from()];
+    let to_node = ns[ci.node_index_to()];
+
+    equation.stamp_current_source(ci.current, from_node.index, to_node.index);
+}
+
+// Stamp the companion model of a capacitor for the next timestep, based on
+// its voltage and current at the end of the previous timestep.
+//
+// Forward Euler gives no conductance term, so it is always stamped as a
+// Thevenin equivalent (i.e. a voltage source).
+pub fn stamp_capacitor(equation: &mut Equation,
+                       cap: &mut Capacitor,
+                       nodes: &Nodes,
+                       prev_current: f64,
+                       timestep: f64) {
+    let &Nodes(ref ns) = nodes;
+    let n0 = ns[cap.node_indexes.0];
+    let n1 = ns[cap.node_indexes.1];
+    let previous_voltage = n0.voltage - n1.voltage;
+
+    match (cap.companion_model, cap.integration_method) {
+        (CompanionModel::Norton, IntegrationMethod::Trapezoidal) => {
+            let conductance = (2.0 * cap.capacitance) / timestep;
+            cap.resistor.set_conductance(conductance);
+            cap.current_source.current = prev_current + (conductance * previous_voltage);
+        }
+        (CompanionModel::Norton, IntegrationMethod::BackwardEuler) => {
+            let conductance = cap.capacitance / timestep;
+            cap.resistor.set_conductance(conductance);
+            cap.current_source.current = conductance * previous_voltage;
+        }
+        (CompanionModel::Thevenin, IntegrationMethod::Trapezoidal) => {
+            let resistance = timestep / (2.0 * cap.capacitance);
+            cap.resistor.set_resistance(resistance);
+            cap.voltage_source.voltage = previous_voltage + (resistance * prev_current);
+        }
+        (CompanionModel::Thevenin, IntegrationMethod::BackwardEuler) => {
+            let resistance = timestep / cap.capacitance;
+            cap.resistor.set_resistance(resistance);
+            cap.voltage_source.voltage = previous_voltage;
+        }
+        (_, IntegrationMethod::ForwardEuler) => {
+            cap.resistor.set_resistance(0.0);
+            cap.voltage_source.voltage = previous_voltage +
+                                         (timestep * prev_current / cap.capacitance);
+        }
+    }
+
+    if cap.uses_branch() {
+        // voltage across the capacitor is V(n0) - V(n1), so the source goes
+        // from n1 to n0
+        equation.stamp_voltage_source_with_resistance(cap.voltage_source.voltage,
+                                                      cap.resistor.resistance(),
+                                                      n1.index,
+                                                      n0.index,
+                                                      cap.voltage_source.index);
+    } else {
+        equation.stamp_conductance(cap.resistor.conductance(), n0.index, n1.index);
+        equation.stamp_current_source(cap.current_source.current, n1.index, n0.index);
+    }
+}
+
+// Current flowing through a capacitor (from its first node to its second)
+// given the solution to the equation.
+pub fn capacitor_current(cap: &Capacitor,
+                         nodes: &Nodes,
+                         voltages: &[f64],
+                         currents: &[f64])
+                         -> f64 {
+    if cap.uses_branch() {
+        // the branch current flows from n1 to n0
+        return -currents[cap.voltage_source.index];
+    }
+
+    let &Nodes(ref ns) = nodes;
+    let n0 = ns[cap.node_indexes.0];
+    let n1 = ns[cap.node_indexes.1];
+
+    let resistor_current = (voltages[n0.index] - voltages[n1.index]) *
+                           cap.resistor.conductance();
+
+    resistor_current - cap.current_source.current
+}
+
+// Stamp the trapezoidal Norton companion model of a capacitance inside
+// another element for the next timestep.
+pub fn stamp_internal_capacitance(equation: &mut Equation,
+                                  cap: &mut InternalCapacitance,
+                                  nodes: &Nodes,
+                                  timestep: f64) {
+    let &Nodes(ref ns) = nodes;
+    let n0 = ns[cap.node_indexes.0];
+    let n1 = ns[cap.node_indexes.1];
+    let previous_voltage = n0.voltage - n1.voltage;
+
+    cap.conductance = (2.0 * cap.capacitance) / timestep;
+    cap.companion_current = cap.current + (cap.conductance * previous_voltage);
+
+    equation.stamp_conductance(cap.conductance, n0.index, n1.index);
+    equation.stamp_current_source(cap.companion_current, n1.index, n0.index);
+}
+
+// Current flowing through a capacitance inside another element (from its
+// first node to its second) given the solved node voltages.
+pub fn internal_capacitance_current(cap: &InternalCapacitance,
+                                    nodes: &Nodes,
+                                    voltages: &[f64])
+                                    -> f64 {
+    let &Nodes(ref ns) = nodes;
+    let n0 = ns[cap.node_indexes.0];
+    let n1 = ns[cap.node_indexes.1];
+
+    (voltages[n0.index] - voltages[n1.index]) * cap.conductance - cap.companion_current
+}
+
+// Stamp the trapezoidal Norton companion model of an inductor for the next
+// timestep.
+pub fn stamp_inductor(equation: &mut Equation,
+                      ind: &mut Inductor,
+                      nodes: &Nodes,
+                      prev_current: f64,
+                      timestep: f64) {
+    let conductance = timestep / (2.0 * ind.inductance);
+    ind.resistor.set_conductance(conductance);
+
+    let &Nodes(ref ns) = nodes;
+    let n0 = ns[ind.node_indexes.0];
+    let n1 = ns[ind.node_indexes.1];
+    let previous_voltage = n0.voltage - n1.voltage;
+    ind.current_source.current = prev_current + (conductance * previous_voltage);
+
+    equation.stamp_conductance(ind.resistor.conductance(), n0.index, n1.index);
+    equation.stamp_current_source(ind.current_source.current, n0.index, n1.index);
+}
+
+// Current flowing through an inductor (from its first node to its second)
+// given the solved node voltages.
+pub fn inductor_current(ind: &Inductor, nodes: &Nodes, voltages: &[f64]) -> f64 {
+    let &Nodes(ref ns) = nodes;
+    let n0 = ns[ind.node_indexes.0];
+    let n1 = ns[ind.node_indexes.1];
+
+    let resistor_current = (voltages[n0.index] - voltages[n1.index]) *
+                           ind.resistor.conductance();
+
+    resistor_current + ind.current_source.current
+}
+
+// Stamp a switch's contacts in their current state.
+pub fn stamp_switch(equation: &mut Equation, switch: &Switch, nodes: &Nodes) {
+    let &Nodes(ref ns) = nodes;
+    let common = ns[0].index;
+
+    for (node, resistance) in switch.contacts() {
+        equation.stamp_resistor(resistance, common, ns[node].index);
+    }
+}
+
+// Current flowing into a switch's common terminal, given the node voltages.
+pub fn switch_current(switch: &Switch, nodes: &Nodes, voltages: &[f64]) -> f64 {
+    let &Nodes(ref ns) = nodes;
+    let common = voltages[ns[0].index];
+
+    switch.contacts()
+        .iter()
+        .map(|&(node, resistance)| (common - voltages[ns[node].index]) / resistance)
+        .sum()
+}
+
+// Minimum conductance stamped across non-linear junctions, to keep the
+// equation solvable when they are reverse biased.
+pub const GMIN: f64 = 1e-12;
+
+// Stamp a diode linearised around the voltages from the previous
+// Newton-Raphson iteration.
+pub fn stamp_diode(equation: &mut Equation,
+                   diode: &mut Diode,
+                   nodes: &Nodes,
+                   voltages: &[f64])
+                   -> Linearisation {
+    let &Nodes(ref ns) = nodes;
+    let anode = ns[diode.node_indexes.0];
+    let cathode = ns[diode.node_indexes.1];
+
+    let new_voltage = voltages[anode.index] - voltages[cathode.index];
+    let voltage = diode.limit_voltage(new_voltage, diode.junction_voltage);
+    diode.junction_voltage = voltage;
+
+    let conductance = diode.conductance(voltage) + GMIN;
+    diode.resistor.set_conductance(conductance);
+    diode.current_source.current = diode.current(voltage) - (conductance * voltage);
+
+    equation.stamp_conductance(diode.resistor.conductance(), anode.index, cathode.index);
+    equation.stamp_current_source(diode.current_source.current, anode.index, cathode.index);
+
+    if voltage == new_voltage {
+        Linearisation::Exact
+    } else {
+        Linearisation::Limited
+    }
+}
+
+// Current flowing through a diode (from anode to cathode) once the equation
+// has been solved.
+pub fn diode_current(diode: &Diode, nodes: &Nodes) -> f64 {
+    let &Nodes(ref ns) = nodes;
+    let anode = ns[diode.node_indexes.0];
+    let cathode = ns[diode.node_indexes.1];
+
+    let resistor_current = (anode.voltage - cathode.voltage) * diode.resistor.conductance();
+
+    resistor_current + diode.current_source.current
+}
+
+// Stamp a BJT linearised around the voltages from the previous
+// Newton-Raphson iteration: the collector and base currents as
+// voltage-controlled current sources to the emitter, plus Norton equivalents
+// for their values at the bias point.
+pub fn stamp_bjt(equation: &mut Equation,
+                 bjt: &mut Bjt,
+                 nodes: &Nodes,
+                 voltages: &[f64])
+                 -> Linearisation {
+    let &Nodes(ref ns) = nodes;
+    let collector = ns[bjt.node_indexes.0].index;
+    let base = ns[bjt.node_indexes.1].index;
+    let emitter = ns[bjt.node_indexes.2].index;
+
+    let sign = bjt.polarity.sign();
+    let critical_voltage = bjt.critical_voltage();
+    let new_base_emitter = sign * (voltages[base] - voltages[emitter]);
+    let new_base_collector = sign * (voltages[base] - voltages[collector]);
+    let base_emitter = diode::limit_junction_voltage(new_base_emitter,
+                                                     bjt.base_emitter_voltage,
+                                                     diode::THERMAL_VOLTAGE,
+                                                     critical_voltage);
+    let base_collector = diode::limit_junction_voltage(new_base_collector,
+                                                       bjt.base_collector_voltage,
+                                                       diode::THERMAL_VOLTAGE,
+                                                       critical_voltage);
+    bjt.base_emitter_voltage = base_emitter;
+    bjt.base_collector_voltage = base_collector;
+
+    let small_signal = bjt.small_signal(base_emitter, base_collector);
+    let (gc_be, gc_bc) = small_signal.collector_conductances;
+    let (gb_be, gb_bc) = small_signal.base_conductances;
+
+    // the sign cancels out of the conductances, but not the currents
+    equation.stamp_vccs(gc_be, collector, emitter, emitter, base);
+    equation.stamp_vccs(gc_bc, collector, emitter, collector, base);
+    equation.stamp_vccs(gb_be, base, emitter, emitter, base);
+    equation.stamp_vccs(gb_bc, base, emitter, collector, base);
+    equation.stamp_current_source(sign *
+                                  (small_signal.collector_current - gc_be * base_emitter -
+                                   gc_bc * base_collector),
+                                  collector,
+                                  emitter);
+    equation.stamp_current_source(sign *
+                                  (small_signal.base_current - gb_be * base_emitter -
+                                   gb_bc * base_collector),
+                                  base,
+                                  emitter);
+
+    equation.stamp_conductance(GMIN, base, emitter);
+    equation.stamp_conductance(GMIN, base, collector);
+
+    if base_emitter == new_base_emitter && base_collector == new_base_collector {
+        Linearisation::Exact
+    } else {
+        Linearisation::Limited
+    }
+}
+
+// Stamp the companion models of a BJT's junction capacitances for the next
+// timestep.
+pub fn stamp_bjt_capacitors(equation: &mut Equation,
+                            bjt: &mut Bjt,
+                            nodes: &Nodes,
+                            timestep: f64) {
+    stamp_internal_capacitance(equation, &mut bjt.base_emitter_capacitance, nodes, timestep);
+    stamp_internal_capacitance(equation, &mut bjt.base_collector_capacitance, nodes, timestep);
+}
+
+// Keep the current through each of a BJT's junction capacitances, given the
+// solution to the equation, for the next timestep.
+pub fn update_bjt_capacitor_currents(bjt: &mut Bjt, nodes: &Nodes, voltages: &[f64]) {
+    bjt.base_emitter_capacitance.current =
+        internal_capacitance_current(&bjt.base_emitter_capacitance, nodes, voltages);
+    bjt.base_collector_capacitance.current =
+        internal_capacitance_current(&bjt.base_collector_capacitance, nodes, voltages);
+}
+
+// Current flowing into a BJT's collector once the equation has been solved,
+// including through its base-collector capacitance.
+pub fn bjt_collector_current(bjt: &Bjt, nodes: &Nodes) -> f64 {
+    let &Nodes(ref ns) = nodes;
+    let collector = ns[bjt.node_indexes.0];
+    let base = ns[bjt.node_indexes.1];
+    let emitter = ns[bjt.node_indexes.2];
+
+    let (collector_current, _) = bjt.currents(base.voltage - emitter.voltage,
+                                              base.voltage - collector.voltage);
+
+    collector_current - bjt.base_collector_capacitance.current
+}
+
+// Stamp a MOSFET linearised around the voltages from the previous
+// Newton-Raphson iteration: the drain current as voltage-controlled current
+// sources to the source, plus a Norton equivalent for its value at the bias
+// point.
+pub fn stamp_mosfet(equation: &mut Equation,
+                    mosfet: &mut Mosfet,
+                    nodes: &Nodes,
+                    voltages: &[f64])
+                    -> Linearisation {
+    let &Nodes(ref ns) = nodes;
+    let drain = ns[mosfet.node_indexes.0].index;
+    let gate = ns[mosfet.node_indexes.1].index;
+    let source = ns[mosfet.node_indexes.2].index;
+    let body = ns[mosfet.node_indexes.3].index;
+
+    let sign = mosfet.polarity.sign();
+    let new_gate_source = sign * (voltages[gate] - voltages[source]);
+    let new_drain_source = sign * (voltages[drain] - voltages[source]);
+    let body_source = sign * (voltages[body] - voltages[source]);
+    let (gate_source, drain_source) = mosfet.limit_voltages(new_gate_source, new_drain_source);
+    mosfet.gate_source_voltage = gate_source;
+    mosfet.drain_source_voltage = drain_source;
+    mosfet.body_source_voltage = body_source;
+
+    let small_signal = mosfet.small_signal(gate_source, drain_source, body_source);
+    let gm = small_signal.transconductance;
+    let gds = small_signal.output_conductance;
+    let gmbs = small_signal.body_transconductance;
+
+    // the sign cancels out of the conductances, but not the current
+    equation.stamp_vccs(gm, drain, source, source, gate);
+    equation.stamp_vccs(gds, drain, source, source, drain);
+    equation.stamp_vccs(gmbs, drain, source, source, body);
+    equation.stamp_current_source(sign *
+                                  (small_signal.drain_current - gm * gate_source -
+                                   gds * drain_source - gmbs * body_source),
+                                  drain,
+                                  source);
+
+    // in place of the body junctions, and to keep the channel solvable when
+    // it's cut off
+    equation.stamp_conductance(GMIN, drain, source);
+    equation.stamp_conductance(GMIN, body, source);
+    equation.stamp_conductance(GMIN, body, drain);
+
+    if gate_source == new_gate_source && drain_source == new_drain_source {
+        Linearisation::Exact
+    } else {
+        Linearisation::Limited
+    }
+}
+
+// Stamp the companion models of a MOSFET's gate capacitances for the next
+// timestep.
+pub fn stamp_mosfet_capacitors(equation: &mut Equation,
+                               mosfet: &mut Mosfet,
+                               nodes: &Nodes,
+                               timestep: f64) {
+    stamp_internal_capacitance(equation, &mut mosfet.gate_source_capacitance, nodes, timestep);
+    stamp_internal_capacitance(equation, &mut mosfet.gate_drain_capacitance, nodes, timestep);
+}
+
+// Keep the current through each of a MOSFET's gate capacitances, given the
+// solution to the equation, for the next timestep.
+pub fn update_mosfet_capacitor_currents(mosfet: &mut Mosfet, nodes: &Nodes, voltages: &[f64]) {
+    mosfet.gate_source_capacitance.current =
+        internal_capacitance_current(&mosfet.gate_source_capacitance, nodes, voltages);
+    mosfet.gate_drain_capacitance.current =
+        internal_capacitance_current(&mosfet.gate_drain_capacitance, nodes, voltages);
+}
+
+// Current flowing into a MOSFET's drain once the equation has been solved,
+// including through its gate-drain capacitance.
+pub fn mosfet_drain_current(mosfet: &Mosfet, nodes: &Nodes) -> f64 {
+    let &Nodes(ref ns) = nodes;
+    let drain = ns[mosfet.node_indexes.0];
+    let gate = ns[mosfet.node_indexes.1];
+    let source = ns[mosfet.node_indexes.2];
+    let body = ns[mosfet.node_indexes.3];
+
+    let drain_current = mosfet.current(gate.voltage - source.voltage,
+                                       drain.voltage - source.voltage,
+                                       body.voltage - source.voltage);
+
+    drain_current - mosfet.gate_drain_capacitance.current
+}
+
+// Stamp the output of an op-amp's macro model, linearised around the
+// voltages from the previous Newton-Raphson iteration: either following the
+// inputs through its pole, or clamped to a rail.
+//
+// The pole is integrated over `timestep`, from the op-amp's
+// `internal_voltage`, or is ignored at DC (`None`). Ideal op-amps are linear,
+// and are stamped with the static elements.
+pub fn stamp_opamp(equation: &mut Equation,
+                   opamp: &OpAmp,
+                   nodes: &Nodes,
+                   voltages: &[f64],
+                   timestep: Option<f64>)
+                   -> Linearisation {
+    let model = match opamp.model {
+        Model::Ideal => return Linearisation::Linear,
+        Model::Macro(ref model) => model,
+    };
+
+    let &Nodes(ref ns) = nodes;
+    let non_inverting = ns[opamp.input_node_indexes.0].index;
+    let inverting = ns[opamp.input_node_indexes.1].index;
+    let output = ns[opamp.output_node_index].index;
+
+    let (gain, offset) = model.transfer(opamp.internal_voltage, timestep);
+    let input_voltage = voltages[non_inverting] - voltages[inverting];
+    let (gain, offset) = match model.saturation(gain * input_voltage + offset) {
+        Some(rail) => (0.0, rail),
+        None => (gain, offset),
+    };
+
+    equation.stamp_voltage_source_with_resistance(offset,
+                                                  model.output_resistance,
+                                                  0,
+                                                  output,
+                                                  opamp.index);
+    equation.stamp_voltage_gain(gain, inverting, non_inverting, opamp.index);
+
+    Linearisation::Exact
+}
+
+// The voltage driving an op-amp's output (before any output resistance) once
+// the equation has been solved, i.e. its next `internal_voltage`.
+pub fn opamp_internal_voltage(opamp: &OpAmp, nodes: &Nodes, timestep: Option<f64>) -> f64 {
+    let &Nodes(ref ns) = nodes;
+
+    match opamp.model {
+        Model::Ideal => ns[opamp.output_node_index].voltage,
+        Model::Macro(ref model) => {
+            let input_voltage = ns[opamp.input_node_indexes.0].voltage -
+                                ns[opamp.input_node_indexes.1].voltage;
+            let (gain, offset) = model.transfer(opamp.internal_voltage, timestep);
+            model.clamp(gain * input_voltage + offset)
+        }
+    }
+}
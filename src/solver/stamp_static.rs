@@ -3,13 +3,21 @@ use elements::Nodes;
 use elements::resistor::Resistor;
 use elements::current_source::CurrentSource;
 use elements::voltage_source::VoltageSource;
+use elements::capacitor::Capacitor;
+use elements::waveform::Waveform;
+use elements::vcvs::Vcvs;
+use elements::vccs::Vccs;
+use elements::ccvs::Ccvs;
+use elements::cccs::Cccs;
+use elements::opamp::OpAmp;
+use elements::opamp::Model;
 use solver::equation;
 
 // Create an equation builder with all static parts of the circuit stamped.
 //
-// Elements that change over time, (e.g. sine wave sources, capacitors), or
-// need linearization
-// (e.g. diodes) don't stamp here.
+// Elements that change over time, (e.g. sine wave sources, capacitors,
+// switches), or need linearization (e.g. diodes, op-amp macro models) don't
+// stamp here.
 //
 // Should be called whenever the circuit is modified.
 //
@@ -17,37 +25,96 @@ use solver::equation;
 // cycle, and has no
 // dependency on Delta.
 pub fn create_static_equation(world: &mut specs::World) -> equation::Equation {
+    create_static_equation_with_backend(world, equation::Backend::default())
+}
+
+// As `create_static_equation`, choosing how the equation is stored and
+// solved.
+pub fn create_static_equation_with_backend(world: &mut specs::World,
+                                           backend: equation::Backend)
+                                           -> equation::Equation {
+    let num_branches = assign_branches(world);
+    let num_nodes = count_nodes(world);
+
+    let mut equation = equation::Equation::with_backend(num_nodes, num_branches, backend);
+    stamp_static_elements(world, &mut equation);
+    equation
+}
+
+// Assign all voltage inputs a branch index, followed by any capacitors which
+// need a voltage source branch for their companion model, then controlled
+// voltage sources, then op-amp outputs. Returns the number of branches.
+pub fn assign_branches(world: &mut specs::World) -> usize {
     use specs::Join;
     use specs::Gate;
 
-    // assign all voltage inputs an index
-    {
-        let mut v_sources = world.write::<VoltageSource>().pass();
-        for (i, (ref mut vi,)) in (&mut v_sources,).join().enumerate() {
-            vi.index = i;
+    let mut v_sources = world.write::<VoltageSource>().pass();
+    let mut capacitors = world.write::<Capacitor>().pass();
+    let mut vcvs = world.write::<Vcvs>().pass();
+    let mut ccvs = world.write::<Ccvs>().pass();
+    let mut opamps = world.write::<OpAmp>().pass();
+
+    let mut num_branches = 0;
+    for (ref mut vi,) in (&mut v_sources,).join() {
+        vi.index = num_branches;
+        num_branches += 1;
+    }
+    for (ref mut cap,) in (&mut capacitors,).join() {
+        if cap.uses_branch() {
+            cap.voltage_source.index = num_branches;
+            num_branches += 1;
         }
     }
+    for (ref mut source,) in (&mut vcvs,).join() {
+        source.index = num_branches;
+        num_branches += 1;
+    }
+    for (ref mut source,) in (&mut ccvs,).join() {
+        source.index = num_branches;
+        num_branches += 1;
+    }
+    for (ref mut opamp,) in (&mut opamps,).join() {
+        opamp.index = num_branches;
+        num_branches += 1;
+    }
+    num_branches
+}
+
+// The number of nodes in the circuit, including ground.
+pub fn count_nodes(world: &specs::World) -> usize {
+    use specs::Join;
+    use specs::Gate;
 
+    let nodes_ticket = world.read::<Nodes>().pass();
+    match (&nodes_ticket,)
+        .join()
+        .flat_map(|(&Nodes(ref ns),)| ns.iter())
+        .max_by(|n1, n2| n1.index.cmp(&n2.index)) {
+        Some(node) => node.index + 1,
+        None => 0,
+    }
+}
+
+// Stamp the static parts of the circuit into an equation, once branches
+// have been assigned.
+pub fn stamp_static_elements(world: &specs::World, equation: &mut equation::Equation) {
+    use specs::Join;
+    use specs::Gate;
+
+    let entities = world.entities();
     let nodes_ticket = world.read::<Nodes>().pass();
     let v_sources = world.read::<VoltageSource>().pass();
     let c_sources = world.read::<CurrentSource>().pass();
     let resistors = world.read::<Resistor>().pass();
+    let waveforms = world.read::<Waveform>().pass();
+    let vcvs = world.read::<Vcvs>().pass();
+    let vccs = world.read::<Vccs>().pass();
+    let ccvs = world.read::<Ccvs>().pass();
+    let cccs = world.read::<Cccs>().pass();
+    let opamps = world.read::<OpAmp>().pass();
 
-    let mut equation = {
-        let num_nodes: usize = match (&nodes_ticket,)
-            .join()
-            .flat_map(|(&Nodes(ref ns),)| ns.iter())
-            .max_by(|n1, n2| n1.index.cmp(&n2.index)) {
-            Some(node) => node.index + 1,
-            None => 0,
-        };
-        let num_v_sources: usize = (&v_sources,).join().count();
-
-        equation::Equation::new(num_nodes, num_v_sources)
-    };
-
-    // Current sources
-    for (nodes, ci) in (&nodes_ticket, &c_sources).join() {
+    // Current sources, unless time-varying
+    for (nodes, ci, _) in (&nodes_ticket, &c_sources, !&waveforms).join() {
         let &Nodes(ref ns) = nodes;
         let from_node = ns[ci.node_index_from()];
         let to_node = ns[ci.node_index_to()];
@@ -55,8 +122,8 @@ pub fn create_static_equation(world: &mut specs::World) -> equation::Equation {
         equation.stamp_current_source(ci.current, from_node.index, to_node.index);
     }
 
-    // Voltage sources
-    for (nodes, vi) in (&nodes_ticket, &v_sources).join() {
+    // Voltage sources, unless time-varying
+    for (nodes, vi, _) in (&nodes_ticket, &v_sources, !&waveforms).join() {
         let &Nodes(ref ns) = nodes;
         let from_node = ns[vi.node_index_from()];
         let to_node = ns[vi.node_index_to()];
@@ -73,5 +140,68 @@ pub fn create_static_equation(world: &mut specs::World) -> equation::Equation {
         equation.stamp_resistor(res.resistance(), n0.index, n1.index);
     }
 
-    equation
+    // Controlled sources
+    for (nodes, source) in (&nodes_ticket, &vcvs).join() {
+        let &Nodes(ref ns) = nodes;
+        equation.stamp_vcvs(source.gain,
+                            ns[source.node_indexes.0].index,
+                            ns[source.node_indexes.1].index,
+                            ns[source.control_node_indexes.0].index,
+                            ns[source.control_node_indexes.1].index,
+                            source.index);
+    }
+    for (nodes, source) in (&nodes_ticket, &vccs).join() {
+        let &Nodes(ref ns) = nodes;
+        equation.stamp_vccs(source.transconductance,
+                            ns[source.node_indexes.0].index,
+                            ns[source.node_indexes.1].index,
+                            ns[source.control_node_indexes.0].index,
+                            ns[source.control_node_indexes.1].index);
+    }
+    for (entity, nodes, source) in (&entities, &nodes_ticket, &ccvs).join() {
+        let &Nodes(ref ns) = nodes;
+        match source.control.and_then(|control| v_sources.get(control)) {
+            Some(control) => {
+                equation.stamp_ccvs(source.transresistance,
+                                    ns[source.node_indexes.0].index,
+                                    ns[source.node_indexes.1].index,
+                                    control.index,
+                                    source.index)
+            }
+            None => equation.stamp_unresolved_control(entity),
+        };
+    }
+    for (entity, nodes, source) in (&entities, &nodes_ticket, &cccs).join() {
+        let &Nodes(ref ns) = nodes;
+        match source.control.and_then(|control| v_sources.get(control)) {
+            Some(control) => {
+                equation.stamp_cccs(source.gain,
+                                    ns[source.node_indexes.0].index,
+                                    ns[source.node_indexes.1].index,
+                                    control.index)
+            }
+            None => equation.stamp_unresolved_control(entity),
+        };
+    }
+
+    // Op-amps: the macro model's output is stamped on each Newton-Raphson
+    // iteration, to clamp it to the rails
+    for (nodes, opamp) in (&nodes_ticket, &opamps).join() {
+        let &Nodes(ref ns) = nodes;
+        let non_inverting = ns[opamp.input_node_indexes.0].index;
+        let inverting = ns[opamp.input_node_indexes.1].index;
+
+        match opamp.model {
+            Model::Ideal => {
+                equation.stamp_nullor(inverting,
+                                      non_inverting,
+                                      0,
+                                      ns[opamp.output_node_index].index,
+                                      opamp.index);
+            }
+            Model::Macro(ref model) => {
+                equation.stamp_resistor(model.input_resistance, non_inverting, inverting);
+            }
+        }
+    }
 }
@@ -1,27 +1,68 @@
 
+use std::f64::consts::PI;
+use specs;
+use Delta;
+use solver::solve;
 use solver::solve::SIM_TIME_PER_SEC;
 use solver::tests::create_planner;
+use solver::tests::create_planner_with;
+use solver::tests::create_planner_without_resources;
 use solver::tests::run_loop_iteration_for_delta;
+use elements::capacitor;
+use elements::capacitor::CompanionModel;
+use elements::capacitor::IntegrationMethod;
+use elements::opamp;
 
 const V: f64 = 5.0;
 const R: f64 = 100.0;
 const C: f64 = 5e-6;
+const L: f64 = 50e-3;
 
 const TIME_CONSTANT: f64 = R * C;
+const RL_TIME_CONSTANT: f64 = L / R;
 
 const T: f64 = TIME_CONSTANT; // simulate one time constant
+const RL_T: f64 = RL_TIME_CONSTANT;
 
 const ACCEPTABLE_DIFF: f64 = 0.02; // 2% leeway is a bit much, but hey-ho
 
 #[test]
 fn resistor_capacitor() {
+    resistor_capacitor_with(CompanionModel::Norton, IntegrationMethod::Trapezoidal);
+}
+
+#[test]
+fn resistor_capacitor_norton_backward_euler() {
+    resistor_capacitor_with(CompanionModel::Norton, IntegrationMethod::BackwardEuler);
+}
+
+#[test]
+fn resistor_capacitor_norton_forward_euler() {
+    resistor_capacitor_with(CompanionModel::Norton, IntegrationMethod::ForwardEuler);
+}
+
+#[test]
+fn resistor_capacitor_thevenin_trapezoidal() {
+    resistor_capacitor_with(CompanionModel::Thevenin, IntegrationMethod::Trapezoidal);
+}
+
+#[test]
+fn resistor_capacitor_thevenin_backward_euler() {
+    resistor_capacitor_with(CompanionModel::Thevenin, IntegrationMethod::BackwardEuler);
+}
+
+#[test]
+fn resistor_capacitor_thevenin_forward_euler() {
+    resistor_capacitor_with(CompanionModel::Thevenin, IntegrationMethod::ForwardEuler);
+}
+
+fn resistor_capacitor_with(companion_model: CompanionModel, integration_method: IntegrationMethod) {
     use specs::Gate;
 
     use elements::Nodes;
     use elements::CalculatedCurrent;
     use elements::DerivedCurrent;
     use elements::resistor;
-    use elements::capacitor;
     use elements::voltage_source;
 
     // expectations after time = T
@@ -47,6 +88,8 @@ fn resistor_capacitor() {
         let mut cs = world.write::<capacitor::Capacitor>().pass();
         let c = cs.get_mut(capacitor).unwrap();
         c.capacitance = C;
+        c.companion_model = companion_model;
+        c.integration_method = integration_method;
 
         let mut vs = world.write::<voltage_source::VoltageSource>().pass();
         let v = vs.get_mut(voltage_source).unwrap();
@@ -106,3 +149,1034 @@ fn resistor_capacitor() {
         }
     }
 }
+
+#[test]
+fn resistor_inductor() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::CalculatedCurrent;
+    use elements::DerivedCurrent;
+    use elements::resistor;
+    use elements::inductor;
+    use elements::voltage_source;
+
+    // expectations after time = RL_T
+    let i_l = (V / R) * (1.0 - (-RL_T / RL_TIME_CONSTANT).exp()); // current through resistor and inductor
+    let v_l = V * (-RL_T / RL_TIME_CONSTANT).exp(); // voltage across inductor
+
+    // Set up world
+    let mut planner = create_planner();
+
+    // Create an RL circuit
+    let (resistor, inductor, voltage_source) = {
+        let world = planner.mut_world();
+        let resistor = resistor::create(world);
+        let inductor = inductor::create(world);
+        let voltage_source = voltage_source::create(world);
+
+        let mut rs = world.write::<resistor::Resistor>().pass();
+        let r = rs.get_mut(resistor).unwrap();
+        r.set_resistance(R);
+
+        let mut ls = world.write::<inductor::Inductor>().pass();
+        let l = ls.get_mut(inductor).unwrap();
+        l.inductance = L;
+
+        let mut vs = world.write::<voltage_source::VoltageSource>().pass();
+        let v = vs.get_mut(voltage_source).unwrap();
+        v.voltage = V;
+
+        (resistor, inductor, voltage_source)
+    };
+
+    // Assign node IDs
+    {
+        let world = planner.mut_world();
+        let mut nodes = world.write::<Nodes>().pass();
+
+        match nodes.get_mut(voltage_source).unwrap() {
+            &mut Nodes(ref mut voltage_source_nodes) => {
+                voltage_source_nodes[0].index = 0;
+                voltage_source_nodes[1].index = 1;
+            }
+        }
+        match nodes.get_mut(resistor).unwrap() {
+            &mut Nodes(ref mut resistor_nodes) => {
+                resistor_nodes[0].index = 1;
+                resistor_nodes[1].index = 2;
+            }
+        }
+        match nodes.get_mut(inductor).unwrap() {
+            &mut Nodes(ref mut inductor_nodes) => {
+                inductor_nodes[0].index = 2;
+                inductor_nodes[1].index = 0;
+            }
+        }
+    }
+
+    run_loop_iteration_for_delta(&mut planner, RL_T / SIM_TIME_PER_SEC);
+
+    // Assert the circuit elements have the correct state
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(inductor).unwrap() {
+        &Nodes(ref inductor_nodes) => {
+            assert_eq!(inductor_nodes[1].voltage, 0f64);
+            assert_approx_eq!(inductor_nodes[0].voltage, v_l, v_l * ACCEPTABLE_DIFF);
+        }
+    }
+
+    let currents = world.read::<CalculatedCurrent>().pass();
+    match currents.get(voltage_source).unwrap() {
+        &CalculatedCurrent(current) => {
+            assert_approx_eq!(current, i_l, i_l * ACCEPTABLE_DIFF);
+        }
+    }
+
+    let currents = world.read::<DerivedCurrent>().pass();
+    match currents.get(inductor).unwrap() {
+        &DerivedCurrent(current) => {
+            assert_approx_eq!(current, i_l, i_l * ACCEPTABLE_DIFF);
+        }
+    }
+}
+
+#[test]
+fn resistor_pulse_voltage_source() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::CalculatedCurrent;
+    use elements::resistor;
+    use elements::waveform;
+    use elements::waveform::Waveform;
+
+    // Set up world
+    let mut planner = create_planner();
+
+    // Create a resistor across a pulse source, which switches on part way
+    // through the simulation
+    let (resistor, voltage_source) = {
+        let world = planner.mut_world();
+        let resistor = resistor::create(world);
+        let voltage_source = waveform::create_voltage_source(world,
+                                                             Waveform::Pulse {
+                                                                 initial: 0.0,
+                                                                 pulsed: V,
+                                                                 delay: T / 4.0,
+                                                                 rise: T / 10.0,
+                                                                 fall: T / 10.0,
+                                                                 width: T,
+                                                                 period: 0.0,
+                                                             });
+
+        let mut rs = world.write::<resistor::Resistor>().pass();
+        let r = rs.get_mut(resistor).unwrap();
+        r.set_resistance(R);
+
+        (resistor, voltage_source)
+    };
+
+    // Assign node IDs
+    {
+        let world = planner.mut_world();
+        let mut nodes = world.write::<Nodes>().pass();
+
+        match nodes.get_mut(voltage_source).unwrap() {
+            &mut Nodes(ref mut voltage_source_nodes) => {
+                voltage_source_nodes[0].index = 0;
+                voltage_source_nodes[1].index = 1;
+            }
+        }
+        match nodes.get_mut(resistor).unwrap() {
+            &mut Nodes(ref mut resistor_nodes) => {
+                resistor_nodes[0].index = 0;
+                resistor_nodes[1].index = 1;
+            }
+        }
+    }
+
+    run_loop_iteration_for_delta(&mut planner, T / SIM_TIME_PER_SEC);
+
+    // Assert the pulse has been applied
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(resistor).unwrap() {
+        &Nodes(ref resistor_nodes) => {
+            assert_eq!(resistor_nodes[0].voltage, 0f64);
+            assert_approx_eq!(resistor_nodes[1].voltage, V);
+        }
+    }
+
+    let currents = world.read::<CalculatedCurrent>().pass();
+    match currents.get(voltage_source).unwrap() {
+        &CalculatedCurrent(current) => {
+            assert_approx_eq!(current, V / R);
+        }
+    }
+}
+
+#[test]
+fn resistor_piecewise_linear_current_source() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::resistor;
+    use elements::waveform;
+    use elements::waveform::Waveform;
+
+    const I: f64 = 0.02;
+
+    // Set up world
+    let mut planner = create_planner();
+
+    // Create a resistor across a current source which ramps up, then holds
+    let (resistor, current_source) = {
+        let world = planner.mut_world();
+        let resistor = resistor::create(world);
+        let current_source =
+            waveform::create_current_source(world,
+                                            Waveform::PiecewiseLinear(vec![(0.0, 0.0),
+                                                                           (T / 2.0, I)]));
+
+        let mut rs = world.write::<resistor::Resistor>().pass();
+        let r = rs.get_mut(resistor).unwrap();
+        r.set_resistance(R);
+
+        (resistor, current_source)
+    };
+
+    // Assign node IDs
+    {
+        let world = planner.mut_world();
+        let mut nodes = world.write::<Nodes>().pass();
+
+        match nodes.get_mut(current_source).unwrap() {
+            &mut Nodes(ref mut current_source_nodes) => {
+                current_source_nodes[0].index = 0;
+                current_source_nodes[1].index = 1;
+            }
+        }
+        match nodes.get_mut(resistor).unwrap() {
+            &mut Nodes(ref mut resistor_nodes) => {
+                resistor_nodes[0].index = 0;
+                resistor_nodes[1].index = 1;
+            }
+        }
+    }
+
+    run_loop_iteration_for_delta(&mut planner, T / SIM_TIME_PER_SEC);
+
+    // Assert the current source has reached its final value
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(resistor).unwrap() {
+        &Nodes(ref resistor_nodes) => {
+            assert_eq!(resistor_nodes[0].voltage, 0f64);
+            assert_approx_eq!(resistor_nodes[1].voltage, I * R);
+        }
+    }
+}
+
+#[test]
+fn half_wave_rectifier() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::resistor;
+    use elements::diode;
+    use elements::waveform;
+    use elements::waveform::Waveform;
+
+    // one cycle takes four time constants
+    const FREQUENCY: f64 = 1.0 / (4.0 * T);
+
+    // Set up world
+    let mut planner = create_planner();
+
+    // Create a sine source, feeding a resistor through a diode
+    let (resistor, diode, voltage_source) = {
+        let world = planner.mut_world();
+        let resistor = resistor::create(world);
+        let diode = diode::create(world);
+        let voltage_source = waveform::create_voltage_source(world,
+                                                             Waveform::Sine {
+                                                                 amplitude: V,
+                                                                 frequency: FREQUENCY,
+                                                                 phase: 0.0,
+                                                                 offset: 0.0,
+                                                             });
+
+        let mut rs = world.write::<resistor::Resistor>().pass();
+        let r = rs.get_mut(resistor).unwrap();
+        r.set_resistance(R);
+
+        (resistor, diode, voltage_source)
+    };
+
+    // Assign node IDs
+    {
+        let world = planner.mut_world();
+        let mut nodes = world.write::<Nodes>().pass();
+
+        match nodes.get_mut(voltage_source).unwrap() {
+            &mut Nodes(ref mut voltage_source_nodes) => {
+                voltage_source_nodes[0].index = 0;
+                voltage_source_nodes[1].index = 1;
+            }
+        }
+        match nodes.get_mut(diode).unwrap() {
+            &mut Nodes(ref mut diode_nodes) => {
+                diode_nodes[0].index = 1;
+                diode_nodes[1].index = 2;
+            }
+        }
+        match nodes.get_mut(resistor).unwrap() {
+            &mut Nodes(ref mut resistor_nodes) => {
+                resistor_nodes[0].index = 2;
+                resistor_nodes[1].index = 0;
+            }
+        }
+    }
+
+    // Positive peak: the diode conducts, dropping roughly 0.6-0.8V
+    run_loop_iteration_for_delta(&mut planner, T / SIM_TIME_PER_SEC);
+    {
+        let world = planner.mut_world();
+        let nodes = world.read::<Nodes>().pass();
+        match nodes.get(resistor).unwrap() {
+            &Nodes(ref resistor_nodes) => {
+                let v = resistor_nodes[0].voltage;
+                assert!(v > V - 0.8 && v < V - 0.6);
+            }
+        }
+    }
+
+    // Negative peak: the diode blocks
+    run_loop_iteration_for_delta(&mut planner, 2.0 * T / SIM_TIME_PER_SEC);
+    {
+        let world = planner.mut_world();
+        let nodes = world.read::<Nodes>().pass();
+        match nodes.get(resistor).unwrap() {
+            &Nodes(ref resistor_nodes) => {
+                assert_approx_eq!(resistor_nodes[0].voltage, 0.0, 1e-6);
+            }
+        }
+        match nodes.get(voltage_source).unwrap() {
+            &Nodes(ref voltage_source_nodes) => {
+                assert_approx_eq!(voltage_source_nodes[1].voltage, -V, V * ACCEPTABLE_DIFF);
+            }
+        }
+    }
+}
+
+// Create an RC circuit, returning the voltage source and capacitor.
+fn create_resistor_capacitor(planner: &mut specs::Planner<Delta>)
+                             -> (specs::Entity, specs::Entity) {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::resistor;
+    use elements::voltage_source;
+
+    let world = planner.mut_world();
+    let resistor = resistor::create(world);
+    let capacitor = capacitor::create(world);
+    let voltage_source = voltage_source::create(world);
+
+    world.write::<resistor::Resistor>().pass().get_mut(resistor).unwrap().set_resistance(R);
+    world.write::<capacitor::Capacitor>().pass().get_mut(capacitor).unwrap().capacitance = C;
+    world.write::<voltage_source::VoltageSource>()
+        .pass()
+        .get_mut(voltage_source)
+        .unwrap()
+        .voltage = V;
+
+    let mut nodes = world.write::<Nodes>().pass();
+    for &(entity, n0, n1) in &[(voltage_source, 0, 1), (resistor, 1, 2), (capacitor, 2, 0)] {
+        match nodes.get_mut(entity).unwrap() {
+            &mut Nodes(ref mut ns) => {
+                ns[0].index = n0;
+                ns[1].index = n1;
+            }
+        }
+    }
+
+    (voltage_source, capacitor)
+}
+
+fn capacitor_voltage(planner: &mut specs::Planner<Delta>, capacitor: specs::Entity) -> f64 {
+    use specs::Gate;
+    use elements::Nodes;
+
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(capacitor).unwrap() {
+        &Nodes(ref ns) => ns[0].voltage - ns[1].voltage,
+    }
+}
+
+#[test]
+fn configured_sim_time_per_sec_and_timestep() {
+    let v_c = V * (1.0 - (-T / TIME_CONSTANT).exp());
+
+    // simulate in real time, with a finer timestep
+    let system = solve::System::new().with_sim_time_per_sec(1.0).with_timestep(1e-6);
+    let mut planner = create_planner_with(system);
+    let (_, capacitor) = create_resistor_capacitor(&mut planner);
+
+    run_loop_iteration_for_delta(&mut planner, T);
+
+    assert_approx_eq!(capacitor_voltage(&mut planner, capacitor), v_c, v_c * ACCEPTABLE_DIFF);
+}
+
+#[test]
+fn invalid_sim_time_per_sec_and_timestep_are_ignored() {
+    let v_c = V * (1.0 - (-T / TIME_CONSTANT).exp());
+
+    let system = solve::System::new().with_sim_time_per_sec(-1.0).with_timestep(0.0);
+    let mut planner = create_planner_with(system);
+    let (_, capacitor) = create_resistor_capacitor(&mut planner);
+    {
+        let mut control = planner.mut_world().write_resource_now::<solve::Control>();
+        control.sim_time_per_sec = Some(std::f64::INFINITY);
+        control.timestep = Some(std::f64::NAN);
+    }
+
+    run_loop_iteration_for_delta(&mut planner, T / SIM_TIME_PER_SEC);
+
+    assert_approx_eq!(capacitor_voltage(&mut planner, capacitor), v_c, v_c * ACCEPTABLE_DIFF);
+}
+
+#[test]
+fn paused() {
+    let mut planner = create_planner();
+    let (_, capacitor) = create_resistor_capacitor(&mut planner);
+    planner.mut_world().write_resource_now::<solve::Control>().pause();
+
+    run_loop_iteration_for_delta(&mut planner, T / SIM_TIME_PER_SEC);
+
+    assert_eq!(capacitor_voltage(&mut planner, capacitor), 0.0);
+}
+
+#[test]
+fn run_steps_while_paused() {
+    let v_c = V * (1.0 - (-T / TIME_CONSTANT).exp());
+
+    let mut planner = create_planner();
+    let (_, capacitor) = create_resistor_capacitor(&mut planner);
+    {
+        let mut control = planner.mut_world().write_resource_now::<solve::Control>();
+        control.pause();
+        control.timestep = Some(T / 100.0);
+        control.run_steps(99);
+        control.step();
+    }
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    assert_eq!(planner.mut_world().read_resource_now::<solve::Control>().pending_steps(),
+               0);
+    assert_approx_eq!(capacitor_voltage(&mut planner, capacitor), v_c, v_c * ACCEPTABLE_DIFF);
+}
+
+#[test]
+fn adaptive_timestep() {
+    use solver::adaptive;
+
+    let v_c = V * (1.0 - (-T / TIME_CONSTANT).exp());
+
+    let system = solve::System::new()
+        .with_sim_time_per_sec(1.0)
+        .with_adaptive_timestep(adaptive::Settings::default());
+    let mut planner = create_planner_with(system);
+    let (_, capacitor) = create_resistor_capacitor(&mut planner);
+
+    run_loop_iteration_for_delta(&mut planner, T);
+
+    assert_approx_eq!(capacitor_voltage(&mut planner, capacitor), v_c, v_c * ACCEPTABLE_DIFF);
+}
+
+#[test]
+fn adaptive_timestep_pulse() {
+    use specs::Gate;
+    use elements::waveform::Waveform;
+    use solver::adaptive;
+
+    let v_c = V * (1.0 - (-T / TIME_CONSTANT).exp());
+
+    let system = solve::System::new()
+        .with_sim_time_per_sec(1.0)
+        .with_adaptive_timestep(adaptive::Settings::default());
+    let mut planner = create_planner_with(system);
+    let (voltage_source, capacitor) = create_resistor_capacitor(&mut planner);
+
+    // switch the source on sharply after a while, then charge for one time
+    // constant
+    {
+        let world = planner.mut_world();
+        world.write::<Waveform>().pass().insert(voltage_source,
+                                                Waveform::Pulse {
+                                                    initial: 0.0,
+                                                    pulsed: V,
+                                                    delay: T,
+                                                    rise: T / 1000.0,
+                                                    fall: T / 1000.0,
+                                                    width: 10.0 * T,
+                                                    period: 0.0,
+                                                });
+    }
+
+    run_loop_iteration_for_delta(&mut planner, T);
+    assert_eq!(capacitor_voltage(&mut planner, capacitor), 0.0);
+
+    run_loop_iteration_for_delta(&mut planner, T);
+    assert_approx_eq!(capacitor_voltage(&mut planner, capacitor), v_c, v_c * ACCEPTABLE_DIFF);
+}
+
+#[test]
+fn adaptive_timestep_lands_on_close_breakpoints() {
+    use specs::Gate;
+    use elements::Nodes;
+    use elements::waveform::Waveform;
+    use solver::adaptive;
+    use solver::recorder::Capacity;
+    use solver::recorder::Probe;
+    use solver::recorder::Recorder;
+
+    // the pulse rises in much less than the minimum step
+    let settings = adaptive::Settings { min_step: T / 100.0, ..adaptive::Settings::default() };
+    let system = solve::System::new()
+        .with_sim_time_per_sec(1.0)
+        .with_adaptive_timestep(settings);
+    let mut planner = create_planner_with(system);
+    let (voltage_source, capacitor) = create_resistor_capacitor(&mut planner);
+    let capacitor_node = match planner.mut_world().read::<Nodes>().pass().get(capacitor).unwrap() {
+        &Nodes(ref ns) => ns[0].index,
+    };
+    {
+        let world = planner.mut_world();
+        world.write::<Waveform>().pass().insert(voltage_source,
+                                                Waveform::Pulse {
+                                                    initial: 0.0,
+                                                    pulsed: V,
+                                                    delay: T,
+                                                    rise: T / 1000.0,
+                                                    fall: T / 1000.0,
+                                                    width: 10.0 * T,
+                                                    period: 0.0,
+                                                });
+        *world.write_resource_now::<Recorder>() =
+            Recorder::new(vec![Probe::Voltage(capacitor_node)], Capacity::Unbounded);
+    }
+
+    run_loop_iteration_for_delta(&mut planner, 2.0 * T);
+
+    let recorder = planner.mut_world().read_resource_now::<Recorder>();
+    let times: Vec<f64> = recorder.samples().iter().map(|sample| sample.time).collect();
+    assert!(times.contains(&T));
+    assert!(times.contains(&(T + T / 1000.0)));
+}
+
+#[test]
+fn recorder() {
+    use specs::Gate;
+    use elements::Nodes;
+    use solver::recorder::Capacity;
+    use solver::recorder::Probe;
+    use solver::recorder::Recorder;
+
+    let mut planner = create_planner();
+    let (voltage_source, capacitor) = create_resistor_capacitor(&mut planner);
+    let capacitor_node = match planner.mut_world().read::<Nodes>().pass().get(capacitor).unwrap() {
+        &Nodes(ref ns) => ns[0].index,
+    };
+    {
+        let world = planner.mut_world();
+        *world.write_resource_now::<Recorder>() =
+            Recorder::new(vec![Probe::Voltage(capacitor_node),
+                               Probe::Current(voltage_source),
+                               Probe::Current(capacitor)],
+                          Capacity::Unbounded);
+        let mut control = world.write_resource_now::<solve::Control>();
+        control.pause();
+        control.timestep = Some(T / 100.0);
+        control.run_steps(100);
+    }
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    let recorder = planner.mut_world().read_resource_now::<Recorder>();
+    let samples = recorder.samples();
+    assert_eq!(samples.len(), 100);
+    assert_approx_eq!(samples[0].time, T / 100.0);
+    assert_approx_eq!(samples[99].time, T);
+
+    // every internal step is kept, not just the last one
+    let v_c = V * (1.0 - (-T / TIME_CONSTANT).exp());
+    assert_approx_eq!(samples[99].values[0], v_c, v_c * ACCEPTABLE_DIFF);
+    assert!(samples[49].values[0] < samples[99].values[0]);
+
+    // the same current flows through the source and capacitor
+    assert_approx_eq!(samples[99].values[1], samples[99].values[2]);
+}
+
+#[test]
+fn without_optional_resources() {
+    let mut planner = create_planner_without_resources(solve::System::default());
+    let (_, capacitor) = create_resistor_capacitor(&mut planner);
+
+    run_loop_iteration_for_delta(&mut planner, 1.0);
+
+    assert!(capacitor_voltage(&mut planner, capacitor) > 0.0);
+}
+
+#[test]
+fn non_positive_timestep_is_ignored() {
+    let mut planner = create_planner();
+    create_resistor_capacitor(&mut planner);
+    planner.mut_world().write_resource_now::<solve::Control>().timestep = Some(0.0);
+
+    // one real second, at the System's default timestep
+    run_loop_iteration_for_delta(&mut planner, 1.0);
+
+    let status = planner.mut_world().read_resource_now::<solve::Status>();
+    let steps = SIM_TIME_PER_SEC / solve::SIM_TIMESTEP;
+    assert!((status.steps as f64 - steps).abs() <= 1.0);
+}
+
+#[test]
+fn status() {
+    let mut planner = create_planner();
+    create_resistor_capacitor(&mut planner);
+    {
+        let mut control = planner.mut_world().write_resource_now::<solve::Control>();
+        control.pause();
+        control.run_steps(10);
+    }
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    let status = planner.mut_world().read_resource_now::<solve::Status>();
+    assert_eq!(status.steps, 10);
+    assert!(!status.stale);
+    assert!(status.last_error.is_none());
+}
+
+#[test]
+fn status_voltage_source_loop() {
+    use specs::Gate;
+    use elements::Nodes;
+    use elements::voltage_source;
+    use solver::equation;
+
+    let mut planner = create_planner();
+    let (v1, v2) = {
+        let world = planner.mut_world();
+        let v1 = voltage_source::create(world);
+        let v2 = voltage_source::create(world);
+        for &entity in &[v1, v2] {
+            match world.write::<Nodes>().pass().get_mut(entity).unwrap() {
+                &mut Nodes(ref mut ns) => {
+                    ns[0].index = 0;
+                    ns[1].index = 1;
+                }
+            }
+        }
+        let mut control = world.write_resource_now::<solve::Control>();
+        control.pause();
+        control.step();
+        (v1, v2)
+    };
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    let status = planner.mut_world().read_resource_now::<solve::Status>();
+    assert_eq!(status.steps, 0);
+    assert!(status.stale);
+    assert_approx_eq!(status.failed_at.unwrap(), solve::SIM_TIMESTEP);
+    match status.last_error {
+        Some(equation::Error::VoltageSourceLoop { ref entities }) => {
+            assert_eq!(*entities, vec![v1, v2])
+        }
+        ref error => panic!("Unexpected error: {:?}", error),
+    }
+}
+
+#[test]
+fn status_controlled_voltage_source_loop() {
+    use specs::Gate;
+    use elements::Nodes;
+    use elements::voltage_source;
+    use elements::vcvs;
+    use solver::equation;
+
+    let mut planner = create_planner();
+    let (source, controlled) = {
+        let world = planner.mut_world();
+        let source = voltage_source::create(world);
+        let controlled = vcvs::create(world);
+        for &entity in &[source, controlled] {
+            match world.write::<Nodes>().pass().get_mut(entity).unwrap() {
+                &mut Nodes(ref mut ns) => {
+                    for (i, node) in ns.iter_mut().enumerate() {
+                        node.index = i % 2;
+                    }
+                }
+            }
+        }
+        let mut control = world.write_resource_now::<solve::Control>();
+        control.pause();
+        control.step();
+        (source, controlled)
+    };
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    let status = planner.mut_world().read_resource_now::<solve::Status>();
+    assert!(status.stale);
+    match status.last_error {
+        Some(equation::Error::VoltageSourceLoop { ref entities }) => {
+            assert_eq!(*entities, vec![source, controlled])
+        }
+        ref error => panic!("Unexpected error: {:?}", error),
+    }
+}
+
+#[test]
+fn bjt_junction_capacitances() {
+    use specs::Gate;
+    use elements::bjt;
+
+    // reverse biasing both junctions
+    charge_through_capacitances(|world| {
+        let transistor = bjt::create(world, bjt::Polarity::Npn);
+        {
+            let mut bjts = world.write::<bjt::Bjt>().pass();
+            let bjt = bjts.get_mut(transistor).unwrap();
+            bjt.base_emitter_capacitance.capacitance = 0.6 * C;
+            bjt.base_collector_capacitance.capacitance = 0.4 * C;
+        }
+        (transistor, vec![0, 2, 0])
+    });
+}
+
+#[test]
+fn mosfet_gate_capacitances() {
+    use specs::Gate;
+    use elements::mosfet;
+
+    // keeping the channel cut off
+    charge_through_capacitances(|world| {
+        let transistor = mosfet::create(world, mosfet::Polarity::Nmos);
+        {
+            let mut mosfets = world.write::<mosfet::Mosfet>().pass();
+            let mosfet = mosfets.get_mut(transistor).unwrap();
+            mosfet.threshold_voltage = 1.0;
+            mosfet.gate_source_capacitance.capacitance = 0.6 * C;
+            mosfet.gate_drain_capacitance.capacitance = 0.4 * C;
+        }
+        (transistor, vec![0, 2, 0, 0])
+    });
+}
+
+// -V through R into a transistor's base or gate, which charges through its
+// capacitances like an RC circuit. `create` adds the transistor with 0.6C
+// to its emitter or source and 0.4C to its collector or drain, returning
+// its node indexes: the base or gate on node 2, everything else grounded.
+//
+// The capacitances have no branch, so mustn't disturb the voltage source's.
+fn charge_through_capacitances<F>(create: F)
+    where F: FnOnce(&mut specs::World) -> (specs::Entity, Vec<usize>)
+{
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::CalculatedCurrent;
+    use elements::DerivedCurrent;
+    use elements::resistor;
+    use elements::voltage_source;
+
+    let mut planner = create_planner();
+    let (voltage_source, resistor, transistor) = {
+        let world = planner.mut_world();
+        let voltage_source = voltage_source::create(world);
+        let resistor = resistor::create(world);
+        let (transistor, transistor_indexes) = create(world);
+
+        world.write::<voltage_source::VoltageSource>()
+            .pass()
+            .get_mut(voltage_source)
+            .unwrap()
+            .voltage = -V;
+        world.write::<resistor::Resistor>().pass().get_mut(resistor).unwrap().set_resistance(R);
+
+        let mut nodes = world.write::<Nodes>().pass();
+        for &(entity, ref indexes) in &[(voltage_source, vec![0, 1]),
+                                        (resistor, vec![1, 2]),
+                                        (transistor, transistor_indexes)] {
+            match nodes.get_mut(entity).unwrap() {
+                &mut Nodes(ref mut ns) => {
+                    for (node, &index) in ns.iter_mut().zip(indexes) {
+                        node.index = index;
+                    }
+                }
+            }
+        }
+
+        (voltage_source, resistor, transistor)
+    };
+    {
+        let mut control = planner.mut_world().write_resource_now::<solve::Control>();
+        control.pause();
+        control.timestep = Some(T / 100.0);
+        control.run_steps(100);
+    }
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    let v = -V * (1.0 - (-T / TIME_CONSTANT).exp());
+    let i_r = (-V / R) * (-T / TIME_CONSTANT).exp();
+
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(resistor).unwrap() {
+        &Nodes(ref ns) => assert_approx_eq!(ns[1].voltage, v, V * ACCEPTABLE_DIFF),
+    }
+
+    let calc_currents = world.read::<CalculatedCurrent>().pass();
+    let source_current = calc_currents.get(voltage_source).unwrap().0;
+    assert_approx_eq!(source_current, i_r, (i_r * ACCEPTABLE_DIFF).abs());
+
+    // the collector or drain capacitance's share of the current comes out of
+    // the collector or drain
+    let derived_currents = world.read::<DerivedCurrent>().pass();
+    let current = derived_currents.get(transistor).unwrap().0;
+    assert_approx_eq!(current, -0.4 * i_r, (0.4 * i_r * ACCEPTABLE_DIFF).abs());
+}
+
+#[test]
+fn toggle_switch_while_running() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::DerivedCurrent;
+    use elements::resistor;
+    use elements::voltage_source;
+    use elements::switch;
+
+    // V, through a switch, into R
+    let mut planner = create_planner();
+    let (resistor, switch) = {
+        let world = planner.mut_world();
+        let voltage_source = voltage_source::create(world);
+        let switch = switch::create(world, switch::Kind::Spst);
+        let resistor = resistor::create(world);
+
+        world.write::<voltage_source::VoltageSource>()
+            .pass()
+            .get_mut(voltage_source)
+            .unwrap()
+            .voltage = V;
+        world.write::<resistor::Resistor>().pass().get_mut(resistor).unwrap().set_resistance(R);
+
+        let mut nodes = world.write::<Nodes>().pass();
+        for &(entity, indexes) in &[(voltage_source, [0, 1]),
+                                    (switch, [1, 2]),
+                                    (resistor, [2, 0])] {
+            match nodes.get_mut(entity).unwrap() {
+                &mut Nodes(ref mut ns) => {
+                    for (node, &index) in ns.iter_mut().zip(&indexes) {
+                        node.index = index;
+                    }
+                }
+            }
+        }
+
+        (resistor, switch)
+    };
+    let step = |planner: &mut specs::Planner<Delta>| {
+        planner.mut_world().write_resource_now::<solve::Control>().step();
+        planner.dispatch(0.0);
+        planner.wait();
+    };
+    let resistor_voltage = |planner: &mut specs::Planner<Delta>| {
+        match planner.mut_world().read::<Nodes>().pass().get(resistor).unwrap() {
+            &Nodes(ref ns) => ns[0].voltage,
+        }
+    };
+
+    planner.mut_world().write_resource_now::<solve::Control>().pause();
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+    step(&mut planner);
+    assert_approx_eq!(resistor_voltage(&mut planner), 0.0, 1e-6);
+
+    // closed, without rebuilding the static equation
+    planner.mut_world().write::<switch::Switch>().pass().get_mut(switch).unwrap().toggle();
+    step(&mut planner);
+    let on_resistance = switch::DEFAULT_ON_RESISTANCE;
+    assert_approx_eq!(resistor_voltage(&mut planner), V * R / (R + on_resistance));
+    let current = planner.mut_world().read::<DerivedCurrent>().pass().get(switch).unwrap().0;
+    assert_approx_eq!(current, V / (R + on_resistance));
+
+    // and open again
+    planner.mut_world().write::<switch::Switch>().pass().get_mut(switch).unwrap().toggle();
+    step(&mut planner);
+    assert_approx_eq!(resistor_voltage(&mut planner), 0.0, 1e-6);
+}
+
+#[test]
+fn opamp_integrator() {
+    use specs::Gate;
+    use elements::Nodes;
+    use elements::CalculatedCurrent;
+
+    let mut planner = create_planner();
+
+    // V through R into the inverting input (node 2), with C from there to
+    // the output (node 3)
+    let (voltage_source, capacitor) = create_resistor_capacitor(&mut planner);
+    {
+        let world = planner.mut_world();
+        match world.write::<Nodes>().pass().get_mut(capacitor).unwrap() {
+            &mut Nodes(ref mut ns) => ns[1].index = 3,
+        }
+    }
+    let opamp = opamp::create(planner.mut_world());
+    {
+        let world = planner.mut_world();
+        world.write::<opamp::OpAmp>().pass().get_mut(opamp).unwrap().model = opamp::Model::Ideal;
+        match world.write::<Nodes>().pass().get_mut(opamp).unwrap() {
+            &mut Nodes(ref mut ns) => {
+                ns[0].index = 0;
+                ns[1].index = 2;
+                ns[2].index = 3;
+            }
+        }
+    }
+    {
+        let mut control = planner.mut_world().write_resource_now::<solve::Control>();
+        control.pause();
+        control.timestep = Some(T / 100.0);
+        control.run_steps(100);
+    }
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    // the output ramps down at V/RC, and supplies all of the input current
+    assert_approx_eq!(capacitor_voltage(&mut planner, capacitor), V, V * ACCEPTABLE_DIFF);
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(opamp).unwrap() {
+        &Nodes(ref ns) => {
+            assert_approx_eq!(ns[1].voltage, 0.0);
+            assert_approx_eq!(ns[2].voltage, -V, V * ACCEPTABLE_DIFF);
+        }
+    }
+    let calc_currents = world.read::<CalculatedCurrent>().pass();
+    assert_approx_eq!(calc_currents.get(opamp).unwrap().0,
+                      -calc_currents.get(voltage_source).unwrap().0);
+}
+
+#[test]
+fn opamp_follower_bandwidth() {
+    use specs::Gate;
+    use elements::Nodes;
+    use elements::voltage_source;
+
+    // a 1V step into a follower, for one closed-loop time constant
+    let model = opamp::MacroModel::default();
+    let time_constant = 1.0 / (2.0 * PI * model.gain_bandwidth);
+
+    let mut planner = create_planner();
+    {
+        let world = planner.mut_world();
+        let voltage_source = voltage_source::create(world);
+        world.write::<voltage_source::VoltageSource>()
+            .pass()
+            .get_mut(voltage_source)
+            .unwrap()
+            .voltage = 1.0;
+        match world.write::<Nodes>().pass().get_mut(voltage_source).unwrap() {
+            &mut Nodes(ref mut ns) => ns[1].index = 1,
+        }
+    }
+    let opamp = opamp::create(planner.mut_world());
+    {
+        let world = planner.mut_world();
+        world.write::<opamp::OpAmp>().pass().get_mut(opamp).unwrap().model =
+            opamp::Model::Macro(model);
+        match world.write::<Nodes>().pass().get_mut(opamp).unwrap() {
+            &mut Nodes(ref mut ns) => {
+                ns[0].index = 1;
+                ns[1].index = 2;
+                ns[2].index = 2;
+            }
+        }
+    }
+    {
+        let mut control = planner.mut_world().write_resource_now::<solve::Control>();
+        control.pause();
+        control.timestep = Some(time_constant / 1000.0);
+        control.run_steps(1000);
+    }
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(opamp).unwrap() {
+        &Nodes(ref ns) => {
+            let expected = 1.0 - (-1f64).exp();
+            assert_approx_eq!(ns[2].voltage, expected, expected * ACCEPTABLE_DIFF);
+        }
+    }
+}
+
+#[test]
+fn opamp_saturates_at_rail() {
+    use specs::Gate;
+    use elements::Nodes;
+
+    let mut planner = create_planner();
+
+    // an integrator, as above, run for long enough to hit the negative rail
+    let (_, capacitor) = create_resistor_capacitor(&mut planner);
+    {
+        let world = planner.mut_world();
+        match world.write::<Nodes>().pass().get_mut(capacitor).unwrap() {
+            &mut Nodes(ref mut ns) => ns[1].index = 3,
+        }
+    }
+    let model = opamp::MacroModel { output_resistance: 0.0, ..opamp::MacroModel::default() };
+    let opamp = opamp::create(planner.mut_world());
+    {
+        let world = planner.mut_world();
+        world.write::<opamp::OpAmp>().pass().get_mut(opamp).unwrap().model =
+            opamp::Model::Macro(model);
+        match world.write::<Nodes>().pass().get_mut(opamp).unwrap() {
+            &mut Nodes(ref mut ns) => {
+                ns[0].index = 0;
+                ns[1].index = 2;
+                ns[2].index = 3;
+            }
+        }
+    }
+    {
+        let mut control = planner.mut_world().write_resource_now::<solve::Control>();
+        control.pause();
+        control.timestep = Some(T / 100.0);
+        control.run_steps(500);
+    }
+
+    run_loop_iteration_for_delta(&mut planner, 0.0);
+
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(opamp).unwrap() {
+        &Nodes(ref ns) => assert_approx_eq!(ns[2].voltage, model.negative_rail),
+    }
+    let opamps = world.read::<opamp::OpAmp>().pass();
+    assert_approx_eq!(opamps.get(opamp).unwrap().internal_voltage, model.negative_rail);
+}
@@ -319,6 +319,271 @@ fn resistor_voltagesource_ground() {
     }
 }
 
+#[test]
+fn resistor_diode_voltagesource() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::CalculatedCurrent;
+    use elements::DerivedCurrent;
+    use elements::resistor;
+    use elements::diode;
+    use elements::voltage_source;
+
+    // Set up world
+    let mut planner = create_planner();
+
+    // Create circuit elements
+    let (resistor, diode, voltage_source) = {
+        let world = planner.mut_world();
+        let resistor = resistor::create(world);
+        let diode = diode::create(world);
+        let voltage_source = voltage_source::create(world);
+        (resistor, diode, voltage_source)
+    };
+
+    // Assign node IDs
+    {
+        let world = planner.mut_world();
+        let mut nodes = world.write::<Nodes>().pass();
+
+        match nodes.get_mut(voltage_source) {
+            Some(&mut Nodes(ref mut voltage_source_nodes)) => {
+                voltage_source_nodes[0].index = 0;
+                voltage_source_nodes[1].index = 1;
+            }
+            None => panic!("oh no"),
+        }
+        match nodes.get_mut(resistor) {
+            Some(&mut Nodes(ref mut resistor_nodes)) => {
+                resistor_nodes[0].index = 1;
+                resistor_nodes[1].index = 2;
+            }
+            None => panic!("oh no"),
+        }
+        match nodes.get_mut(diode) {
+            Some(&mut Nodes(ref mut diode_nodes)) => {
+                diode_nodes[0].index = 2;
+                diode_nodes[1].index = 0;
+            }
+            None => panic!("oh no"),
+        }
+    }
+
+    run_loop_iteration(&mut planner);
+
+    // Assert the diode is forward biased, and the currents agree
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    let diode_voltage = match nodes.get(diode) {
+        Some(&Nodes(ref diode_nodes)) => diode_nodes[0].voltage - diode_nodes[1].voltage,
+        None => panic!("oh no"),
+    };
+    assert!(diode_voltage > 0.6 && diode_voltage < 0.7);
+
+    let expected_current = (voltage_source::DEFAULT_VOLTAGE - diode_voltage) /
+                           resistor::DEFAULT_RESISTANCE;
+    let currents = world.read::<CalculatedCurrent>().pass();
+    match currents.get(voltage_source) {
+        Some(&CalculatedCurrent(current)) => {
+            assert_approx_eq!(current, expected_current);
+        }
+        None => panic!("oh no"),
+    }
+    let currents = world.read::<DerivedCurrent>().pass();
+    match currents.get(diode) {
+        Some(&DerivedCurrent(current)) => {
+            assert_approx_eq!(current, expected_current, expected_current * 1e-3);
+        }
+        None => panic!("oh no"),
+    }
+}
+
+#[test]
+fn resistor_voltagesource_wire_ground_by_position() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::Position;
+    use elements::Terminals;
+    use elements::CalculatedCurrent;
+    use elements::resistor;
+    use elements::wire;
+    use elements::ground;
+    use elements::voltage_source;
+
+    // Set up world
+    let mut planner = create_planner();
+
+    // Lay out a circuit:
+    //
+    //  (0,0)--wire--(2,0)
+    //    |            |
+    //    V            R
+    //    |            |
+    //  (0,2)--wire--(2,2)--ground
+    let (resistor, voltage_source, wire, ground) = {
+        let world = planner.mut_world();
+        let resistor = resistor::create(world);
+        let voltage_source = voltage_source::create(world);
+        let wire = wire::create(world);
+        let return_wire = wire::create(world);
+        let ground = ground::create(world);
+
+        let mut terminals = world.write::<Terminals>().pass();
+        terminals.insert(voltage_source,
+                         Terminals(vec![Position::new(0, 2), Position::new(0, 0)]));
+        terminals.insert(wire, Terminals(vec![Position::new(0, 0), Position::new(2, 0)]));
+        terminals.insert(return_wire,
+                         Terminals(vec![Position::new(0, 2), Position::new(2, 2)]));
+        terminals.insert(resistor,
+                         Terminals(vec![Position::new(2, 2), Position::new(2, 0)]));
+        terminals.insert(ground, Terminals(vec![Position::new(2, 2)]));
+
+        (resistor, voltage_source, wire, ground)
+    };
+
+    run_loop_iteration(&mut planner);
+
+    // Assert the circuit elements have the correct state
+    let expected_voltage = voltage_source::DEFAULT_VOLTAGE;
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    match nodes.get(resistor) {
+        Some(&Nodes(ref resistor_nodes)) => {
+            assert_eq!(resistor_nodes[0].voltage, 0f64);
+            assert_eq!(resistor_nodes[1].voltage, expected_voltage);
+        }
+        None => panic!("oh no"),
+    }
+    match nodes.get(ground) {
+        Some(&Nodes(ref ground_nodes)) => {
+            assert_eq!(ground_nodes[1].index, 0);
+        }
+        None => panic!("oh no"),
+    }
+
+    let expected_current = voltage_source::DEFAULT_VOLTAGE / resistor::DEFAULT_RESISTANCE;
+    let currents = world.read::<CalculatedCurrent>().pass();
+    match currents.get(voltage_source) {
+        Some(&CalculatedCurrent(current)) => {
+            assert_eq!(current, expected_current);
+        }
+        None => panic!("oh no"),
+    }
+    match currents.get(wire) {
+        Some(&CalculatedCurrent(current)) => {
+            assert_eq!(current, expected_current);
+        }
+        None => panic!("oh no"),
+    }
+}
+
+#[test]
+fn controlled_sources() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::CalculatedCurrent;
+    use elements::resistor;
+    use elements::voltage_source;
+    use elements::vcvs;
+    use elements::cccs;
+
+    let mut planner = create_planner();
+
+    // 5V across a resistor, amplified by a VCVS into node 2 and mirrored by
+    // a CCCS into node 3
+    let (voltage_source, amplifier, amplifier_load, mirror_load) = {
+        let world = planner.mut_world();
+        let voltage_source = voltage_source::create(world);
+        let resistor = resistor::create(world);
+        let amplifier = vcvs::create(world);
+        let amplifier_load = resistor::create(world);
+        let mirror = cccs::create(world);
+        let mirror_load = resistor::create(world);
+
+        world.write::<vcvs::Vcvs>().pass().get_mut(amplifier).unwrap().gain = 2.0;
+        world.write::<cccs::Cccs>().pass().get_mut(mirror).unwrap().control =
+            Some(voltage_source);
+
+        let mut nodes = world.write::<Nodes>().pass();
+        for &(entity, ref indexes) in &[(voltage_source, vec![0, 1]),
+                                        (resistor, vec![1, 0]),
+                                        (amplifier, vec![0, 2, 0, 1]),
+                                        (amplifier_load, vec![2, 0]),
+                                        (mirror, vec![0, 3]),
+                                        (mirror_load, vec![3, 0])] {
+            match nodes.get_mut(entity) {
+                Some(&mut Nodes(ref mut ns)) => {
+                    for (node, &index) in ns.iter_mut().zip(indexes) {
+                        node.index = index;
+                    }
+                }
+                None => panic!("oh no"),
+            }
+        }
+
+        (voltage_source, amplifier, amplifier_load, mirror_load)
+    };
+
+    run_loop_iteration(&mut planner);
+
+    let world = planner.mut_world();
+    let nodes = world.read::<Nodes>().pass();
+    let voltage = |entity| match nodes.get(entity) {
+        Some(&Nodes(ref ns)) => ns[0].voltage,
+        None => panic!("oh no"),
+    };
+    let source_voltage = voltage_source::DEFAULT_VOLTAGE;
+    assert_approx_eq!(voltage(amplifier_load), 2.0 * source_voltage);
+    assert_approx_eq!(voltage(mirror_load), source_voltage);
+
+    let currents = world.read::<CalculatedCurrent>().pass();
+    let source_current = source_voltage / resistor::DEFAULT_RESISTANCE;
+    assert_approx_eq!(currents.get(voltage_source).unwrap().0, source_current);
+    assert_approx_eq!(currents.get(amplifier).unwrap().0, 2.0 * source_current);
+}
+
+#[test]
+fn unresolved_controls() {
+    use specs::Gate;
+
+    use elements::Nodes;
+    use elements::resistor;
+    use elements::voltage_source;
+    use elements::ccvs;
+    use elements::cccs;
+    use solver;
+    use solver::equation;
+
+    let mut planner = create_planner();
+    let world = planner.mut_world();
+
+    // one source with no control, and one controlled by a resistor
+    let voltage_source = voltage_source::create(world);
+    let resistor = resistor::create(world);
+    let uncontrolled = ccvs::create(world);
+    let misconnected = cccs::create(world);
+    world.write::<cccs::Cccs>().pass().get_mut(misconnected).unwrap().control = Some(resistor);
+    {
+        let mut nodes = world.write::<Nodes>().pass();
+        for &entity in &[voltage_source, resistor, uncontrolled, misconnected] {
+            match nodes.get_mut(entity) {
+                Some(&mut Nodes(ref mut ns)) => ns[1].index = 1,
+                None => panic!("oh no"),
+            }
+        }
+    }
+
+    match solver::create_static_equation(world).solve() {
+        Err(equation::Error::UnresolvedControl { ref entities }) => {
+            assert_eq!(*entities, vec![uncontrolled, misconnected]);
+        }
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}
+
 #[bench]
 fn bench(b: &mut Bencher) {
     b.iter(|| resistor_voltagesource_wire());
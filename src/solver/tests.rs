@@ -1,31 +1,33 @@
 use specs;
 use Delta;
+use elements;
 use solver;
 
 const SINGLE_FRAME: f64 = 1.0 / 60.0;
 
 pub fn create_planner() -> specs::Planner<Delta> {
-    use elements::Nodes;
-    use elements::CalculatedCurrent;
-    use elements::DerivedCurrent;
-    use elements::CircuitElement;
-    use elements::resistor::Resistor;
-    use elements::current_source::CurrentSource;
-    use elements::voltage_source::VoltageSource;
-    use elements::capacitor::Capacitor;
+    create_planner_with(solver::solve::System::default())
+}
+
+pub fn create_planner_with(system: solver::solve::System) -> specs::Planner<Delta> {
+    let mut planner = create_planner_without_resources(system);
+    {
+        let world = planner.mut_world();
+        world.add_resource(solver::solve::Control::new());
+        world.add_resource(solver::recorder::Recorder::default());
+        world.add_resource(solver::solve::Status::new());
+    }
+    planner
+}
 
+// Without the optional `Control`, `Recorder` and `Status` resources
+pub fn create_planner_without_resources(system: solver::solve::System)
+                                        -> specs::Planner<Delta> {
     let mut world = specs::World::new();
-    world.register::<CircuitElement>();
-    world.register::<Nodes>();
-    world.register::<CalculatedCurrent>();
-    world.register::<DerivedCurrent>();
-    world.register::<VoltageSource>();
-    world.register::<CurrentSource>();
-    world.register::<Resistor>();
-    world.register::<Capacitor>();
+    elements::register(&mut world);
 
     let mut planner = specs::Planner::with_num_threads(world, 1);
-    planner.add_system(solver::solve::System::default(), "solver", 10);
+    planner.add_system(system, "solver", 10);
 
     planner
 }
@@ -42,6 +44,7 @@ pub fn run_loop_iteration_for_delta(planner: &mut specs::Planner<Delta>, delta:
 
 fn run_pre_loop(planner: &mut specs::Planner<Delta>) {
     let mut world = planner.mut_world();
+    solver::assign_nodes(&mut world);
     let equation = solver::create_static_equation(&mut world);
     world.add_resource(equation);
 }